@@ -2,6 +2,12 @@
 pub mod arg;
 #[path = "common/config.rs"]
 pub mod config;
+#[path = "common/credential.rs"]
+pub mod credential;
+#[path = "common/cron.rs"]
+pub mod cron;
+#[path = "common/env.rs"]
+pub mod env;
 #[path = "common/handle.rs"]
 pub mod handle;
 #[path = "common/task.rs"]
@@ -41,7 +41,7 @@ pub enum Commands {
     /// Reload tasks
     Reload(AddArgs),
     /// Start tasks
-    Start(FlagArgs),
+    Start(StartArgs),
     /// Restart tasks
     Restart(FlagArgs),
     /// Stop tasks
@@ -52,6 +52,8 @@ pub enum Commands {
     Pause(FlagArgs),
     /// Resume interval tasks
     Resume(FlagArgs),
+    /// Change the number of instances of tasks
+    Scale(ScaleArgs),
     /// Get tasks list
     List(ListArgs),
 }
@@ -126,6 +128,30 @@ pub struct FlagArgs {
     pub mat: bool,
 }
 
+#[derive(Args, Debug, PartialEq)]
+pub struct StartArgs {
+    #[command(flatten)]
+    pub flag: FlagArgs,
+
+    /// Wait until tasks are running (ready)
+    #[arg(short = 'w', long)]
+    pub wait: bool,
+
+    /// Seconds to wait with '--wait'
+    #[arg(short = 't', long, default_value = "60")]
+    pub timeout: u64,
+}
+
+#[derive(Args, Debug, PartialEq)]
+pub struct ScaleArgs {
+    #[command(flatten)]
+    pub flag: FlagArgs,
+
+    /// Number of instances
+    #[arg(short = 'N', long)]
+    pub instances: u32,
+}
+
 #[derive(Args, Debug, PartialEq)]
 pub struct AddArgs {
     /// Task config directory
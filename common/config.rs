@@ -1,7 +1,9 @@
-use std::{error::Error, path::PathBuf};
+use std::{collections::HashMap, error::Error, path::PathBuf};
 
 use serde::{Deserialize, Serialize};
 
+use crate::common::task::InheritEnv;
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Config {
     pub watchmen: Watchmen,
@@ -9,6 +11,9 @@ pub struct Config {
     pub socket: Socket,
     pub http: Http,
     pub redis: Redis,
+    /// Webhooks notified of task events
+    #[serde(default)]
+    pub notifier: Vec<Notifier>,
 }
 
 impl Config {
@@ -124,6 +129,10 @@ pub struct Watchmen {
     pub interval: Option<u64>,
     // 定时任务时间间隔，单位秒 default 20s
     pub schedule_interval: Option<u64>,
+    /// Delegated cgroup v2 directory, each task gets a sub cgroup for its resource limits
+    pub cgroup: Option<String>,
+    /// Daemon environment variables inherited by tasks without `inherit_env`, default `all`
+    pub inherit_env: Option<InheritEnv>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -154,3 +163,47 @@ pub struct Redis {
     pub subscribe_channels: Vec<String>,
     pub subscribe_name: String,
 }
+
+/// Task event sent to notifiers
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotifyEvent {
+    /// Async task exited with a non-zero code or by a signal
+    Exited,
+    /// Async task was restarted automatically
+    Restarted,
+    /// Async task crashed `max_restart` times in a row and is not restarted any more
+    GaveUp,
+    /// Run of a scheduled task failed to start or exited with a non-zero code
+    ScheduleFailed,
+}
+
+impl std::fmt::Display for NotifyEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            NotifyEvent::Exited => "exited",
+            NotifyEvent::Restarted => "restarted",
+            NotifyEvent::GaveUp => "gave_up",
+            NotifyEvent::ScheduleFailed => "schedule_failed",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Notifier {
+    /// Url the event is posted to
+    pub url: String,
+    /// Events sent to the url, empty means all events
+    #[serde(default)]
+    pub events: Vec<NotifyEvent>,
+    /// JSON body template, `{{ event }}`, `{{ id }}`, `{{ name }}`, `{{ group }}`, `{{ code }}`,
+    /// `{{ message }}` and `{{ timestamp }}` are replaced. Default is the event as a JSON object
+    pub body: Option<String>,
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    /// Retries after a failed request, default 3
+    pub retries: Option<u32>,
+    /// Milliseconds before the first retry, doubled for each retry, default 1000
+    pub backoff_ms: Option<u64>,
+}
@@ -0,0 +1,406 @@
+use std::{error::Error, ffi::CString};
+
+use crate::common::task::Task;
+
+/// Linux capability names, index is the capability number
+static CAPABILITIES: [&str; 41] = [
+    "CHOWN",
+    "DAC_OVERRIDE",
+    "DAC_READ_SEARCH",
+    "FOWNER",
+    "FSETID",
+    "KILL",
+    "SETGID",
+    "SETUID",
+    "SETPCAP",
+    "LINUX_IMMUTABLE",
+    "NET_BIND_SERVICE",
+    "NET_BROADCAST",
+    "NET_ADMIN",
+    "NET_RAW",
+    "IPC_LOCK",
+    "IPC_OWNER",
+    "SYS_MODULE",
+    "SYS_RAWIO",
+    "SYS_CHROOT",
+    "SYS_PTRACE",
+    "SYS_PACCT",
+    "SYS_ADMIN",
+    "SYS_BOOT",
+    "SYS_NICE",
+    "SYS_RESOURCE",
+    "SYS_TIME",
+    "SYS_TTY_CONFIG",
+    "MKNOD",
+    "LEASE",
+    "AUDIT_WRITE",
+    "AUDIT_CONTROL",
+    "SETFCAP",
+    "MAC_OVERRIDE",
+    "MAC_ADMIN",
+    "SYSLOG",
+    "WAKE_ALARM",
+    "BLOCK_SUSPEND",
+    "AUDIT_READ",
+    "PERFMON",
+    "BPF",
+    "CHECKPOINT_RESTORE",
+];
+
+const CAP_SETGID: u32 = 6;
+const CAP_SETUID: u32 = 7;
+const CAP_SETPCAP: u32 = 8;
+
+/// Parse capability name like `CAP_NET_BIND_SERVICE` or `net_bind_service`
+pub fn parse_capability(input: &str) -> Result<u32, String> {
+    let name = input.trim().to_uppercase();
+    let name = name.strip_prefix("CAP_").unwrap_or(&name);
+    CAPABILITIES
+        .iter()
+        .position(|c| *c == name)
+        .map(|i| i as u32)
+        .ok_or_else(|| format!("Invalid capability: {}", input))
+}
+
+/// Identity of the task process, resolved in the daemon before fork and applied
+/// in the child before exec
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Credential {
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+    pub groups: Option<Vec<u32>>,
+    pub umask: Option<u32>,
+    /// Bit set of capabilities kept, `None` keeps capabilities unchanged
+    pub capabilities: Option<u64>,
+    last_cap: u32,
+}
+
+struct Passwd {
+    name: String,
+    uid: u32,
+    gid: u32,
+}
+
+fn getpwnam(name: &str) -> Result<Option<Passwd>, Box<dyn Error>> {
+    let cname = CString::new(name)?;
+    let mut pwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut buf = vec![0 as libc::c_char; 16384];
+    let mut res: *mut libc::passwd = std::ptr::null_mut();
+    let code = unsafe {
+        libc::getpwnam_r(cname.as_ptr(), &mut pwd, buf.as_mut_ptr(), buf.len(), &mut res)
+    };
+    if code != 0 {
+        return Err(Box::new(std::io::Error::from_raw_os_error(code)));
+    }
+    if res.is_null() {
+        return Ok(None);
+    }
+    Ok(Some(Passwd {
+        name: name.to_string(),
+        uid: pwd.pw_uid,
+        gid: pwd.pw_gid,
+    }))
+}
+
+fn getpwuid(uid: u32) -> Result<Option<Passwd>, Box<dyn Error>> {
+    let mut pwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut buf = vec![0 as libc::c_char; 16384];
+    let mut res: *mut libc::passwd = std::ptr::null_mut();
+    let code = unsafe { libc::getpwuid_r(uid, &mut pwd, buf.as_mut_ptr(), buf.len(), &mut res) };
+    if code != 0 {
+        return Err(Box::new(std::io::Error::from_raw_os_error(code)));
+    }
+    if res.is_null() {
+        return Ok(None);
+    }
+    let name = unsafe { std::ffi::CStr::from_ptr(pwd.pw_name) }
+        .to_string_lossy()
+        .to_string();
+    Ok(Some(Passwd {
+        name,
+        uid: pwd.pw_uid,
+        gid: pwd.pw_gid,
+    }))
+}
+
+fn resolve_user(user: &str) -> Result<Passwd, Box<dyn Error>> {
+    let passwd = match user.parse::<u32>() {
+        Ok(uid) => getpwuid(uid)?.or(Some(Passwd {
+            name: String::new(),
+            uid,
+            gid: uid,
+        })),
+        Err(_) => getpwnam(user)?,
+    };
+    passwd.ok_or_else(|| format!("User {} not exists", user).into())
+}
+
+fn resolve_group(group: &str) -> Result<u32, Box<dyn Error>> {
+    if let Ok(gid) = group.parse::<u32>() {
+        return Ok(gid);
+    }
+    let cname = CString::new(group)?;
+    let mut grp: libc::group = unsafe { std::mem::zeroed() };
+    let mut buf = vec![0 as libc::c_char; 65536];
+    let mut res: *mut libc::group = std::ptr::null_mut();
+    let code = unsafe {
+        libc::getgrnam_r(cname.as_ptr(), &mut grp, buf.as_mut_ptr(), buf.len(), &mut res)
+    };
+    if code != 0 {
+        return Err(Box::new(std::io::Error::from_raw_os_error(code)));
+    }
+    if res.is_null() {
+        return Err(format!("Group {} not exists", group).into());
+    }
+    Ok(grp.gr_gid)
+}
+
+/// Groups of the user from the group database, like `initgroups`
+fn grouplist(name: &str, gid: u32) -> Result<Vec<u32>, Box<dyn Error>> {
+    if name.is_empty() {
+        return Ok(vec![gid]);
+    }
+    let cname = CString::new(name)?;
+    let mut count: libc::c_int = 64;
+    loop {
+        let mut groups = vec![0 as libc::gid_t; count as usize];
+        let res =
+            unsafe { libc::getgrouplist(cname.as_ptr(), gid, groups.as_mut_ptr(), &mut count) };
+        if res >= 0 {
+            groups.truncate(count as usize);
+            return Ok(groups);
+        }
+        count *= 2;
+    }
+}
+
+fn cap_last_cap() -> u32 {
+    std::fs::read_to_string("/proc/sys/kernel/cap_last_cap")
+        .ok()
+        .and_then(|s| s.trim().parse().ok())
+        .unwrap_or(CAPABILITIES.len() as u32 - 1)
+}
+
+/// Effective capabilities of the daemon
+fn effective_capabilities() -> u64 {
+    std::fs::read_to_string("/proc/self/status")
+        .ok()
+        .and_then(|s| {
+            s.lines()
+                .find_map(|l| l.strip_prefix("CapEff:"))
+                .and_then(|v| u64::from_str_radix(v.trim(), 16).ok())
+        })
+        .unwrap_or(0)
+}
+
+/// Supplementary groups of the daemon
+fn current_groups() -> Vec<u32> {
+    let count = unsafe { libc::getgroups(0, std::ptr::null_mut()) };
+    if count <= 0 {
+        return Vec::new();
+    }
+    let mut groups = vec![0 as libc::gid_t; count as usize];
+    let count = unsafe { libc::getgroups(count, groups.as_mut_ptr()) };
+    groups.truncate(count.max(0) as usize);
+    groups
+}
+
+impl Credential {
+    /// Resolve user, group and capabilities of the task, `None` when nothing is configured
+    pub fn from_task(task: &Task) -> Result<Option<Self>, Box<dyn Error>> {
+        if task.user.is_none()
+            && task.user_group.is_none()
+            && task.supplementary_groups.is_empty()
+            && task.umask.is_none()
+            && task.capabilities.is_none()
+        {
+            return Ok(None);
+        }
+        let mut credential = Credential {
+            last_cap: cap_last_cap(),
+            ..Default::default()
+        };
+        let passwd = match &task.user {
+            Some(user) => Some(resolve_user(user)?),
+            None => None,
+        };
+        if let Some(passwd) = &passwd {
+            credential.uid = Some(passwd.uid);
+            credential.gid = Some(passwd.gid);
+        }
+        if let Some(group) = &task.user_group {
+            credential.gid = Some(resolve_group(group)?);
+        }
+        if !task.supplementary_groups.is_empty() {
+            let mut groups = Vec::new();
+            for group in &task.supplementary_groups {
+                groups.push(resolve_group(group)?);
+            }
+            credential.groups = Some(groups);
+        } else if let Some(passwd) = &passwd {
+            // 切换用户时使用目标用户的附加组，不继承守护进程的附加组
+            credential.groups = Some(grouplist(&passwd.name, credential.gid.unwrap_or(passwd.gid))?);
+        }
+        if let Some(umask) = &task.umask {
+            credential.umask = Some(
+                u32::from_str_radix(umask.trim(), 8)
+                    .ok()
+                    .filter(|m| *m <= 0o777)
+                    .ok_or_else(|| format!("Invalid umask: {}", umask))?,
+            );
+        }
+        if let Some(caps) = &task.capabilities {
+            let mut set = 0u64;
+            for cap in caps {
+                set |= 1 << parse_capability(cap)?;
+            }
+            credential.capabilities = Some(set);
+        }
+        Ok(Some(credential))
+    }
+
+    /// Drop the uid, gid and groups the daemon already runs with, so running a task
+    /// as the user of the daemon needs no privilege
+    pub fn without_current(mut self) -> Self {
+        let (uid, gid) = unsafe { (libc::geteuid(), libc::getegid()) };
+        if self.uid == Some(uid) {
+            self.uid = None;
+        }
+        if self.gid == Some(gid) {
+            self.gid = None;
+        }
+        if let Some(groups) = &self.groups {
+            let normalize = |groups: &[u32]| {
+                let mut groups = groups.to_vec();
+                groups.push(gid);
+                groups.sort_unstable();
+                groups.dedup();
+                groups
+            };
+            if normalize(groups) == normalize(&current_groups()) {
+                self.groups = None;
+            }
+        }
+        self
+    }
+
+    /// Check the daemon is permitted to switch to the identity
+    pub fn check(&self) -> Result<(), Box<dyn Error>> {
+        let caps = effective_capabilities();
+        let has = |cap: u32| caps & (1 << cap) != 0;
+        let (uid, gid) = unsafe { (libc::geteuid(), libc::getegid()) };
+        if let Some(target) = self.uid {
+            if target != uid && !has(CAP_SETUID) {
+                return Err(format!(
+                    "Permission denied to run as uid {}, daemon runs as uid {}",
+                    target, uid
+                )
+                .into());
+            }
+        }
+        let change_group = self.gid.map(|g| g != gid).unwrap_or(false) || self.groups.is_some();
+        if change_group && !has(CAP_SETGID) {
+            return Err(format!(
+                "Permission denied to change group, daemon runs as gid {}",
+                gid
+            )
+            .into());
+        }
+        if self.capabilities.is_some() && !has(CAP_SETPCAP) {
+            return Err("Permission denied to drop capabilities, CAP_SETPCAP is required".into());
+        }
+        Ok(())
+    }
+
+    /// Apply the identity to the current process, only async-signal-safe calls are
+    /// allowed because it runs between fork and exec
+    pub fn apply(&self) -> std::io::Result<()> {
+        fn check(res: libc::c_int) -> std::io::Result<()> {
+            if res < 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        }
+        unsafe {
+            if let Some(caps) = self.capabilities {
+                // 从能力边界集中移除不在白名单中的能力，exec 后无法再获得
+                for cap in 0..=self.last_cap {
+                    if caps & (1 << cap) == 0 {
+                        check(libc::prctl(libc::PR_CAPBSET_DROP, cap as libc::c_ulong, 0, 0, 0))?;
+                    }
+                }
+                if self.uid.is_some() {
+                    // 切换用户后保留 permitted 能力，随后设置为白名单
+                    check(libc::prctl(libc::PR_SET_KEEPCAPS, 1, 0, 0, 0))?;
+                }
+            }
+            if let Some(groups) = &self.groups {
+                check(libc::setgroups(groups.len(), groups.as_ptr()))?;
+            }
+            if let Some(gid) = self.gid {
+                check(libc::setgid(gid))?;
+            }
+            if let Some(uid) = self.uid {
+                check(libc::setuid(uid))?;
+            }
+            if let Some(umask) = self.umask {
+                libc::umask(umask as libc::mode_t);
+            }
+            if let Some(caps) = self.capabilities {
+                capset(caps)?;
+                // 非 root 用户 exec 后只保留 ambient 能力
+                for cap in 0..=self.last_cap {
+                    if caps & (1 << cap) != 0 {
+                        check(libc::prctl(
+                            libc::PR_CAP_AMBIENT,
+                            libc::PR_CAP_AMBIENT_RAISE as libc::c_ulong,
+                            cap as libc::c_ulong,
+                            0,
+                            0,
+                        ))?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[repr(C)]
+struct CapHeader {
+    version: u32,
+    pid: libc::c_int,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct CapData {
+    effective: u32,
+    permitted: u32,
+    inheritable: u32,
+}
+
+/// Set effective, permitted and inheritable capabilities of the current process
+fn capset(caps: u64) -> std::io::Result<()> {
+    // _LINUX_CAPABILITY_VERSION_3
+    let header = CapHeader {
+        version: 0x20080522,
+        pid: 0,
+    };
+    let mut data = [CapData {
+        effective: 0,
+        permitted: 0,
+        inheritable: 0,
+    }; 2];
+    for (i, d) in data.iter_mut().enumerate() {
+        let bits = (caps >> (32 * i)) as u32;
+        d.effective = bits;
+        d.permitted = bits;
+        d.inheritable = bits;
+    }
+    let res = unsafe { libc::syscall(libc::SYS_capset, &header, data.as_ptr()) };
+    if res < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
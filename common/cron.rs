@@ -0,0 +1,213 @@
+use std::{error::Error, str::FromStr};
+
+use chrono::{
+    DateTime, Datelike, Duration, LocalResult, NaiveDate, NaiveDateTime, NaiveTime, TimeZone,
+    Timelike,
+};
+
+static MONTHS: [&str; 12] = [
+    "JAN", "FEB", "MAR", "APR", "MAY", "JUN", "JUL", "AUG", "SEP", "OCT", "NOV", "DEC",
+];
+
+static WEEKDAYS: [&str; 7] = ["SUN", "MON", "TUE", "WED", "THU", "FRI", "SAT"];
+
+/// Cron expression
+///
+/// Accepts the standard 5 fields `minute hour day month weekday`, 6 fields with a
+/// leading `second`, and the macros `@yearly`, `@annually`, `@monthly`, `@weekly`,
+/// `@daily`, `@midnight` and `@hourly`. Fields support `*`, `?`, lists (`1,15`),
+/// ranges (`1-5`), steps (`*/10`, `8-18/2`, `5/15`) and month/weekday names.
+///
+/// Like vixie cron, when both day and weekday are restricted the expression matches
+/// days matching either of them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cron {
+    seconds: u64,
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    day_any: bool,
+    weekday_any: bool,
+}
+
+impl FromStr for Cron {
+    type Err = Box<dyn Error>;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let expr = match input.trim().to_lowercase().as_str() {
+            "@yearly" | "@annually" => "0 0 0 1 1 *".to_string(),
+            "@monthly" => "0 0 0 1 * *".to_string(),
+            "@weekly" => "0 0 0 * * 0".to_string(),
+            "@daily" | "@midnight" => "0 0 0 * * *".to_string(),
+            "@hourly" => "0 0 * * * *".to_string(),
+            s if s.starts_with('@') => return Err(format!("Invalid cron macro: {}", input).into()),
+            _ => input.trim().to_string(),
+        };
+        let mut fields: Vec<&str> = expr.split_whitespace().collect();
+        match fields.len() {
+            5 => fields.insert(0, "0"),
+            6 => {}
+            _ => {
+                return Err(format!(
+                    "Invalid cron expression: {}, expected 5 or 6 fields",
+                    input
+                )
+                .into())
+            }
+        }
+        let weekdays = parse_field(fields[5], 0, 7, &WEEKDAYS)?;
+        Ok(Cron {
+            seconds: parse_field(fields[0], 0, 59, &[])?,
+            minutes: parse_field(fields[1], 0, 59, &[])?,
+            hours: parse_field(fields[2], 0, 23, &[])?,
+            days: parse_field(fields[3], 1, 31, &[])?,
+            months: parse_field(fields[4], 1, 12, &MONTHS)?,
+            // 7 和 0 都表示周日
+            weekdays: (weekdays | (weekdays >> 7)) & 0x7f,
+            day_any: is_any(fields[3]),
+            weekday_any: is_any(fields[5]),
+        })
+    }
+}
+
+fn is_any(field: &str) -> bool {
+    field == "*" || field == "?"
+}
+
+/// Parse a cron field into a bit set of the allowed values
+///
+/// `names` are the names of the values starting from `min`
+fn parse_field(field: &str, min: u32, max: u32, names: &[&str]) -> Result<u64, Box<dyn Error>> {
+    let value = |s: &str| -> Result<u32, Box<dyn Error>> {
+        let upper = s.to_uppercase();
+        if let Some(i) = names.iter().position(|n| *n == upper) {
+            return Ok(min + i as u32);
+        }
+        match s.parse::<u32>() {
+            Ok(v) if v >= min && v <= max => Ok(v),
+            _ => Err(format!("Invalid cron value: {}, expected {}-{}", s, min, max).into()),
+        }
+    };
+    let mut bits = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => match step.parse::<u32>() {
+                Ok(step) if step > 0 => (range, step),
+                _ => return Err(format!("Invalid cron step: {}", part).into()),
+            },
+            None => (part, 1),
+        };
+        let (start, end) = match range {
+            "*" | "?" => (min, max),
+            _ => match range.split_once('-') {
+                Some((start, end)) => (value(start)?, value(end)?),
+                // `5/15` 表示从 5 开始到最大值
+                None if part.contains('/') => (value(range)?, max),
+                None => {
+                    let v = value(range)?;
+                    (v, v)
+                }
+            },
+        };
+        if start > end {
+            return Err(format!("Invalid cron range: {}", part).into());
+        }
+        for v in (start..=end).step_by(step as usize) {
+            bits |= 1 << v;
+        }
+    }
+    Ok(bits)
+}
+
+fn has(bits: u64, v: u32) -> bool {
+    bits & (1 << v) != 0
+}
+
+impl Cron {
+    fn match_day(&self, date: NaiveDate) -> bool {
+        let day = has(self.days, date.day());
+        let weekday = has(self.weekdays, date.weekday().num_days_from_sunday());
+        match (self.day_any, self.weekday_any) {
+            (true, true) => true,
+            (true, false) => weekday,
+            (false, true) => day,
+            (false, false) => day || weekday,
+        }
+    }
+
+    /// First time matching the expression strictly after `after`
+    ///
+    /// `None` when there is no such time within the next years, e.g. `0 0 30 2 *`.
+    pub fn next_after(&self, after: NaiveDateTime) -> Option<NaiveDateTime> {
+        let mut t = after.with_nanosecond(0)? + Duration::seconds(1);
+        let limit = t + Duration::days(366 * 5);
+        while t < limit {
+            if !has(self.months, t.month()) {
+                // 跳到下个月的第一天
+                let (y, m) = if t.month() == 12 {
+                    (t.year() + 1, 1)
+                } else {
+                    (t.year(), t.month() + 1)
+                };
+                t = NaiveDate::from_ymd_opt(y, m, 1)?.and_time(NaiveTime::MIN);
+                continue;
+            }
+            if !self.match_day(t.date()) {
+                t = t.date().succ_opt()?.and_time(NaiveTime::MIN);
+                continue;
+            }
+            if !has(self.hours, t.hour()) {
+                t = t.with_minute(0)?.with_second(0)? + Duration::hours(1);
+                continue;
+            }
+            if !has(self.minutes, t.minute()) {
+                t = t.with_second(0)? + Duration::minutes(1);
+                continue;
+            }
+            if !has(self.seconds, t.second()) {
+                t += Duration::seconds(1);
+                continue;
+            }
+            return Some(t);
+        }
+        None
+    }
+
+    /// First time matching the expression in the time zone of `after`, strictly after it
+    ///
+    /// Wall-clock times skipped by a DST change fire once when the gap ends, and
+    /// repeated wall-clock times fire only on their first occurrence, see [`resolve_local`].
+    pub fn next_after_in<Tz: TimeZone>(&self, after: &DateTime<Tz>) -> Option<DateTime<Tz>> {
+        let mut naive = after.naive_local();
+        loop {
+            naive = self.next_after(naive)?;
+            let t = resolve_local(&after.timezone(), naive)?;
+            // 重复时段的第二次经过时，第一次出现的时刻已经过去
+            if t > *after {
+                return Some(t);
+            }
+        }
+    }
+}
+
+/// Instant of the wall-clock time `naive` in time zone `tz`
+///
+/// - a time repeated when clocks go back (e.g. 01:30 twice) resolves to its first occurrence
+/// - a time skipped when clocks go forward (e.g. 02:30 in a 02:00 -> 03:00 gap) resolves to
+///   the end of the gap (03:00)
+pub fn resolve_local<Tz: TimeZone>(tz: &Tz, naive: NaiveDateTime) -> Option<DateTime<Tz>> {
+    match tz.from_local_datetime(&naive) {
+        LocalResult::Single(t) => Some(t),
+        LocalResult::Ambiguous(first, _) => Some(first),
+        LocalResult::None => {
+            // 跳过的时段不超过一天，逐分钟向后查找第一个存在的时间
+            let start = naive.with_second(0)?;
+            (1..=24 * 60).find_map(|m| {
+                tz.from_local_datetime(&(start + Duration::minutes(m)))
+                    .earliest()
+            })
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use std::{collections::HashMap, error::Error, path::Path};
+
+/// Replace `${VAR}` and `${VAR:-default}` in `input` with variables in `vars`
+///
+/// An unset variable is replaced with an empty string, `default` is used when the
+/// variable is unset or empty. Text that is not a valid reference is kept as is,
+/// e.g. `${1}` or an unclosed `${`.
+pub fn interpolate(input: &str, vars: &HashMap<String, String>) -> String {
+    let mut output = String::new();
+    let mut rest = input;
+    while let Some(start) = rest.find("${") {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let end = match closing_brace(after) {
+            Some(end) => end,
+            None => {
+                output.push_str(&rest[start..]);
+                return output;
+            }
+        };
+        let inner = &after[..end];
+        let (name, default) = match inner.find(":-") {
+            Some(i) => (&inner[..i], Some(&inner[i + 2..])),
+            None => (inner, None),
+        };
+        if is_name(name) {
+            match (vars.get(name), default) {
+                (Some(value), Some(_)) if !value.is_empty() => output.push_str(value),
+                (_, Some(default)) => output.push_str(&interpolate(default, vars)),
+                (Some(value), None) => output.push_str(value),
+                (None, None) => {}
+            }
+        } else {
+            output.push_str(&rest[start..start + 2 + end + 1]);
+        }
+        rest = &after[end + 1..];
+    }
+    output.push_str(rest);
+    output
+}
+
+/// Position of the `}` closing a reference, nested references in the default are skipped
+fn closing_brace(s: &str) -> Option<usize> {
+    let mut depth = 0;
+    let bytes = s.as_bytes();
+    for (i, b) in bytes.iter().enumerate() {
+        match b {
+            b'{' if i > 0 && bytes[i - 1] == b'$' => depth += 1,
+            b'}' if depth == 0 => return Some(i),
+            b'}' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Parse dotenv file contents
+///
+/// Lines are `KEY=VALUE`, optionally prefixed with `export`. Blank lines and lines
+/// starting with `#` are skipped. Single quoted values are literal, double quoted
+/// values support `\n`, `\t`, `\"` and `\\`. Unquoted and double quoted values are
+/// interpolated with `vars` and variables defined earlier in the file.
+pub fn parse_dotenv(
+    contents: &str,
+    vars: &HashMap<String, String>,
+) -> Result<Vec<(String, String)>, String> {
+    let mut vars = vars.clone();
+    let mut pairs = Vec::new();
+    for (n, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (key, value) = match line.split_once('=') {
+            Some((key, value)) => (key.trim(), value.trim()),
+            None => return Err(format!("line {}: expected KEY=VALUE", n + 1)),
+        };
+        if !is_name(key) {
+            return Err(format!("line {}: invalid variable name `{}`", n + 1, key));
+        }
+        let value = if let Some(quoted) = value.strip_prefix('\'') {
+            match quoted.find('\'') {
+                Some(end) => quoted[..end].to_string(),
+                None => return Err(format!("line {}: unclosed quote", n + 1)),
+            }
+        } else if let Some(quoted) = value.strip_prefix('"') {
+            let mut unescaped = String::new();
+            let mut chars = quoted.chars();
+            let mut closed = false;
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' => {
+                        closed = true;
+                        break;
+                    }
+                    '\\' => match chars.next() {
+                        Some('n') => unescaped.push('\n'),
+                        Some('t') => unescaped.push('\t'),
+                        Some(c) => unescaped.push(c),
+                        None => {}
+                    },
+                    c => unescaped.push(c),
+                }
+            }
+            if !closed {
+                return Err(format!("line {}: unclosed quote", n + 1));
+            }
+            interpolate(&unescaped, &vars)
+        } else {
+            // 未加引号的值中 ` #` 之后为注释
+            let value = match value.find(" #") {
+                Some(i) => value[..i].trim_end(),
+                None => value,
+            };
+            interpolate(value, &vars)
+        };
+        vars.insert(key.to_string(), value.clone());
+        pairs.push((key.to_string(), value));
+    }
+    Ok(pairs)
+}
+
+/// Read the dotenv file at `path`, see [`parse_dotenv`]
+pub fn read_dotenv(
+    path: &Path,
+    vars: &HashMap<String, String>,
+) -> Result<Vec<(String, String)>, Box<dyn Error>> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| format!("Read env file {} failed: {}", path.display(), e))?;
+    let pairs = parse_dotenv(&contents, vars)
+        .map_err(|e| format!("Invalid env file {}, {}", path.display(), e))?;
+    Ok(pairs)
+}
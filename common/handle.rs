@@ -21,6 +21,7 @@ pub enum Command {
     Write(TaskFlag, String),
     Pause(TaskFlag),
     Resume(TaskFlag),
+    Scale(TaskFlag, u32),
     List(Option<TaskFlag>),
 }
 
@@ -107,6 +108,7 @@ pub struct Status {
     pub args: Vec<String>,
     pub dir: Option<String>,
     pub group: Option<String>,
+    /// Environment variables, secret values are redacted
     pub env: HashMap<String, String>,
     pub stdin: Option<bool>,
     pub stdout: Option<String>,
@@ -116,6 +118,16 @@ pub struct Status {
     pub pid: Option<u32>,
     pub status: Option<String>,
     pub code: Option<i32>,
+    #[serde(default)]
+    pub health: Option<String>,
+    #[serde(default)]
+    pub restart_reason: Option<String>,
+    /// Instance number of an instance row
+    #[serde(default)]
+    pub instance: Option<u32>,
+    /// Instances of a task with `instances`
+    #[serde(default)]
+    pub instances: Vec<Status>,
 }
 
 impl From<crate::common::task::Task> for Status {
@@ -127,7 +139,11 @@ impl From<crate::common::task::Task> for Status {
             args: task.args,
             dir: task.dir,
             group: task.group,
-            env: task.env,
+            env: task
+                .env
+                .iter()
+                .map(|(key, value)| (key.clone(), value.redacted()))
+                .collect(),
             stdin: task.stdin,
             stdout: task.stdout,
             stderr: task.stderr,
@@ -136,6 +152,10 @@ impl From<crate::common::task::Task> for Status {
             pid: task.pid,
             status: task.status,
             code: task.code,
+            health: task.health,
+            restart_reason: task.restart_reason,
+            instance: task.instance,
+            instances: Vec::new(),
         }
     }
 }
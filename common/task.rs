@@ -1,12 +1,19 @@
 use serde::{Deserialize, Serialize};
 use std::{
     collections::HashMap,
+    path::{Path, PathBuf},
     time::{SystemTime, UNIX_EPOCH},
 };
 use sysinfo::{
     Disks, Pid, ProcessRefreshKind, ProcessesToUpdate, System as sys, System,
 };
 
+use chrono_tz::Tz;
+
+use crate::common::config::get_with_home_path;
+use crate::common::cron::Cron;
+use crate::common::env;
+
 fn default_i64_0() -> i64 {
     0
 }
@@ -23,6 +30,10 @@ fn default_none_string() -> Option<String> {
     None
 }
 
+fn default_f64_2() -> f64 {
+    2.0
+}
+
 fn default_false() -> bool {
     false
 }
@@ -31,10 +42,46 @@ fn default_vec_string() -> Vec<String> {
     Vec::new()
 }
 
-fn default_map_string_string() -> HashMap<String, String> {
+fn default_map_string_env() -> HashMap<String, EnvValue> {
     HashMap::new()
 }
 
+fn default_success_exit_codes() -> Vec<i32> {
+    vec![0]
+}
+
+fn default_vec_i32() -> Vec<i32> {
+    Vec::new()
+}
+
+fn default_vec_i64() -> Vec<i64> {
+    Vec::new()
+}
+
+fn default_u64_10() -> u64 {
+    10
+}
+
+fn default_u64_3() -> u64 {
+    3
+}
+
+fn default_u64_30() -> u64 {
+    30
+}
+
+fn default_u32_5() -> u32 {
+    5
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_concurrency_policy() -> ConcurrencyPolicy {
+    ConcurrencyPolicy::Forbid
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ScheduledTask {
     pub year: Option<i32>,
@@ -43,6 +90,73 @@ pub struct ScheduledTask {
     pub hour: Option<u32>,
     pub minute: Option<u32>,
     pub second: Option<u32>,
+    /// Cron expression, e.g. `0 2 * * *`, used instead of the fields above
+    #[serde(default = "default_none_string")]
+    pub cron: Option<String>,
+    /// Next fire time of the cron expression, unix timestamp
+    #[serde(default)]
+    pub next_run: Option<i64>,
+    /// IANA time zone the schedule is evaluated in, e.g. `Asia/Shanghai`, default
+    /// the local time zone of the daemon
+    ///
+    /// Wall-clock times skipped by a DST change run once when the gap ends, repeated
+    /// wall-clock times run only on their first occurrence.
+    #[serde(default = "default_none_string")]
+    pub timezone: Option<String>,
+    /// Time of the last run as unix timestamp, runs missed before it are already handled
+    #[serde(default)]
+    pub last_run: Option<i64>,
+}
+
+impl ScheduledTask {
+    /// Time zone of the schedule, `None` for the local time zone
+    pub fn tz(&self) -> Result<Option<Tz>, String> {
+        match &self.timezone {
+            Some(tz) => tz
+                .parse::<Tz>()
+                .map(Some)
+                .map_err(|_| format!("Invalid timezone: {}", tz)),
+            None => Ok(None),
+        }
+    }
+
+    /// Schedule as cron expression, fields not set match any value
+    ///
+    /// `year` has no cron field and must be checked by the caller.
+    pub fn to_cron(&self) -> Result<Cron, String> {
+        let expr = match &self.cron {
+            Some(cron) => cron.clone(),
+            None => {
+                let field = |v: Option<u32>| v.map(|v| v.to_string()).unwrap_or("*".to_string());
+                format!(
+                    "{} {} {} {} {} *",
+                    field(self.second),
+                    field(self.minute),
+                    field(self.hour),
+                    field(self.day),
+                    field(self.month)
+                )
+            }
+        };
+        expr.parse::<Cron>().map_err(|e| e.to_string())
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        self.tz()?;
+        if let Some(cron) = &self.cron {
+            cron.parse::<Cron>().map_err(|e| e.to_string())?;
+            if self.year.is_some()
+                || self.month.is_some()
+                || self.day.is_some()
+                || self.hour.is_some()
+                || self.minute.is_some()
+                || self.second.is_some()
+            {
+                return Err("cron can not be used with year, month, day, hour, minute or second".to_string());
+            }
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -55,6 +169,68 @@ pub struct AsyncTask {
     pub started_at: u64,
     #[serde(default = "default_u64_0")]
     pub stopped_at: u64,
+    /// Stopped by `watchmen stop`, must not be recovered by monitor
+    #[serde(default = "default_false")]
+    pub stopped_by_user: bool,
+    /// Seconds to wait before the first automatic restart
+    #[serde(default = "default_u64_0")]
+    pub restart_delay: u64,
+    /// Restart delay grows by this factor after each consecutive restart
+    #[serde(default = "default_f64_2")]
+    pub backoff_multiplier: f64,
+    /// Upper bound of the restart delay, in seconds
+    #[serde(default = "default_none_u64")]
+    pub max_backoff: Option<u64>,
+    /// A run that lasts at least this many seconds resets `has_restart`
+    #[serde(default = "default_none_u64")]
+    pub min_uptime: Option<u64>,
+    /// Timestamp before which the monitor must not restart the task
+    #[serde(default = "default_u64_0")]
+    pub next_restart_at: u64,
+}
+
+impl AsyncTask {
+    /// Delay before the `n`th consecutive restart, `n` starts from 1
+    pub fn backoff(&self, n: u64) -> u64 {
+        if self.restart_delay == 0 || n == 0 {
+            return 0;
+        }
+        let exp = (n - 1).min(i32::MAX as u64) as i32;
+        let delay = self.restart_delay as f64 * self.backoff_multiplier.max(1.0).powi(exp);
+        let delay = if delay.is_finite() { delay as u64 } else { u64::MAX };
+        match self.max_backoff {
+            Some(max) => delay.min(max),
+            None => delay,
+        }
+    }
+
+    /// Record the process exited at `now`, a run lasting `min_uptime` resets `has_restart`
+    pub fn exited_at(&mut self, now: u64) {
+        self.stopped_at = now;
+        if let Some(min_uptime) = self.min_uptime {
+            if self.started_at > 0 && now.saturating_sub(self.started_at) >= min_uptime {
+                self.has_restart = 0;
+            }
+        }
+    }
+
+    /// `max_restart` consecutive restarts are used up, the task must not be restarted
+    pub fn restarts_exhausted(&self) -> bool {
+        self.max_restart.is_some_and(|max| self.has_restart >= max)
+    }
+
+    /// Count one more consecutive restart and delay it by the backoff
+    ///
+    /// Returns `false` without scheduling when `max_restart` restarts are used up,
+    /// without `max_restart` the task is restarted forever.
+    pub fn schedule_restart(&mut self, now: u64) -> bool {
+        if self.restarts_exhausted() {
+            return false;
+        }
+        self.has_restart += 1;
+        self.next_restart_at = now + self.backoff(self.has_restart);
+        true
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -64,8 +240,596 @@ pub struct PeriodicTask {
     pub interval: u64,
     #[serde(default = "default_u64_0")]
     pub last_run: u64,
-    #[serde(default = "default_false")]
-    pub sync: bool,
+    /// What to do when the previous run is still executing at the next tick
+    #[serde(default = "default_concurrency_policy")]
+    pub concurrency_policy: ConcurrencyPolicy,
+    /// Maximum of runs executing at the same time with `allow`, unlimited when not set
+    #[serde(default = "default_none_u64")]
+    pub max_concurrent: Option<u64>,
+}
+
+/// What a periodic task does when its previous run is still executing
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConcurrencyPolicy {
+    /// Start another run next to the executing ones, at most `max_concurrent` runs
+    Allow,
+    /// Skip the tick until the previous run exits
+    Forbid,
+    /// Stop the executing runs before starting the new one
+    Replace,
+}
+
+impl std::str::FromStr for ConcurrencyPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "allow" => Ok(ConcurrencyPolicy::Allow),
+            "forbid" => Ok(ConcurrencyPolicy::Forbid),
+            "replace" => Ok(ConcurrencyPolicy::Replace),
+            _ => Err(format!("Invalid concurrency_policy: {}", s)),
+        }
+    }
+}
+
+/// When an async task is restarted after its process exited
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RestartPolicy {
+    /// Restart whatever the exit code is, also started again on daemon load even if stopped by user
+    Always,
+    /// Restart when the exit code is not one of `success_exit_codes`
+    OnFailure,
+    /// Never restart
+    Never,
+    /// Like `Always`, but a task stopped by user stays stopped on daemon load
+    UnlessStopped,
+}
+
+impl std::str::FromStr for RestartPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "always" => Ok(RestartPolicy::Always),
+            "on-failure" => Ok(RestartPolicy::OnFailure),
+            "never" => Ok(RestartPolicy::Never),
+            "unless-stopped" => Ok(RestartPolicy::UnlessStopped),
+            _ => Err(format!("Invalid restart_policy: {}", s)),
+        }
+    }
+}
+
+/// What to do with runs of scheduled and periodic tasks missed while the daemon was down
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MisfirePolicy {
+    /// Drop missed runs and wait for the next scheduled time
+    Skip,
+    /// Run once on daemon load however many runs were missed
+    RunOnce,
+    /// Run every missed run one after another, at most `misfire_max` runs
+    RunAll,
+}
+
+impl std::str::FromStr for MisfirePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "skip" => Ok(MisfirePolicy::Skip),
+            "run-once" => Ok(MisfirePolicy::RunOnce),
+            "run-all" => Ok(MisfirePolicy::RunAll),
+            _ => Err(format!("Invalid misfire: {}", s)),
+        }
+    }
+}
+
+/// Daemon environment variables inherited by the task process
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum InheritEnv {
+    /// Inherit all variables
+    #[default]
+    All,
+    /// Inherit nothing, only `env_file` and `env` are set
+    None,
+    /// Inherit listed variables, a name ending with `*` is a prefix, e.g. `LC_*`
+    Allow(Vec<String>),
+}
+
+impl InheritEnv {
+    /// Whether the daemon variable `name` is inherited
+    pub fn allows(&self, name: &str) -> bool {
+        match self {
+            InheritEnv::All => true,
+            InheritEnv::None => false,
+            InheritEnv::Allow(names) => names.iter().any(|n| match n.strip_suffix('*') {
+                Some(prefix) => name.starts_with(prefix),
+                None => name == n,
+            }),
+        }
+    }
+}
+
+impl std::str::FromStr for InheritEnv {
+    type Err = String;
+
+    /// `all`, `none` or names separated by whitespace
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "all" => Ok(InheritEnv::All),
+            "none" => Ok(InheritEnv::None),
+            "" => Err("Invalid inherit_env: empty".to_string()),
+            names => Ok(InheritEnv::Allow(
+                names.split_whitespace().map(|n| n.to_string()).collect(),
+            )),
+        }
+    }
+}
+
+impl Serialize for InheritEnv {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match self {
+            InheritEnv::All => serializer.serialize_str("all"),
+            InheritEnv::None => serializer.serialize_str("none"),
+            InheritEnv::Allow(names) => names.serialize(serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for InheritEnv {
+    /// Deserialize from `all`, `none` or a list of names
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Value {
+            Text(String),
+            Names(Vec<String>),
+        }
+        match Value::deserialize(deserializer)? {
+            Value::Text(s) => s.parse().map_err(serde::de::Error::custom),
+            Value::Names(names) => Ok(InheritEnv::Allow(names)),
+        }
+    }
+}
+
+/// Placeholder shown instead of secret environment values
+pub static REDACTED: &str = "<redacted>";
+
+/// Value of a task environment variable
+///
+/// Secret references are resolved only when the process is spawned, the resolved
+/// value is never stored in the task. They are shown as [`REDACTED`] in status and logs.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum EnvValue {
+    Plain(String),
+    /// Read from the file, e.g. `{ secret_file = "/run/secrets/db" }`, trailing newline removed
+    SecretFile { secret_file: String },
+    /// Copied from the daemon environment variable, e.g. `{ from_env = "DB_PASSWORD" }`
+    FromEnv { from_env: String },
+}
+
+impl EnvValue {
+    pub fn is_secret(&self) -> bool {
+        !matches!(self, EnvValue::Plain(_))
+    }
+
+    /// Value of a plain variable, `None` for a secret reference
+    pub fn plain(&self) -> Option<&str> {
+        match self {
+            EnvValue::Plain(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Value shown in status, secret values are redacted
+    pub fn redacted(&self) -> String {
+        self.plain().unwrap_or(REDACTED).to_string()
+    }
+
+    /// Resolve the value, reading the secret file or the daemon environment variable
+    pub fn resolve(&self) -> Result<String, Box<dyn std::error::Error>> {
+        match self {
+            EnvValue::Plain(value) => Ok(value.clone()),
+            EnvValue::SecretFile { secret_file } => {
+                let value = std::fs::read_to_string(get_with_home_path(secret_file))
+                    .map_err(|e| format!("Read secret file {} failed: {}", secret_file, e))?;
+                Ok(value.trim_end_matches(['\n', '\r']).to_string())
+            }
+            EnvValue::FromEnv { from_env } => std::env::var(from_env)
+                .map_err(|_| format!("Environment variable {} is not set", from_env).into()),
+        }
+    }
+}
+
+impl std::fmt::Debug for EnvValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // 日志中不输出密钥的来源
+        match self {
+            EnvValue::Plain(value) => std::fmt::Debug::fmt(value, f),
+            _ => f.write_str(REDACTED),
+        }
+    }
+}
+
+impl From<&str> for EnvValue {
+    fn from(value: &str) -> Self {
+        EnvValue::Plain(value.to_string())
+    }
+}
+
+impl From<String> for EnvValue {
+    fn from(value: String) -> Self {
+        EnvValue::Plain(value)
+    }
+}
+
+impl PartialEq<&str> for EnvValue {
+    fn eq(&self, other: &&str) -> bool {
+        self.plain() == Some(*other)
+    }
+}
+
+/// Default maximum of missed runs executed by `run-all`
+pub static DEFAULT_MISFIRE_MAX: u64 = 10;
+
+/// How the health of a task is checked
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthCheckType {
+    /// `GET url`, healthy when the response status is 2xx or 3xx
+    Http,
+    /// Healthy when `address` accepts TCP connections
+    Tcp,
+    /// Healthy when `command` exits with code 0
+    Exec,
+}
+
+impl std::str::FromStr for HealthCheckType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "http" => Ok(HealthCheckType::Http),
+            "tcp" => Ok(HealthCheckType::Tcp),
+            "exec" => Ok(HealthCheckType::Exec),
+            _ => Err(format!("Invalid health check type: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthCheck {
+    #[serde(rename = "type")]
+    pub check_type: HealthCheckType,
+    /// Url requested by `http` check
+    pub url: Option<String>,
+    /// `host:port` connected by `tcp` check
+    pub address: Option<String>,
+    /// Command run by `exec` check
+    pub command: Option<String>,
+    #[serde(default = "default_vec_string")]
+    pub args: Vec<String>,
+    /// Seconds between two checks
+    #[serde(default = "default_u64_10")]
+    pub interval: u64,
+    /// Seconds before a check is considered failed
+    #[serde(default = "default_u64_3")]
+    pub timeout: u64,
+    /// Consecutive failures before the task is unhealthy
+    #[serde(default = "default_u64_3")]
+    pub retries: u64,
+    /// Seconds after start during which failures are not counted
+    #[serde(default = "default_u64_0")]
+    pub start_period: u64,
+    /// Restart the task when it becomes unhealthy
+    #[serde(default = "default_true")]
+    pub restart: bool,
+}
+
+impl HealthCheck {
+    pub fn new(check_type: HealthCheckType) -> Self {
+        HealthCheck {
+            check_type,
+            url: None,
+            address: None,
+            command: None,
+            args: Vec::new(),
+            interval: default_u64_10(),
+            timeout: default_u64_3(),
+            retries: default_u64_3(),
+            start_period: 0,
+            restart: true,
+        }
+    }
+
+    /// Check required target of the check type is configured
+    pub fn validate(&self) -> Result<(), String> {
+        let target = match self.check_type {
+            HealthCheckType::Http => &self.url,
+            HealthCheckType::Tcp => &self.address,
+            HealthCheckType::Exec => &self.command,
+        };
+        if target.is_none() {
+            return Err(format!(
+                "Health check {:?} requires {}",
+                self.check_type,
+                match self.check_type {
+                    HealthCheckType::Http => "url",
+                    HealthCheckType::Tcp => "address",
+                    HealthCheckType::Exec => "command",
+                }
+            ));
+        }
+        if self.interval == 0 {
+            return Err("Health check interval must be greater than 0".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Condition a started task must meet before it is running
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReadinessType {
+    /// `address` accepts TCP connections
+    Tcp,
+    /// File `path` exists
+    File,
+    /// A new line of stdout or stderr matches regex `pattern`
+    Log,
+    /// Process is still alive after `timeout`
+    Timeout,
+}
+
+impl std::str::FromStr for ReadinessType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tcp" => Ok(ReadinessType::Tcp),
+            "file" => Ok(ReadinessType::File),
+            "log" => Ok(ReadinessType::Log),
+            "timeout" => Ok(ReadinessType::Timeout),
+            _ => Err(format!("Invalid readiness type: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Readiness {
+    #[serde(rename = "type")]
+    pub check_type: ReadinessType,
+    /// `host:port` connected by `tcp` readiness
+    pub address: Option<String>,
+    /// File waited by `file` readiness, relative to the task directory
+    pub path: Option<String>,
+    /// Regex matched by `log` readiness
+    pub pattern: Option<String>,
+    /// Seconds to wait for the condition, the task is restarted as failed after that.
+    /// For `timeout` readiness the task is ready after it
+    #[serde(default = "default_u64_30")]
+    pub timeout: u64,
+}
+
+impl Readiness {
+    pub fn new(check_type: ReadinessType) -> Self {
+        Readiness {
+            check_type,
+            address: None,
+            path: None,
+            pattern: None,
+            timeout: default_u64_30(),
+        }
+    }
+
+    /// Check required target of the readiness type is configured
+    pub fn validate(&self) -> Result<(), String> {
+        let (target, key) = match self.check_type {
+            ReadinessType::Tcp => (&self.address, "address"),
+            ReadinessType::File => (&self.path, "path"),
+            ReadinessType::Log => (&self.pattern, "pattern"),
+            ReadinessType::Timeout => return Ok(()),
+        };
+        match target {
+            Some(target) => {
+                if self.check_type == ReadinessType::Log {
+                    regex::Regex::new(target).map_err(|e| e.to_string())?;
+                }
+                Ok(())
+            }
+            None => Err(format!("Readiness {:?} requires {}", self.check_type, key)),
+        }
+    }
+}
+
+/// Command run at a point of the task lifecycle, with the dir, env and user of the task
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Hook {
+    pub command: String,
+    #[serde(default = "default_vec_string")]
+    pub args: Vec<String>,
+    /// Seconds before the hook is killed and considered failed
+    #[serde(default = "default_u64_30")]
+    pub timeout: u64,
+}
+
+impl Hook {
+    /// Parse hook from a command line like `python manage.py migrate`
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let mut parts = line.split_whitespace().map(|s| s.to_string());
+        match parts.next() {
+            Some(command) => Ok(Hook {
+                command,
+                args: parts.collect(),
+                timeout: default_u64_30(),
+            }),
+            None => Err("Hook command is empty".to_string()),
+        }
+    }
+}
+
+/// Parse size like `1024`, `512K`, `256M` or `2G` into bytes
+pub fn parse_size(input: &str) -> Result<u64, String> {
+    let input = input.trim();
+    let (num, unit) = match input.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => input.split_at(i),
+        None => (input, ""),
+    };
+    let num: u64 = num
+        .parse()
+        .map_err(|_| format!("Invalid size: {}", input))?;
+    let unit: u64 = match unit.trim().to_uppercase().trim_end_matches('B') {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        "T" => 1 << 40,
+        _ => return Err(format!("Invalid size: {}", input)),
+    };
+    num.checked_mul(unit)
+        .ok_or_else(|| format!("Invalid size: {}", input))
+}
+
+/// Deserialize size from number of bytes or string accepted by `parse_size`
+fn deserialize_size<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Size {
+        Bytes(u64),
+        Text(String),
+    }
+    match Option::<Size>::deserialize(deserializer)? {
+        Some(Size::Bytes(n)) => Ok(Some(n)),
+        Some(Size::Text(s)) => parse_size(&s).map(Some).map_err(serde::de::Error::custom),
+        None => Ok(None),
+    }
+}
+
+/// Deserialize a list of strings from a string or an array of strings
+fn deserialize_one_or_many<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+    match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(s) => Ok(vec![s]),
+        OneOrMany::Many(v) => Ok(v),
+    }
+}
+
+/// Resource limits of the task process
+///
+/// `memory_max`, `cpu_quota`, `cpu_weight` and `pids_max` are applied through the task
+/// cgroup and require `cgroup` in the daemon config, `nofile` and `core` are rlimits.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Limits {
+    /// Memory limit in bytes, e.g. `512M`
+    #[serde(default, deserialize_with = "deserialize_size")]
+    pub memory_max: Option<u64>,
+    /// CPU time in cores, `0.5` means half a core
+    pub cpu_quota: Option<f64>,
+    /// Relative CPU weight, 1 - 10000
+    pub cpu_weight: Option<u64>,
+    /// Max number of processes
+    pub pids_max: Option<u64>,
+    /// Max number of open files (`RLIMIT_NOFILE`)
+    pub nofile: Option<u64>,
+    /// Max size of core dump in bytes (`RLIMIT_CORE`)
+    #[serde(default, deserialize_with = "deserialize_size")]
+    pub core: Option<u64>,
+}
+
+impl Limits {
+    /// Any limit applied through cgroup
+    pub fn has_cgroup(&self) -> bool {
+        self.memory_max.is_some()
+            || self.cpu_quota.is_some()
+            || self.cpu_weight.is_some()
+            || self.pids_max.is_some()
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(quota) = self.cpu_quota {
+            if quota.is_nan() || quota <= 0.0 {
+                return Err(format!("Invalid cpu_quota: {}", quota));
+            }
+        }
+        if let Some(weight) = self.cpu_weight {
+            if !(1..=10000).contains(&weight) {
+                return Err(format!("Invalid cpu_weight: {}", weight));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Rotation of the log files of the task, by size and/or daily
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LogRotate {
+    /// Rotate when the file would grow over this size, e.g. `100M`
+    #[serde(default, deserialize_with = "deserialize_size")]
+    pub max_size: Option<u64>,
+    /// Rotate when the local date changes
+    #[serde(default)]
+    pub daily: bool,
+    /// Rotated files kept as `<file>.1` (newest) to `<file>.<max_files>`, default 5
+    #[serde(default = "default_u32_5")]
+    pub max_files: u32,
+    /// Compress rotated files with gzip as `<file>.<n>.gz`
+    #[serde(default)]
+    pub compress: bool,
+}
+
+impl Default for LogRotate {
+    fn default() -> Self {
+        LogRotate {
+            max_size: None,
+            daily: false,
+            max_files: default_u32_5(),
+            compress: false,
+        }
+    }
+}
+
+impl LogRotate {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_size.is_none() && !self.daily {
+            return Err("Log rotate requires max_size or daily".to_string());
+        }
+        if self.max_size == Some(0) {
+            return Err("Log rotate max_size must be greater than 0".to_string());
+        }
+        if self.max_files == 0 {
+            return Err("Log rotate max_files must be greater than 0".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Options decided by the daemon when spawning the task process
+#[derive(Debug, Clone, Default)]
+pub struct StartOptions {
+    /// Cgroup directory joined by the process before exec
+    pub cgroup: Option<String>,
+    /// Daemon environment variables inherited by tasks without `inherit_env`
+    pub inherit_env: InheritEnv,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -97,20 +861,146 @@ pub struct Task {
     /// Task working directory
     pub dir: Option<String>,
 
-    /// Task environment variables
-    #[serde(default = "default_map_string_string")]
-    pub env: HashMap<String, String>,
+    /// Task environment variables, a value is a string or a secret reference, see [`EnvValue`]
+    #[serde(default = "default_map_string_env")]
+    pub env: HashMap<String, EnvValue>,
+
+    /// Dotenv files relative to the task file, loaded when the task starts, e.g. `[".env"]`
+    #[serde(default, deserialize_with = "deserialize_one_or_many")]
+    pub env_file: Vec<String>,
+
+    /// Daemon environment variables inherited, default is `inherit_env` of the daemon config
+    pub inherit_env: Option<InheritEnv>,
 
     pub stdin: Option<bool>,
     pub stdout: Option<String>,
     pub stderr: Option<String>,
 
+    /// Rotate `stdout` and `stderr` files, the daemon writes them instead of the process
+    pub log_rotate: Option<LogRotate>,
+
+    /// Signal sent to stop the task, default `SIGTERM`
+    pub stop_signal: Option<String>,
+
+    /// Seconds to wait for the task to exit before `SIGKILL`, default 10
+    pub stop_timeout: Option<u64>,
+
+    /// Also kill descendants that escaped the task process group (e.g. `setsid`)
+    #[serde(default = "default_false")]
+    pub kill_descendants: bool,
+
+    /// Restart policy of async task, default `on-failure` when `max_restart` is set, otherwise `never`
+    pub restart_policy: Option<RestartPolicy>,
+
+    /// Misfire policy of scheduled and periodic task, default `skip` for scheduled
+    /// task and `run-once` for periodic task
+    pub misfire: Option<MisfirePolicy>,
+
+    /// Missed runs older than this many seconds are dropped, default no limit
+    pub misfire_grace: Option<u64>,
+
+    /// Maximum missed runs executed by `run-all`, default 10
+    pub misfire_max: Option<u64>,
+
+    /// Exit codes treated as success by `on-failure`
+    #[serde(default = "default_success_exit_codes")]
+    pub success_exit_codes: Vec<i32>,
+
+    /// Only these exit codes trigger restart with `on-failure`, empty means any failure
+    #[serde(default = "default_vec_i32")]
+    pub restart_exit_codes: Vec<i32>,
+
+    /// Ids of tasks that must be started before this task
+    #[serde(default = "default_vec_i64")]
+    pub depends_on: Vec<i64>,
+
+    /// Seconds to wait for each dependency to be running before starting, default is the
+    /// readiness timeout of the dependency, don't wait when neither is set
+    pub depends_wait: Option<u64>,
+
+    /// Health check of async task
+    pub health_check: Option<HealthCheck>,
+
+    /// Result of the last health checks: starting, healthy or unhealthy
+    #[serde(default = "default_none_string")]
+    pub health: Option<String>,
+
+    /// Task stays `starting` after spawned until it is ready
+    pub readiness: Option<Readiness>,
+
+    /// Run before the async task starts, the start is aborted when it fails
+    pub pre_start: Option<Hook>,
+
+    /// Run after the async task process is spawned
+    pub post_start: Option<Hook>,
+
+    /// Run before the async task is stopped
+    pub pre_stop: Option<Hook>,
+
+    /// Run after the async task is stopped
+    pub post_stop: Option<Hook>,
+
+    /// Resource limits
+    pub limits: Option<Limits>,
+
+    /// User to run the task as, name or uid
+    pub user: Option<String>,
+
+    /// Group to run the task as, name or gid (`group` is the task group)
+    pub user_group: Option<String>,
+
+    /// Supplementary groups, default are the groups of `user`
+    #[serde(default = "default_vec_string")]
+    pub supplementary_groups: Vec<String>,
+
+    /// File mode creation mask in octal, e.g. `022`
+    pub umask: Option<String>,
+
+    /// Capabilities kept by the task, all others are dropped, e.g. `["CAP_NET_BIND_SERVICE"]`
+    pub capabilities: Option<Vec<String>>,
+
+    /// Restart async task when memory of its processes exceeds this many bytes, e.g. `512M`
+    #[serde(default, deserialize_with = "deserialize_size")]
+    pub max_memory_restart: Option<u64>,
+
+    /// Restart async task when CPU usage (percent of one core) stays above it for `max_cpu_seconds`
+    pub max_cpu_restart: Option<f32>,
+
+    /// Seconds CPU usage must stay above `max_cpu_restart`, default 60
+    pub max_cpu_seconds: Option<u64>,
+
+    /// Reason of the last automatic restart
+    #[serde(default = "default_none_string")]
+    pub restart_reason: Option<String>,
+
+    /// Number of processes of async task, each started with `WATCHMEN_INSTANCE` and
+    /// `{{ ... }}` in env values and args rendered, e.g. `PORT = "{{ 8000 + instance }}"`
+    pub instances: Option<u32>,
+
+    /// Instance number when the task is one instance of a task with `instances`
+    #[serde(default)]
+    pub instance: Option<u32>,
+
+    /// Files, directories or globs relative to `dir`, async task is restarted when they change
+    pub watch: Option<Vec<String>>,
+
+    /// Globs of changed files not causing a restart, e.g. `["*.pyc", "**/node_modules/**"]`
+    #[serde(default = "default_vec_string")]
+    pub watch_ignore: Vec<String>,
+
+    /// Milliseconds to wait for more changes before the restart, default 500
+    pub watch_debounce_ms: Option<u64>,
+
     #[serde(default = "default_created_at")]
     pub created_at: u64,
     pub task_type: TaskType,
 
     pub pid: Option<u32>,
 
+    /// Start time of process `pid` after system boot in clock ticks, tells a reused pid apart
+    #[serde(default)]
+    pub pid_start_time: Option<u64>,
+
     #[serde(default = "default_status")]
     pub status: Option<String>,
     pub code: Option<i32>,
@@ -129,6 +1019,71 @@ fn default_status() -> Option<String> {
     Some("added".to_owned())
 }
 
+/// Render `{{ expr }}` in `value` for instance `instance`
+///
+/// `expr` is integers and `instance` joined by `+`, `-` and `*`, e.g. `{{ 8000 + instance }}`.
+pub fn render_template(value: &str, instance: u32) -> Result<String, String> {
+    let mut rendered = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find("{{") {
+        let end = match rest[start..].find("}}") {
+            Some(end) => start + end,
+            None => return Err(format!("Unclosed template: {}", value)),
+        };
+        rendered.push_str(&rest[..start]);
+        rendered.push_str(&eval_template(&rest[start + 2..end], instance)?.to_string());
+        rest = &rest[end + 2..];
+    }
+    rendered.push_str(rest);
+    Ok(rendered)
+}
+
+fn eval_template(expr: &str, instance: u32) -> Result<i64, String> {
+    let invalid = || format!("Invalid template: {{{{{}}}}}", expr);
+    let mut chars = expr.chars().peekable();
+    // 先乘后加减：sum 为已完成的项之和，term 为当前项的乘积
+    let (mut sum, mut term, mut sign) = (0i64, 1i64, 1i64);
+    let mut operand = true;
+    while let Some(&c) = chars.peek() {
+        match c {
+            ' ' => {
+                chars.next();
+            }
+            '0'..='9' | 'a'..='z' | '_' if operand => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if !c.is_ascii_alphanumeric() && c != '_' {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                let v = match word.as_str() {
+                    "instance" => instance as i64,
+                    _ => word.parse::<i64>().map_err(|_| invalid())?,
+                };
+                term = term.checked_mul(v).ok_or_else(invalid)?;
+                operand = false;
+            }
+            '*' if !operand => {
+                chars.next();
+                operand = true;
+            }
+            '+' | '-' if !operand => {
+                chars.next();
+                sum = sum.checked_add(sign * term).ok_or_else(invalid)?;
+                (term, sign) = (1, if c == '+' { 1 } else { -1 });
+                operand = true;
+            }
+            _ => return Err(invalid()),
+        }
+    }
+    if operand {
+        return Err(invalid());
+    }
+    sum.checked_add(sign * term).ok_or_else(invalid)
+}
+
 impl Default for Task {
     fn default() -> Self {
         let now = SystemTime::now();
@@ -144,18 +1099,235 @@ impl Default for Task {
             group: None,
             dir: None,
             env: HashMap::new(),
+            env_file: Vec::new(),
+            inherit_env: None,
             stdin: None,
             stdout: None,
             stderr: None,
+            log_rotate: None,
+            stop_signal: None,
+            stop_timeout: None,
+            kill_descendants: false,
+            restart_policy: None,
+            misfire: None,
+            misfire_grace: None,
+            misfire_max: None,
+            success_exit_codes: vec![0],
+            restart_exit_codes: Vec::new(),
+            depends_on: Vec::new(),
+            depends_wait: None,
+            health_check: None,
+            health: None,
+            readiness: None,
+            pre_start: None,
+            post_start: None,
+            pre_stop: None,
+            post_stop: None,
+            limits: None,
+            user: None,
+            user_group: None,
+            supplementary_groups: Vec::new(),
+            umask: None,
+            capabilities: None,
+            max_memory_restart: None,
+            max_cpu_restart: None,
+            max_cpu_seconds: None,
+            restart_reason: None,
+            instances: None,
+            instance: None,
+            watch: None,
+            watch_ignore: Vec::new(),
+            watch_debounce_ms: None,
             created_at: timestamp,
             task_type: TaskType::None,
             pid: None,
+            pid_start_time: None,
             status: None,
             code: None,
         }
     }
 }
 
+impl Task {
+    /// Environment of the task process: the daemon environment inherited by
+    /// `inherit_env` (`default` when unset), then `env_file` in order, then `env`,
+    /// later ones override earlier ones
+    ///
+    /// Values of `env` are interpolated with the daemon environment and `env_file`.
+    /// Secret references of `env` are not included, see [`Task::secrets`].
+    pub fn environment(
+        &self,
+        default: &InheritEnv,
+    ) -> Result<HashMap<String, String>, Box<dyn std::error::Error>> {
+        let inherit = self.inherit_env.as_ref().unwrap_or(default);
+        let mut vars: HashMap<String, String> = std::env::vars()
+            .filter(|(name, _)| inherit.allows(name))
+            .collect();
+        for file in &self.env_file {
+            // 未解析的相对路径相对于任务工作目录
+            let path = match &self.dir {
+                Some(dir) => Path::new(dir).join(file),
+                None => PathBuf::from(file),
+            };
+            vars.extend(env::read_dotenv(&path, &vars)?);
+        }
+        let inline: Vec<(String, String)> = self
+            .env
+            .iter()
+            .filter_map(|(key, value)| {
+                value
+                    .plain()
+                    .map(|value| (key.clone(), env::interpolate(value, &vars)))
+            })
+            .collect();
+        vars.extend(inline);
+        Ok(vars)
+    }
+
+    /// Resolve secret references of `env`, called only when the process is spawned
+    pub fn secrets(&self) -> Result<Vec<(String, String)>, Box<dyn std::error::Error>> {
+        self.env
+            .iter()
+            .filter(|(_, value)| value.is_secret())
+            .map(|(key, value)| {
+                value
+                    .resolve()
+                    .map(|value| (key.clone(), value))
+                    .map_err(|e| format!("Env {}: {}", key, e).into())
+            })
+            .collect()
+    }
+
+    /// Replace `${VAR}` and `${VAR:-default}` in command, args, dir, stdout and stderr
+    /// with variables of [`Task::environment`]
+    pub fn interpolate(&mut self, default: &InheritEnv) -> Result<(), Box<dyn std::error::Error>> {
+        let vars = self.environment(default)?;
+        self.command = env::interpolate(&self.command, &vars);
+        for arg in self.args.iter_mut() {
+            *arg = env::interpolate(arg, &vars);
+        }
+        for path in [&mut self.dir, &mut self.stdout, &mut self.stderr]
+            .into_iter()
+            .flatten()
+        {
+            *path = env::interpolate(path, &vars);
+        }
+        for hook in self.hooks_mut() {
+            hook.command = env::interpolate(&hook.command, &vars);
+            for arg in hook.args.iter_mut() {
+                *arg = env::interpolate(arg, &vars);
+            }
+        }
+        Ok(())
+    }
+
+    fn hooks_mut(&mut self) -> impl Iterator<Item = &mut Hook> {
+        [
+            &mut self.pre_start,
+            &mut self.post_start,
+            &mut self.pre_stop,
+            &mut self.post_stop,
+        ]
+        .into_iter()
+        .flatten()
+    }
+
+    /// Resolve relative `env_file` against `dir`, the directory of the task file
+    pub fn resolve_env_files(&mut self, dir: &Path) {
+        for file in self.env_file.iter_mut() {
+            let path = get_with_home_path(file);
+            if path.is_relative() {
+                *file = dir.join(path).to_string_lossy().to_string();
+            } else {
+                *file = path.to_string_lossy().to_string();
+            }
+        }
+    }
+
+    /// Task run as instance `instance` of this task with `instances`
+    pub fn instance(&self, instance: u32) -> Result<Task, String> {
+        let mut task = self.clone();
+        for value in task.env.values_mut() {
+            if let EnvValue::Plain(v) = value {
+                *v = render_template(v, instance)?;
+            }
+        }
+        for arg in task.args.iter_mut() {
+            *arg = render_template(arg, instance)?;
+        }
+        task.env
+            .insert("WATCHMEN_INSTANCE".to_string(), instance.to_string().into());
+        task.instances = None;
+        task.instance = Some(instance);
+        task.pid = None;
+        task.pid_start_time = None;
+        task.code = None;
+        task.health = None;
+        task.restart_reason = None;
+        Ok(task)
+    }
+
+    /// Restart policy in effect, tasks without explicit policy keep the old
+    /// behaviour: restart on failure only when `max_restart` is set
+    pub fn restart_policy(&self) -> RestartPolicy {
+        if let Some(policy) = self.restart_policy {
+            return policy;
+        }
+        match &self.task_type {
+            TaskType::Async(tt) if tt.max_restart.is_some() => RestartPolicy::OnFailure,
+            _ => RestartPolicy::Never,
+        }
+    }
+
+    /// Misfire policy in effect, tasks without explicit policy keep the old behaviour:
+    /// scheduled tasks skip missed runs and periodic tasks run once
+    pub fn misfire_policy(&self) -> MisfirePolicy {
+        match (self.misfire, &self.task_type) {
+            (Some(policy), _) => policy,
+            (None, TaskType::Periodic(_)) => MisfirePolicy::RunOnce,
+            (None, _) => MisfirePolicy::Skip,
+        }
+    }
+
+    /// Whether the monitor should start again the stopped async task
+    ///
+    /// Tasks without explicit policy keep the old behaviour: recovered unless stopped
+    /// by the user or by `kill -15` (exit code 143).
+    pub fn should_recover(&self) -> bool {
+        match &self.task_type {
+            TaskType::Async(tt) if !tt.stopped_by_user && !tt.restarts_exhausted() => {}
+            _ => return false,
+        }
+        match self.restart_policy {
+            Some(_) => self.should_restart(self.code),
+            None => self.code != Some(143),
+        }
+    }
+
+    /// Whether the async task should be restarted after its process exited with `code`
+    ///
+    /// `code` is `None` when the exit status is unknown (e.g. process adopted after a
+    /// daemon restart), which is not treated as a failure.
+    pub fn should_restart(&self, code: Option<i32>) -> bool {
+        match &self.task_type {
+            TaskType::Async(tt) if !tt.stopped_by_user => {}
+            _ => return false,
+        }
+        match self.restart_policy() {
+            RestartPolicy::Never => false,
+            RestartPolicy::Always | RestartPolicy::UnlessStopped => true,
+            RestartPolicy::OnFailure => match code {
+                None => false,
+                Some(code) if self.success_exit_codes.contains(&code) => false,
+                Some(code) if !self.restart_exit_codes.is_empty() => {
+                    self.restart_exit_codes.contains(&code)
+                }
+                _ => true,
+            },
+        }
+    }
+}
+
 unsafe impl Send for Task {}
 unsafe impl Sync for Task {}
 
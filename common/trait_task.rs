@@ -1,11 +1,16 @@
-use std::{error::Error, fs::File, io::Read, path::Path, process::Stdio};
+use std::{error::Error, ffi::CString, fs::File, io::Read, path::Path, process::Stdio};
 
 use configparser::ini::Ini;
 use tokio::process::{Child, Command};
 
 use crate::common::{
     arg::{AddArgs, FlagArgs},
-    task::{AsyncTask, PeriodicTask, ScheduledTask, Task, TaskFlag, TaskType, Tasks},
+    credential::Credential,
+    task::{
+        parse_size, AsyncTask, ConcurrencyPolicy, EnvValue, HealthCheck, HealthCheckType, Hook,
+        InheritEnv, Limits, LogRotate, MisfirePolicy, PeriodicTask, Readiness, ReadinessType,
+        RestartPolicy, ScheduledTask, StartOptions, Task, TaskFlag, TaskType, Tasks,
+    },
 };
 
 impl TaskFlag {
@@ -248,12 +253,193 @@ impl Task {
             for env in ini.get(section, "env").unwrap_or(String::new()).split(" ") {
                 let kv: Vec<&str> = env.split("=").collect();
                 if kv.len() == 2 {
-                    task.env.insert(kv[0].to_string(), kv[1].to_string());
+                    task.env.insert(kv[0].to_string(), kv[1].into());
                 }
             }
+            // 密钥引用：secret_file = NAME=/path/to/file，from_env = NAME=VAR
+            for (key, secret) in [("secret_file", true), ("from_env", false)] {
+                for pair in ini.get(section, key).unwrap_or_default().split_whitespace() {
+                    let (name, value) = match pair.split_once('=') {
+                        Some(kv) => kv,
+                        None => {
+                            return Err(Box::new(std::io::Error::new(
+                                std::io::ErrorKind::Other,
+                                format!("Invalid {}: {}", key, pair),
+                            )))
+                        }
+                    };
+                    let value = if secret {
+                        EnvValue::SecretFile {
+                            secret_file: value.to_string(),
+                        }
+                    } else {
+                        EnvValue::FromEnv {
+                            from_env: value.to_string(),
+                        }
+                    };
+                    task.env.insert(name.to_string(), value);
+                }
+            }
+            if let Some(inherit) = ini.get(section, "inherit_env") {
+                task.inherit_env = Some(inherit.parse::<InheritEnv>()?);
+            }
+            if let Some(files) = ini.get(section, "env_file") {
+                task.env_file = files.split_whitespace().map(|f| f.to_string()).collect();
+            }
             task.stdin = ini.getbool(section, "stdin")?;
             task.stdout = ini.get(section, "stdout");
             task.stderr = ini.get(section, "stderr");
+            task.stop_signal = ini.get(section, "stop_signal");
+            if let Some(stop_timeout) = ini.getint(section, "stop_timeout")? {
+                if stop_timeout < 0 {
+                    return Err(Box::new(std::io::Error::new(
+                        std::io::ErrorKind::Other,
+                        format!("Invalid stop_timeout: {}", stop_timeout),
+                    )));
+                }
+                task.stop_timeout = Some(stop_timeout as u64);
+            }
+            if let Some(kill_descendants) = ini.getbool(section, "kill_descendants")? {
+                task.kill_descendants = kill_descendants;
+            }
+            if let Some(policy) = ini.get(section, "restart_policy") {
+                task.restart_policy = Some(policy.parse::<RestartPolicy>()?);
+            }
+            if let Some(misfire) = ini.get(section, "misfire") {
+                task.misfire = Some(misfire.parse::<MisfirePolicy>()?);
+            }
+            task.misfire_grace = ini.getuint(section, "misfire_grace")?;
+            task.misfire_max = ini.getuint(section, "misfire_max")?;
+            if let Some(codes) = ini.get(section, "success_exit_codes") {
+                task.success_exit_codes = codes
+                    .split_whitespace()
+                    .map(|c| c.parse::<i32>())
+                    .collect::<Result<Vec<i32>, _>>()?;
+            }
+            if let Some(codes) = ini.get(section, "restart_exit_codes") {
+                task.restart_exit_codes = codes
+                    .split_whitespace()
+                    .map(|c| c.parse::<i32>())
+                    .collect::<Result<Vec<i32>, _>>()?;
+            }
+            if let Some(deps) = ini.get(section, "depends_on") {
+                task.depends_on = deps
+                    .split_whitespace()
+                    .map(|d| d.parse::<i64>())
+                    .collect::<Result<Vec<i64>, _>>()?;
+            }
+            if let Some(wait) = ini.getint(section, "depends_wait")? {
+                if wait < 0 {
+                    return Err(Box::new(std::io::Error::new(
+                        std::io::ErrorKind::Other,
+                        format!("Invalid depends_wait: {}", wait),
+                    )));
+                }
+                task.depends_wait = Some(wait as u64);
+            }
+            if let Some(check_type) = ini.get(section, "health_check") {
+                let mut hc = HealthCheck::new(check_type.parse::<HealthCheckType>()?);
+                hc.url = ini.get(section, "health_check_url");
+                hc.address = ini.get(section, "health_check_address");
+                if let Some(command) = ini.get(section, "health_check_command") {
+                    let mut parts = command.split_whitespace().map(|s| s.to_string());
+                    hc.command = parts.next();
+                    hc.args = parts.collect();
+                }
+                for (key, value) in [
+                    ("health_check_interval", &mut hc.interval),
+                    ("health_check_timeout", &mut hc.timeout),
+                    ("health_check_retries", &mut hc.retries),
+                    ("health_check_start_period", &mut hc.start_period),
+                ] {
+                    if let Some(v) = ini.getuint(section, key)? {
+                        *value = v;
+                    }
+                }
+                if let Some(restart) = ini.getbool(section, "health_check_restart")? {
+                    hc.restart = restart;
+                }
+                hc.validate()?;
+                task.health_check = Some(hc);
+            }
+            if let Some(check_type) = ini.get(section, "readiness") {
+                let mut readiness = Readiness::new(check_type.parse::<ReadinessType>()?);
+                readiness.address = ini.get(section, "readiness_address");
+                readiness.path = ini.get(section, "readiness_path");
+                readiness.pattern = ini.get(section, "readiness_pattern");
+                if let Some(timeout) = ini.getuint(section, "readiness_timeout")? {
+                    readiness.timeout = timeout;
+                }
+                readiness.validate()?;
+                task.readiness = Some(readiness);
+            }
+            // 生命周期钩子：pre_start = python manage.py migrate，pre_start_timeout = 300
+            for (key, hook) in [
+                ("pre_start", &mut task.pre_start),
+                ("post_start", &mut task.post_start),
+                ("pre_stop", &mut task.pre_stop),
+                ("post_stop", &mut task.post_stop),
+            ] {
+                if let Some(line) = ini.get(section, key) {
+                    let mut h = Hook::parse(&line)?;
+                    if let Some(timeout) = ini.getuint(section, &format!("{}_timeout", key))? {
+                        h.timeout = timeout;
+                    }
+                    *hook = Some(h);
+                }
+            }
+            // 日志轮转：log_max_size = 100M，log_daily = true，log_max_files = 5，log_compress = true
+            let mut rotate = LogRotate::default();
+            if let Some(size) = ini.get(section, "log_max_size") {
+                rotate.max_size = Some(parse_size(&size)?);
+            }
+            rotate.daily = ini.getbool(section, "log_daily")?.unwrap_or(false);
+            if rotate.max_size.is_some() || rotate.daily {
+                rotate.compress = ini.getbool(section, "log_compress")?.unwrap_or(false);
+                if let Some(max_files) = ini.getuint(section, "log_max_files")? {
+                    rotate.max_files = max_files as u32;
+                }
+                rotate.validate()?;
+                task.log_rotate = Some(rotate);
+            }
+            let mut limits = Limits::default();
+            if let Some(memory_max) = ini.get(section, "memory_max") {
+                limits.memory_max = Some(parse_size(&memory_max)?);
+            }
+            limits.cpu_quota = ini.getfloat(section, "cpu_quota")?;
+            limits.cpu_weight = ini.getuint(section, "cpu_weight")?;
+            limits.pids_max = ini.getuint(section, "pids_max")?;
+            limits.nofile = ini.getuint(section, "nofile")?;
+            if let Some(core) = ini.get(section, "core") {
+                limits.core = Some(parse_size(&core)?);
+            }
+            if limits.has_cgroup() || limits.nofile.is_some() || limits.core.is_some() {
+                limits.validate()?;
+                task.limits = Some(limits);
+            }
+            task.user = ini.get(section, "user");
+            task.user_group = ini.get(section, "user_group");
+            if let Some(groups) = ini.get(section, "supplementary_groups") {
+                task.supplementary_groups =
+                    groups.split_whitespace().map(|g| g.to_string()).collect();
+            }
+            task.umask = ini.get(section, "umask");
+            if let Some(caps) = ini.get(section, "capabilities") {
+                task.capabilities = Some(caps.split_whitespace().map(|c| c.to_string()).collect());
+            }
+            if let Some(memory) = ini.get(section, "max_memory_restart") {
+                task.max_memory_restart = Some(parse_size(&memory)?);
+            }
+            task.max_cpu_restart = ini.getfloat(section, "max_cpu_restart")?.map(|c| c as f32);
+            task.max_cpu_seconds = ini.getuint(section, "max_cpu_seconds")?;
+            task.instances = ini.getuint(section, "instances")?.map(|n| n as u32);
+            if let Some(watch) = ini.get(section, "watch") {
+                task.watch = Some(watch.split_whitespace().map(|w| w.to_string()).collect());
+            }
+            if let Some(ignore) = ini.get(section, "watch_ignore") {
+                task.watch_ignore = ignore.split_whitespace().map(|w| w.to_string()).collect();
+            }
+            task.watch_debounce_ms = ini.getuint(section, "watch_debounce_ms")?;
             task.status = Some("added".to_string());
 
             let task_type = ini.get(section, "task_type").unwrap_or("none".to_string());
@@ -267,6 +453,10 @@ impl Task {
                         hour: None,
                         minute: None,
                         second: None,
+                        cron: ini.get(section, "cron"),
+                        next_run: None,
+                        timezone: ini.get(section, "timezone"),
+                        last_run: None,
                     };
                     if let Some(year) = ini.getint(section, "year")? {
                         if year < 1970 {
@@ -322,11 +512,13 @@ impl Task {
                         }
                         tt.second = Some(second as u32);
                     }
+                    tt.validate()
+                        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
                     TaskType::Scheduled(tt)
                 }
                 "async" => {
                     let max_restart = ini.get(section, "max_restart");
-                    TaskType::Async(AsyncTask {
+                    let mut tt = AsyncTask {
                         max_restart: if let Some(max) = max_restart {
                             Some(max.parse::<u64>()?)
                         } else {
@@ -335,14 +527,40 @@ impl Task {
                         has_restart: 0,
                         started_at: 0,
                         stopped_at: 0,
-                    })
+                        stopped_by_user: false,
+                        restart_delay: 0,
+                        backoff_multiplier: 2.0,
+                        max_backoff: None,
+                        min_uptime: None,
+                        next_restart_at: 0,
+                    };
+                    if let Some(restart_delay) = ini.get(section, "restart_delay") {
+                        tt.restart_delay = restart_delay.parse::<u64>()?;
+                    }
+                    if let Some(multiplier) = ini.getfloat(section, "backoff_multiplier")? {
+                        if multiplier < 1.0 {
+                            return Err(Box::new(std::io::Error::new(
+                                std::io::ErrorKind::Other,
+                                format!("Invalid backoff_multiplier: {}", multiplier),
+                            )));
+                        }
+                        tt.backoff_multiplier = multiplier;
+                    }
+                    if let Some(max_backoff) = ini.get(section, "max_backoff") {
+                        tt.max_backoff = Some(max_backoff.parse::<u64>()?);
+                    }
+                    if let Some(min_uptime) = ini.get(section, "min_uptime") {
+                        tt.min_uptime = Some(min_uptime.parse::<u64>()?);
+                    }
+                    TaskType::Async(tt)
                 }
                 "periodic" => {
                     let mut tt = PeriodicTask {
                         started_after: 0,
                         interval: 60,
                         last_run: 0,
-                        sync: true,
+                        concurrency_policy: ConcurrencyPolicy::Forbid,
+                        max_concurrent: None,
                     };
                     if let Some(started_after) = ini.getint(section, "started_after")? {
                         if started_after < 0 {
@@ -362,9 +580,10 @@ impl Task {
                         }
                         tt.interval = interval as u64;
                     }
-                    if let Some(sync) = ini.getbool(section, "sync")? {
-                        tt.sync = sync;
+                    if let Some(policy) = ini.get(section, "concurrency_policy") {
+                        tt.concurrency_policy = policy.parse::<ConcurrencyPolicy>()?;
                     }
+                    tt.max_concurrent = ini.getuint(section, "max_concurrent")?;
                     TaskType::Periodic(tt)
                 }
                 _ => {
@@ -378,14 +597,17 @@ impl Task {
             tasks.push(task);
         }
 
-        Ok(Tasks { task: tasks })
+        let mut tasks = Tasks { task: tasks };
+        tasks.resolve_env_files(path);
+        Ok(tasks)
     }
 
     pub fn from_toml(path: &Path) -> Result<Tasks, Box<dyn Error>> {
         let mut file = File::open(path)?;
         let mut contents = String::new();
         file.read_to_string(&mut contents)?;
-        let config: Tasks = toml::from_str(&contents)?;
+        let mut config: Tasks = toml::from_str(&contents)?;
+        config.resolve_env_files(path);
         Ok(config)
     }
 
@@ -394,7 +616,20 @@ impl Task {
         let mut contents = String::new();
         file.read_to_string(&mut contents)?;
         let tasks = Task::deserialize(&contents)?;
-        Ok(Tasks { task: tasks })
+        let mut tasks = Tasks { task: tasks };
+        tasks.resolve_env_files(path);
+        Ok(tasks)
+    }
+}
+
+impl Tasks {
+    /// Resolve relative `env_file` of tasks against the directory of the task file `path`
+    fn resolve_env_files(&mut self, path: &Path) {
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        for task in self.task.iter_mut() {
+            task.resolve_env_files(&dir);
+        }
     }
 }
 
@@ -411,28 +646,25 @@ impl Task {
 }
 
 impl Task {
-    pub async fn start(&self) -> Result<Child, Box<dyn Error>> {
+    pub async fn start(&self, opts: &StartOptions) -> Result<Child, Box<dyn Error>> {
         let mut command = Command::new(&self.command);
         let command = command.args(&self.args);
-        let command = command.envs(std::env::vars());
+        let command = command
+            .env_clear()
+            .envs(self.environment(&opts.inherit_env)?)
+            .envs(self.secrets()?);
         let mut command = command.kill_on_drop(false);
-        for (key, value) in &self.env {
-            command = command.env(key, value);
-        }
+        // 子进程使用独立的进程组，停止时向整个进程组发送信号
+        command = command.process_group(0);
         if let Some(dir) = &self.dir {
             command = command.current_dir(&dir);
         }
         if let Some(stdout) = &self.stdout {
-            if stdout != "" {
-                let dir = Path::new(stdout).parent().unwrap();
-                if !dir.exists() {
-                    std::fs::create_dir_all(dir)?;
-                }
-                let file = std::fs::OpenOptions::new()
-                    .create(true)
-                    .append(true)
-                    .open(stdout)?;
-                command = command.stdout(Stdio::from(file));
+            if stdout != "" && self.log_rotate.is_some() {
+                // 需要轮转的日志由守护进程写入
+                command = command.stdout(Stdio::piped());
+            } else if stdout != "" {
+                command = command.stdout(Stdio::from(log_file(stdout)?));
             } else {
                 command = command.stdout(Stdio::piped());
             }
@@ -440,16 +672,11 @@ impl Task {
             command = command.stdout(Stdio::null());
         }
         if let Some(stderr) = &self.stderr {
-            if stderr != "" {
-                let dir = Path::new(stderr).parent().unwrap();
-                if !dir.exists() {
-                    std::fs::create_dir_all(dir)?;
-                }
-                let file = std::fs::OpenOptions::new()
-                    .create(true)
-                    .append(true)
-                    .open(stderr)?;
-                command = command.stderr(Stdio::from(file));
+            if stderr != "" && self.log_rotate.is_some() {
+                // 需要轮转的日志由守护进程写入
+                command = command.stderr(Stdio::piped());
+            } else if stderr != "" {
+                command = command.stderr(Stdio::from(log_file(stderr)?));
             } else {
                 command = command.stderr(Stdio::piped());
             }
@@ -462,10 +689,116 @@ impl Task {
             command = command.stdin(Stdio::null());
         }
 
+        // 在子进程 exec 之前加入 cgroup、设置 rlimit 并切换用户，保证命令启动时限制已经生效
+        let cgroup_procs = match &opts.cgroup {
+            Some(dir) => Some(CString::new(format!("{}/cgroup.procs", dir))?),
+            None => None,
+        };
+        let (nofile, core) = match &self.limits {
+            Some(limits) => (limits.nofile, limits.core),
+            None => (None, None),
+        };
+        let credential = Credential::from_task(self)?.map(Credential::without_current);
+        if cgroup_procs.is_some() || nofile.is_some() || core.is_some() || credential.is_some() {
+            unsafe {
+                command = command.pre_exec(move || {
+                    if let Some(procs) = &cgroup_procs {
+                        join_cgroup(procs)?;
+                    }
+                    if let Some(nofile) = nofile {
+                        set_rlimit(libc::RLIMIT_NOFILE as i32, nofile)?;
+                    }
+                    if let Some(core) = core {
+                        set_rlimit(libc::RLIMIT_CORE as i32, core)?;
+                    }
+                    // 最后切换用户，之前的操作可能需要 root 权限
+                    if let Some(credential) = &credential {
+                        credential.apply()?;
+                    }
+                    Ok(())
+                });
+            }
+        }
+
         let child = command.spawn()?;
 
         Ok(child)
     }
+
+    /// Spawn the hook command with the dir, env and user of the task
+    ///
+    /// Output of the hook is appended to the log files of the task.
+    pub fn spawn_hook(&self, hook: &Hook, opts: &StartOptions) -> Result<Child, Box<dyn Error>> {
+        let mut command = Command::new(&hook.command);
+        command
+            .args(&hook.args)
+            .env_clear()
+            .envs(self.environment(&opts.inherit_env)?)
+            .envs(self.secrets()?)
+            .stdin(Stdio::null())
+            // 钩子使用独立的进程组，超时后结束整个进程组
+            .process_group(0)
+            .kill_on_drop(true);
+        if let Some(dir) = &self.dir {
+            command.current_dir(dir);
+        }
+        match self.stdout.as_deref() {
+            Some(stdout) if !stdout.is_empty() => command.stdout(Stdio::from(log_file(stdout)?)),
+            _ => command.stdout(Stdio::null()),
+        };
+        match self.stderr.as_deref() {
+            Some(stderr) if !stderr.is_empty() => command.stderr(Stdio::from(log_file(stderr)?)),
+            _ => command.stderr(Stdio::null()),
+        };
+        if let Some(credential) = Credential::from_task(self)?.map(Credential::without_current) {
+            unsafe {
+                command.pre_exec(move || credential.apply());
+            }
+        }
+        Ok(command.spawn()?)
+    }
+}
+
+/// Open the log file for appending, missing parent directories are created
+fn log_file(path: &str) -> Result<File, Box<dyn Error>> {
+    if let Some(dir) = Path::new(path).parent() {
+        if !dir.exists() {
+            std::fs::create_dir_all(dir)?;
+        }
+    }
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    Ok(file)
+}
+
+/// Move the calling process into the cgroup, only async-signal-safe calls are
+/// allowed because it runs between fork and exec
+fn join_cgroup(procs: &CString) -> std::io::Result<()> {
+    unsafe {
+        let fd = libc::open(procs.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC);
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let res = libc::write(fd, b"0".as_ptr() as *const libc::c_void, 1);
+        libc::close(fd);
+        if res < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+fn set_rlimit(resource: i32, value: u64) -> std::io::Result<()> {
+    let limit = libc::rlimit {
+        rlim_cur: value as libc::rlim_t,
+        rlim_max: value as libc::rlim_t,
+    };
+    if unsafe { libc::setrlimit(resource as _, &limit) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
 }
 
 impl Tasks {
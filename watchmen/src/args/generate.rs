@@ -41,6 +41,15 @@ cache = "$HOME/.watchmen/cache.json"
 # Monitor interval for rerun tasks, u64: second
 interval = 5
 
+# Delegated cgroup v2 directory for task resource limits (Linux only)
+# Default is None, cgroup limits are not available
+# cgroup = "/sys/fs/cgroup/watchmen"
+
+# Daemon environment variables inherited by tasks without their own `inherit_env`
+# Valid values are "all", "none" or a list of names, a name ending with `*` is a prefix
+# Default is "all"
+# inherit_env = ["PATH", "HOME", "LANG", "LC_*"]
+
 
 [sock]
 # The unix socket path of the watchmen server
@@ -66,6 +75,21 @@ queue_index = 0
 queue_name = "watchmen"
 subscribe_channels = ["watchmen"]
 subscribe_name = "watchmen"
+
+
+# Webhooks notified of task events, one [[notifier]] table for each webhook
+# Valid events are "exited", "restarted", "gave_up", "schedule_failed", default is all events
+# The body is a JSON template, {{ event }}, {{ id }}, {{ name }}, {{ group }}, {{ code }},
+# {{ message }} and {{ timestamp }} are replaced, default is the event as a JSON object
+# Failed requests are retried `retries` times (default 3), waiting `backoff_ms` (default 1000)
+# before the first retry and doubling it for each retry
+# [[notifier]]
+# url = "https://hooks.slack.com/services/xxx"
+# events = ["exited", "gave_up", "schedule_failed"]
+# body = '{"text": "Task {{ name }} {{ event }}: {{ message }}"}'
+# headers = { Authorization = "Bearer xxx" }
+# retries = 3
+# backoff_ms = 1000
 "#;
 
 pub fn generate(path: &str) -> Result<(), Box<dyn Error>> {
@@ -7,6 +7,7 @@ pub mod remove;
 pub mod restart;
 pub mod resume;
 pub mod run;
+pub mod scale;
 pub mod start;
 pub mod stop;
 
@@ -32,6 +33,7 @@ pub async fn handle_exec(commands: Commands, config: Config) -> Result<(), Box<d
         Commands::Remove(args) => self::remove::remove(args, config).await?,
         Commands::Pause(args) => self::pause::pause(args, config).await?,
         Commands::Resume(args) => self::resume::resume(args, config).await?,
+        Commands::Scale(args) => self::scale::scale(args, config).await?,
         Commands::List(args) => self::list::list(args, config).await?,
     }
     Ok(())
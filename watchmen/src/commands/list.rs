@@ -166,38 +166,48 @@ pub async fn print_result(res: Vec<Response>) {
     let mut column_code = Vec::new();
     column_code.push("ExitCode".bold());
 
+    let mut column_health = Vec::new();
+    column_health.push("Health".bold());
+
+    let mut column_reason = Vec::new();
+    column_reason.push("Reason".bold());
+
     let mut column_type = Vec::new();
     column_type.push("Type".bold());
 
-    for s in status {
-        total += 1;
+    for s in with_instances(status) {
+        // 实例行不计入任务总数
+        let counted = s.instance.is_none() as i32;
+        total += counted;
         column_id.push(s.id.to_string().italic());
         column_name.push(s.name.normal());
         match s.status {
             Some(t) => match t.as_str() {
                 "added" => {
-                    total_added += 1;
+                    total_added += counted;
                     column_status.push(t.magenta())
                 }
                 "running" => {
-                    total_running += 1;
+                    total_running += counted;
                     column_status.push(t.green())
                 }
                 "stopped" => {
-                    total_stopped += 1;
+                    total_stopped += counted;
                     column_status.push(t.red())
                 }
+                "starting" => column_status.push(t.bright_yellow()),
                 "auto restart" => column_status.push(t.truecolor(128, 128, 128)),
+                "errored" => column_status.push(t.bright_red()),
                 "waiting" => {
-                    total_waiting += 1;
+                    total_waiting += counted;
                     column_status.push(t.blue())
                 }
                 "interval" => {
-                    total_interval += 1;
+                    total_interval += counted;
                     column_status.push(t.cyan())
                 }
                 "paused" => {
-                    total_paused += 1;
+                    total_paused += counted;
                     column_status.push(t.yellow())
                 }
                 "executing" => column_status.push(t.green()),
@@ -214,6 +224,18 @@ pub async fn print_result(res: Vec<Response>) {
             Some(t) => column_code.push(t.to_string().normal()),
             None => column_code.push("".normal()),
         }
+        match s.health {
+            Some(t) => match t.as_str() {
+                "healthy" => column_health.push(t.green()),
+                "unhealthy" => column_health.push(t.bright_red()),
+                _ => column_health.push(t.yellow()),
+            },
+            None => column_health.push("".normal()),
+        }
+        match s.restart_reason {
+            Some(t) => column_reason.push(t.truecolor(128, 128, 128)),
+            None => column_reason.push("".normal()),
+        }
         match s.task_type {
             crate::common::task::TaskType::Scheduled(_) => column_type.push("Scheduled".normal()),
             crate::common::task::TaskType::Async(_) => column_type.push("Async".normal()),
@@ -227,6 +249,8 @@ pub async fn print_result(res: Vec<Response>) {
     let max_command = column_command.iter().map(|s| s.len()).max().unwrap();
     let max_pid = column_pid.iter().map(|s| s.len()).max().unwrap();
     let max_code = column_code.iter().map(|s| s.len()).max().unwrap();
+    let max_health = column_health.iter().map(|s| s.len()).max().unwrap();
+    let max_reason = column_reason.iter().map(|s| s.len()).max().unwrap();
     let max_type = column_type.iter().map(|s| s.len()).max().unwrap();
 
     let max_sum = max_id
@@ -235,20 +259,24 @@ pub async fn print_result(res: Vec<Response>) {
         + max_command
         + max_pid
         + max_code
+        + max_health
+        + max_reason
         + max_type
-        + 3 * (7 - 1)
+        + 3 * (9 - 1)
         + 4;
 
     for i in 0..column_id.len() {
         println!("{:-<max_sum$}", "", max_sum = max_sum);
         println!(
-            "| {: <max_id$} | {: <max_name$} | {: <max_status$} | {: <max_command$} | {: <max_pid$} | {: <max_code$} | {: <max_type$} |",
+            "| {: <max_id$} | {: <max_name$} | {: <max_status$} | {: <max_command$} | {: <max_pid$} | {: <max_code$} | {: <max_health$} | {: <max_reason$} | {: <max_type$} |",
             column_id[i],
             column_name[i],
             column_status[i],
             column_command[i],
             column_pid[i],
             column_code[i],
+            column_health[i],
+            column_reason[i],
             column_type[i],
             max_id = max_id,
             max_name = max_name,
@@ -256,6 +284,8 @@ pub async fn print_result(res: Vec<Response>) {
             max_command = max_command,
             max_pid = max_pid,
             max_code = max_code,
+            max_health = max_health,
+            max_reason = max_reason,
             max_type = max_type,
         );
     }
@@ -272,6 +302,20 @@ pub async fn print_result(res: Vec<Response>) {
     );
 }
 
+/// Task rows each followed by the rows of its instances, named `name#instance`
+fn with_instances(status: Vec<Status>) -> Vec<Status> {
+    let mut rows = Vec::new();
+    for mut s in status {
+        let instances = std::mem::take(&mut s.instances);
+        rows.push(s);
+        for mut i in instances {
+            i.name = format!("{}#{}", i.name, i.instance.unwrap_or_default());
+            rows.push(i);
+        }
+    }
+    rows
+}
+
 async fn extract_resp_from(res: Vec<Response>, status: &mut Vec<Status>) -> bool {
     for r in res {
         if r.code != 10000 {
@@ -330,11 +374,19 @@ pub async fn print_result_more(res: Vec<Response>) {
     let mut column_code = Vec::new();
     column_code.push("ExitCode".bold());
 
+    let mut column_health = Vec::new();
+    column_health.push("Health".bold());
+
+    let mut column_reason = Vec::new();
+    column_reason.push("Reason".bold());
+
     let mut column_type = Vec::new();
     column_type.push("Type".bold());
 
-    for s in status {
-        total += 1;
+    for s in with_instances(status) {
+        // 实例行不计入任务总数
+        let counted = s.instance.is_none() as i32;
+        total += counted;
         column_id.push(s.id.to_string().italic());
         if s.group.is_some() {
             column_group.push(s.group.unwrap().normal());
@@ -345,28 +397,30 @@ pub async fn print_result_more(res: Vec<Response>) {
         match s.status {
             Some(t) => match t.as_str() {
                 "added" => {
-                    total_added += 1;
+                    total_added += counted;
                     column_status.push(t.magenta())
                 }
                 "running" => {
-                    total_running += 1;
+                    total_running += counted;
                     column_status.push(t.green())
                 }
                 "stopped" => {
-                    total_stopped += 1;
+                    total_stopped += counted;
                     column_status.push(t.red())
                 }
+                "starting" => column_status.push(t.bright_yellow()),
                 "auto restart" => column_status.push(t.truecolor(128, 128, 128)),
+                "errored" => column_status.push(t.bright_red()),
                 "waiting" => {
-                    total_waiting += 1;
+                    total_waiting += counted;
                     column_status.push(t.blue())
                 }
                 "interval" => {
-                    total_interval += 1;
+                    total_interval += counted;
                     column_status.push(t.cyan())
                 }
                 "paused" => {
-                    total_paused += 1;
+                    total_paused += counted;
                     column_status.push(t.yellow())
                 }
                 "executing" => column_status.push(t.green()),
@@ -384,6 +438,18 @@ pub async fn print_result_more(res: Vec<Response>) {
             Some(t) => column_code.push(t.to_string().normal()),
             None => column_code.push("".normal()),
         }
+        match s.health {
+            Some(t) => match t.as_str() {
+                "healthy" => column_health.push(t.green()),
+                "unhealthy" => column_health.push(t.bright_red()),
+                _ => column_health.push(t.yellow()),
+            },
+            None => column_health.push("".normal()),
+        }
+        match s.restart_reason {
+            Some(t) => column_reason.push(t.truecolor(128, 128, 128)),
+            None => column_reason.push("".normal()),
+        }
         match s.task_type {
             crate::common::task::TaskType::Scheduled(_) => column_type.push("Scheduled".normal()),
             crate::common::task::TaskType::Async(_) => column_type.push("Async".normal()),
@@ -399,6 +465,8 @@ pub async fn print_result_more(res: Vec<Response>) {
     let max_args = column_args.iter().map(|s| s.len()).max().unwrap();
     let max_pid = column_pid.iter().map(|s| s.len()).max().unwrap();
     let max_code = column_code.iter().map(|s| s.len()).max().unwrap();
+    let max_health = column_health.iter().map(|s| s.len()).max().unwrap();
+    let max_reason = column_reason.iter().map(|s| s.len()).max().unwrap();
     let max_type = column_type.iter().map(|s| s.len()).max().unwrap();
 
     let max_sum = max_id
@@ -409,14 +477,16 @@ pub async fn print_result_more(res: Vec<Response>) {
         + max_args
         + max_pid
         + max_code
+        + max_health
+        + max_reason
         + max_type
-        + 3 * (9 - 1)
+        + 3 * (11 - 1)
         + 5;
 
     for i in 0..column_id.len() {
         println!("{:-<max_sum$}", "", max_sum = max_sum);
         println!(
-            "| {: <max_id$} | {: <max_group$}  | {: <max_name$} | {: <max_status$} | {: <max_command$} | {: <max_args$} | {: <max_pid$} | {: <max_code$} | {: <max_type$} |",
+            "| {: <max_id$} | {: <max_group$}  | {: <max_name$} | {: <max_status$} | {: <max_command$} | {: <max_args$} | {: <max_pid$} | {: <max_code$} | {: <max_health$} | {: <max_reason$} | {: <max_type$} |",
             column_id[i],
             column_group[i],
             column_name[i],
@@ -425,6 +495,8 @@ pub async fn print_result_more(res: Vec<Response>) {
             column_args[i],
             column_pid[i],
             column_code[i],
+            column_health[i],
+            column_reason[i],
             column_type[i],
             max_id = max_id,
             max_name = max_name,
@@ -433,6 +505,8 @@ pub async fn print_result_more(res: Vec<Response>) {
             max_args = max_args,
             max_pid = max_pid,
             max_code = max_code,
+            max_health = max_health,
+            max_reason = max_reason,
             max_type = max_type,
         );
     }
@@ -470,35 +544,39 @@ pub async fn print_result_less(res: Vec<Response>) {
     let mut column_status = Vec::new();
     column_status.push("Status".bold());
 
-    for s in status {
-        total += 1;
+    for s in with_instances(status) {
+        // 实例行不计入任务总数
+        let counted = s.instance.is_none() as i32;
+        total += counted;
         column_id.push(s.id.to_string().italic());
         column_name.push(s.name.normal());
         match s.status {
             Some(t) => match t.as_str() {
                 "added" => {
-                    total_added += 1;
+                    total_added += counted;
                     column_status.push(t.magenta())
                 }
                 "running" => {
-                    total_running += 1;
+                    total_running += counted;
                     column_status.push(t.green())
                 }
                 "stopped" => {
-                    total_stopped += 1;
+                    total_stopped += counted;
                     column_status.push(t.red())
                 }
+                "starting" => column_status.push(t.bright_yellow()),
                 "auto restart" => column_status.push(t.truecolor(128, 128, 128)),
+                "errored" => column_status.push(t.bright_red()),
                 "waiting" => {
-                    total_waiting += 1;
+                    total_waiting += counted;
                     column_status.push(t.blue())
                 }
                 "interval" => {
-                    total_interval += 1;
+                    total_interval += counted;
                     column_status.push(t.cyan())
                 }
                 "paused" => {
-                    total_paused += 1;
+                    total_paused += counted;
                     column_status.push(t.yellow())
                 }
                 "executing" => column_status.push(t.green()),
@@ -0,0 +1,26 @@
+use crate::common::{
+    arg::ScaleArgs,
+    config::Config,
+    handle::{Command, Request, Response},
+};
+use std::error::Error;
+
+use crate::{engine::send, utils::print_result};
+
+use super::taskflag_to_request;
+
+pub async fn scale(args: ScaleArgs, config: Config) -> Result<(), Box<dyn Error>> {
+    let taskflags = taskflag_to_request(args.flag, config.clone()).await?;
+    if taskflags.is_empty() {
+        print_result(vec![Response::wrong("No task to scale".to_string())]).await;
+    } else {
+        let mut requests = Vec::new();
+        for taskflag in taskflags {
+            requests.push(Request {
+                command: Command::Scale(taskflag, args.instances),
+            });
+        }
+        print_result(send(config, requests).await?).await;
+    }
+    Ok(())
+}
@@ -1,26 +1,89 @@
 use crate::common::{
-    arg::FlagArgs,
+    arg::StartArgs,
     config::Config,
-    handle::{Command, Request, Response},
+    handle::{Command, Data, Request, Response},
+    task::{TaskFlag, TaskType},
+};
+use std::{
+    error::Error,
+    time::{Duration, Instant},
 };
-use std::error::Error;
 
 use crate::{engine::send, utils::print_result};
 
 use super::taskflag_to_request;
 
-pub async fn start(args: FlagArgs, config: Config) -> Result<(), Box<dyn Error>> {
-    let taskflags = taskflag_to_request(args, config.clone()).await?;
+pub async fn start(args: StartArgs, config: Config) -> Result<(), Box<dyn Error>> {
+    let taskflags = taskflag_to_request(args.flag, config.clone()).await?;
     if taskflags.is_empty() {
         print_result(vec![Response::wrong("No task to start".to_string())]).await;
     } else {
         let mut requests = Vec::new();
-        for taskflag in taskflags {
+        for taskflag in taskflags.clone() {
             requests.push(Request {
                 command: Command::Start(taskflag),
             });
         }
-        print_result(send(config, requests).await?).await;
+        let res = send(config.clone(), requests).await?;
+        let started = res.iter().all(|r| r.is_success());
+        print_result(res).await;
+        if args.wait && started {
+            print_result(vec![wait(taskflags, config, args.timeout).await?]).await;
+        }
     }
     Ok(())
 }
+
+/// Wait until async tasks are running, tasks in `starting` are not ready yet
+async fn wait(
+    taskflags: Vec<TaskFlag>,
+    config: Config,
+    timeout: u64,
+) -> Result<Response, Box<dyn Error>> {
+    let deadline = Instant::now() + Duration::from_secs(timeout);
+    loop {
+        let mut requests = Vec::new();
+        for taskflag in taskflags.clone() {
+            requests.push(Request {
+                command: Command::List(Some(taskflag)),
+            });
+        }
+        let mut pending = Vec::new();
+        for r in send(config.clone(), requests).await? {
+            if !r.is_success() {
+                return Ok(r);
+            }
+            if let Some(Data::Status(status)) = r.data {
+                for s in status {
+                    if !matches!(s.task_type, TaskType::Async(_)) {
+                        continue;
+                    }
+                    match s.status.as_deref() {
+                        Some("running") => {}
+                        Some("stopped") | Some("errored") => {
+                            return Ok(Response::failed(format!(
+                                "Task [{}] is {}",
+                                s.id,
+                                s.status.unwrap_or_default()
+                            )));
+                        }
+                        _ => pending.push(s.id.to_string()),
+                    }
+                }
+            }
+        }
+        if pending.is_empty() {
+            return Ok(Response::success(Some(Data::String(
+                "Tasks are running".to_string(),
+            ))));
+        }
+        if Instant::now() >= deadline {
+            return Ok(Response::failed(format!(
+                "Task [{}] is not running after {}s",
+                pending.join(","),
+                timeout
+            )));
+        }
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    }
+}
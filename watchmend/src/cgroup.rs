@@ -0,0 +1,158 @@
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+    time::Duration,
+};
+
+use tracing::warn;
+
+use crate::common::task::{Limits, TaskMatrix};
+
+/// Period of `cpu.max`, in microseconds
+static CPU_PERIOD: u64 = 100_000;
+
+/// Delegated cgroup v2 directory of the daemon, task cgroups are created under it
+static ROOT: OnceLock<PathBuf> = OnceLock::new();
+
+/// Set cgroup directory of the daemon and enable controllers for task cgroups
+///
+/// The directory must be a cgroup v2 directory writable by the daemon (e.g.
+/// `Delegate=yes` under systemd) without processes of its own.
+pub fn init(root: &str) -> Result<(), Box<dyn Error>> {
+    let root = PathBuf::from(root);
+    if !root.join("cgroup.controllers").exists() {
+        return Err(format!("{} is not a cgroup v2 directory", root.display()).into());
+    }
+    let available = std::fs::read_to_string(root.join("cgroup.controllers"))?;
+    let controllers: Vec<String> = ["cpu", "memory", "pids"]
+        .iter()
+        .filter(|c| available.split_whitespace().any(|a| a == **c))
+        .map(|c| format!("+{}", c))
+        .collect();
+    if let Err(e) = std::fs::write(root.join("cgroup.subtree_control"), controllers.join(" ")) {
+        warn!("Enable cgroup controllers {:?} failed: {}", controllers, e);
+    }
+    let _ = ROOT.set(root.canonicalize()?);
+    Ok(())
+}
+
+/// Cgroup directory of the task
+pub fn task_dir(id: i64) -> Option<PathBuf> {
+    ROOT.get().map(|root| root.join(format!("task-{}", id)))
+}
+
+/// Create the task cgroup and write its limits, returns the directory to join
+pub fn prepare(id: i64, limits: Option<&Limits>) -> Result<Option<String>, Box<dyn Error>> {
+    let dir = match task_dir(id) {
+        Some(dir) => dir,
+        None => {
+            if limits.map(|l| l.has_cgroup()).unwrap_or(false) {
+                return Err(format!(
+                    "Task [{}] has cgroup limits but cgroup is not configured",
+                    id
+                )
+                .into());
+            }
+            return Ok(None);
+        }
+    };
+    if !dir.exists() {
+        std::fs::create_dir(&dir)?;
+    }
+    let default = Limits::default();
+    let limits = limits.unwrap_or(&default);
+    // 未配置的限制写入默认值，避免沿用上次启动时的配置
+    write(&dir, "memory.max", limits.memory_max.map(|m| m.to_string()), "max")?;
+    write(
+        &dir,
+        "cpu.max",
+        limits
+            .cpu_quota
+            .map(|quota| format!("{} {}", (quota * CPU_PERIOD as f64) as u64, CPU_PERIOD)),
+        &format!("max {}", CPU_PERIOD),
+    )?;
+    write(&dir, "cpu.weight", limits.cpu_weight.map(|w| w.to_string()), "100")?;
+    write(&dir, "pids.max", limits.pids_max.map(|p| p.to_string()), "max")?;
+    Ok(Some(dir.to_string_lossy().to_string()))
+}
+
+fn write(
+    dir: &Path,
+    file: &str,
+    value: Option<String>,
+    default: &str,
+) -> Result<(), Box<dyn Error>> {
+    let path = dir.join(file);
+    let value = match value {
+        Some(value) => {
+            if !path.exists() {
+                return Err(format!("Cgroup controller of {} is not enabled", file).into());
+            }
+            value
+        }
+        // 控制器未启用时对应的文件不存在，无需写入默认值
+        None if !path.exists() => return Ok(()),
+        None => default.to_string(),
+    };
+    std::fs::write(&path, &value)
+        .map_err(|e| format!("Write {} to {} failed: {}", value, path.display(), e).into())
+}
+
+/// Remove the task cgroup, fails silently when processes are still in it
+pub fn remove(id: i64) {
+    if let Some(dir) = task_dir(id) {
+        if dir.exists() {
+            if let Err(e) = std::fs::remove_dir(&dir) {
+                warn!("Remove cgroup {} failed: {}", dir.display(), e);
+            }
+        }
+    }
+}
+
+/// Task cgroup directory that process `pid` belongs to, `None` when the process
+/// is not in a task cgroup
+fn process_dir(pid: u32) -> Option<PathBuf> {
+    let root = ROOT.get()?;
+    let content = std::fs::read_to_string(format!("/proc/{}/cgroup", pid)).ok()?;
+    let path = content.lines().find_map(|l| l.strip_prefix("0::"))?;
+    let dir = Path::new("/sys/fs/cgroup").join(path.trim_start_matches('/'));
+    if dir.parent()? != root {
+        return None;
+    }
+    Some(dir)
+}
+
+/// Memory in bytes used by the task cgroup that process `pid` belongs to
+pub fn memory(pid: u32) -> Option<u64> {
+    let dir = process_dir(pid)?;
+    std::fs::read_to_string(dir.join("memory.current"))
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// Usage of the task cgroup that process `pid` belongs to, `None` when the
+/// process is not in a task cgroup
+pub fn usage(pid: u32) -> Option<TaskMatrix> {
+    let dir = process_dir(pid)?;
+    let memory = memory(pid)?;
+    let before = cpu_usage_usec(&dir)?;
+    std::thread::sleep(sysinfo::MINIMUM_CPU_UPDATE_INTERVAL);
+    let after = cpu_usage_usec(&dir)?;
+    let elapsed = sysinfo::MINIMUM_CPU_UPDATE_INTERVAL.max(Duration::from_millis(1));
+    Some(TaskMatrix {
+        cpu_usage: (after.saturating_sub(before) as f64 / elapsed.as_micros() as f64 * 100.0)
+            as f32,
+        memory_usage: memory / 1024 / 1024,
+    })
+}
+
+fn cpu_usage_usec(dir: &Path) -> Option<u64> {
+    let content = std::fs::read_to_string(dir.join("cpu.stat")).ok()?;
+    content
+        .lines()
+        .find_map(|l| l.strip_prefix("usage_usec "))
+        .and_then(|v| v.trim().parse().ok())
+}
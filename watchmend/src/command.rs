@@ -19,6 +19,7 @@ pub async fn handle_exec(request: Request) -> Result<Response, Box<dyn Error>> {
         Command::Write(tf, data) => global::write(tf, data).await,
         Command::Pause(tf) => global::pause(tf).await,
         Command::Resume(tf) => global::resume(tf).await,
+        Command::Scale(tf, instances) => global::scale(tf, instances).await,
         Command::List(condition) => global::list(condition).await,
     };
     match r {
@@ -34,8 +35,8 @@ pub async fn handle_exec(request: Request) -> Result<Response, Box<dyn Error>> {
 }
 
 // 获取操作系统当前主要监控指标：CPU, 内存, 硬盘, 网络
-pub(crate) fn matrix(id: usize) -> Result<Matrix, Box<dyn Error>> {
+pub(crate) async fn matrix(id: usize) -> Result<Matrix, Box<dyn Error>> {
     info!("Receive request: matrix");
-    let r = global::matrix(id);
+    let r = global::matrix(id).await;
     r
 }
\ No newline at end of file
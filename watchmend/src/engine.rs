@@ -17,6 +17,19 @@ mod actix_http;
 
 
 pub async fn start(config: Config, load: bool) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(cgroup) = &config.watchmen.cgroup {
+        if let Err(e) = crate::cgroup::init(cgroup) {
+            info!("Cgroup init failed: {}", e);
+            println!("Cgroup init failed: {}", e);
+        }
+    }
+    if let Some(inherit) = &config.watchmen.inherit_env {
+        global::set_inherit_env(inherit.clone());
+    }
+    if let Err(e) = crate::notify::init(config.notifier.clone()) {
+        info!("Notifier init failed: {}", e);
+        println!("Notifier init failed: {}", e);
+    }
     if load {
         if let Some(path) = config.watchmen.cache.clone() {
             global::set_cache(path.clone()).await;
@@ -108,6 +121,7 @@ pub async fn start(config: Config, load: bool) -> Result<(), Box<dyn std::error:
     s_ctrl_d.abort();
 
     println!("Shutting down...");
+    global::shutdown().await?;
     #[cfg(feature = "sock")]
     if config.watchmen.engines.contains(&"sock".to_string()) && joinhandle_sock.is_some() {
         joinhandle_sock.unwrap().abort();
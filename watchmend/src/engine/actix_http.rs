@@ -65,7 +65,7 @@ async fn matrix(param: web::Query<QueryParams>) -> impl Responder {
     info!("收到matrix请求 pid:{}", param.pid.unwrap());
     // let body = serde_json::to_vec(&command::matrix(param.pid).unwrap()).unwrap();
     match param.pid {
-        Some(pid) => match command::matrix(pid) {
+        Some(pid) => match command::matrix(pid).await {
             Ok(matrix) => HttpResponse::Ok().json(matrix),  // 成功时返回 JSON 数据
             Err(e) => {
                 info!("error in matrix, pid: {:?}", param.pid);
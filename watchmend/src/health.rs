@@ -0,0 +1,221 @@
+use std::{
+    error::Error,
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+    process::Stdio,
+    time::Duration,
+};
+
+use regex::Regex;
+use tokio::{net::TcpStream, process::Command, time};
+use tracing::{info, warn};
+
+use crate::common::task::{HealthCheck, HealthCheckType, Readiness, ReadinessType, Task};
+use crate::global;
+
+/// Run the health check once, `Err` contains the reason of the failure
+pub async fn check(hc: &HealthCheck, dir: Option<&str>) -> Result<(), Box<dyn Error>> {
+    let timeout = Duration::from_secs(hc.timeout);
+    match time::timeout(timeout, probe(hc, dir)).await {
+        Ok(res) => res,
+        Err(_) => Err(format!("Health check timed out after {:?}", timeout).into()),
+    }
+}
+
+async fn probe(hc: &HealthCheck, dir: Option<&str>) -> Result<(), Box<dyn Error>> {
+    match hc.check_type {
+        HealthCheckType::Http => {
+            let url = hc.url.as_deref().unwrap_or_default();
+            let res = reqwest::Client::new().get(url).send().await?;
+            let status = res.status();
+            if status.is_success() || status.is_redirection() {
+                Ok(())
+            } else {
+                Err(format!("GET {} responded {}", url, status).into())
+            }
+        }
+        HealthCheckType::Tcp => {
+            TcpStream::connect(hc.address.as_deref().unwrap_or_default()).await?;
+            Ok(())
+        }
+        HealthCheckType::Exec => {
+            let mut command = Command::new(hc.command.as_deref().unwrap_or_default());
+            command
+                .args(&hc.args)
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                // 超时后丢弃 future 时结束检查命令
+                .kill_on_drop(true);
+            if let Some(dir) = dir {
+                command.current_dir(dir);
+            }
+            let status = command.status().await?;
+            if status.success() {
+                Ok(())
+            } else {
+                Err(format!("Health check command exited with {}", status).into())
+            }
+        }
+    }
+}
+
+/// Start a coroutine checking the health of the task process `pid`
+///
+/// The coroutine exits when the task no longer runs `pid`. After `retries`
+/// consecutive failures the task is marked unhealthy and, when `restart` is set,
+/// restarted through the auto restart path.
+pub fn watch(id: i64, pid: u32, hc: HealthCheck, dir: Option<String>) {
+    tokio::spawn(async move {
+        let started = time::Instant::now();
+        let start_period = Duration::from_secs(hc.start_period);
+        let mut healthy = false;
+        let mut failures = 0;
+        loop {
+            time::sleep(Duration::from_secs(hc.interval)).await;
+            let res = check(&hc, dir.as_deref()).await.map_err(|e| e.to_string());
+            let health = match res {
+                Ok(_) => {
+                    healthy = true;
+                    failures = 0;
+                    "healthy"
+                }
+                // 启动阶段的失败不计入重试次数
+                Err(_) if !healthy && started.elapsed() < start_period => continue,
+                Err(e) => {
+                    failures += 1;
+                    warn!(
+                        "Task [{}] health check failed ({}/{}): {}",
+                        id, failures, hc.retries, e
+                    );
+                    if failures < hc.retries {
+                        continue;
+                    }
+                    "unhealthy"
+                }
+            };
+            if !global::set_health(id, pid, health).await {
+                break;
+            }
+            if health == "unhealthy" && hc.restart {
+                info!("Restart unhealthy task: {}", id);
+                let reason = "health check failed".to_string();
+                if let Err(e) = global::restart_failed(id, pid, reason).await {
+                    warn!("Restart unhealthy task [{}] failed: {}", id, e);
+                }
+                break;
+            }
+        }
+    });
+}
+
+/// Readiness condition of a task process started just now
+pub struct ReadyProbe {
+    readiness: Readiness,
+    path: Option<String>,
+    logs: Vec<(String, u64)>,
+    regex: Option<Regex>,
+    buf: String,
+    deadline: time::Instant,
+}
+
+impl ReadyProbe {
+    pub fn new(readiness: &Readiness, task: &Task) -> Self {
+        let path = readiness.path.as_ref().map(|path| match &task.dir {
+            Some(dir) => Path::new(dir).join(path).to_string_lossy().to_string(),
+            None => path.clone(),
+        });
+        // 只匹配启动之后新写入的日志
+        let mut logs: Vec<(String, u64)> = [&task.stdout, &task.stderr]
+            .into_iter()
+            .flatten()
+            .filter(|f| !f.is_empty())
+            .map(|f| (f.clone(), std::fs::metadata(f).map(|m| m.len()).unwrap_or(0)))
+            .collect();
+        logs.dedup_by(|a, b| a.0 == b.0);
+        ReadyProbe {
+            readiness: readiness.clone(),
+            path,
+            logs,
+            regex: readiness.pattern.as_deref().and_then(|p| Regex::new(p).ok()),
+            buf: String::new(),
+            deadline: time::Instant::now() + Duration::from_secs(readiness.timeout),
+        }
+    }
+
+    /// Whether the condition holds now
+    pub async fn ready(&mut self) -> bool {
+        match self.readiness.check_type {
+            ReadinessType::Tcp => {
+                TcpStream::connect(self.readiness.address.as_deref().unwrap_or_default())
+                    .await
+                    .is_ok()
+            }
+            ReadinessType::File => self
+                .path
+                .as_ref()
+                .map(|p| Path::new(p).exists())
+                .unwrap_or(false),
+            ReadinessType::Log => match &self.regex {
+                Some(regex) => {
+                    for (file, offset) in self.logs.iter_mut() {
+                        read_new(file, offset, &mut self.buf);
+                    }
+                    self.buf.lines().any(|line| regex.is_match(line))
+                }
+                None => false,
+            },
+            ReadinessType::Timeout => self.expired(),
+        }
+    }
+
+    /// Whether the timeout is over
+    pub fn expired(&self) -> bool {
+        time::Instant::now() >= self.deadline
+    }
+}
+
+/// Start a coroutine waiting for the task process `pid` to be ready
+///
+/// The task is marked running when the condition holds. When it does not hold
+/// within `timeout`, the process is restarted like a crashed task.
+pub fn watch_ready(id: i64, pid: u32, readiness: Readiness, task: &Task) {
+    let mut probe = ReadyProbe::new(&readiness, task);
+    tokio::spawn(async move {
+        loop {
+            if probe.ready().await {
+                if let Err(e) = global::set_ready(id, pid).await {
+                    warn!("Set task [{}] ready failed: {}", id, e);
+                }
+                break;
+            }
+            if probe.expired() {
+                warn!(
+                    "Task [{}] is not ready after {}s, restart it",
+                    id, readiness.timeout
+                );
+                let reason = format!("not ready after {}s", readiness.timeout);
+                if let Err(e) = global::restart_failed(id, pid, reason).await {
+                    warn!("Restart task [{}] failed: {}", id, e);
+                }
+                break;
+            }
+            // 进程已退出或被停止
+            if !global::is_managed(id, pid).await {
+                break;
+            }
+            time::sleep(Duration::from_millis(200)).await;
+        }
+    });
+}
+
+/// Append content written to `file` after `offset` to `buf`
+fn read_new(file: &str, offset: &mut u64, buf: &mut String) {
+    if let Ok(mut f) = std::fs::File::open(file) {
+        let mut content = Vec::new();
+        if f.seek(SeekFrom::Start(*offset)).is_ok() && f.read_to_end(&mut content).is_ok() {
+            *offset += content.len() as u64;
+            buf.push_str(&String::from_utf8_lossy(&content));
+        }
+    }
+}
@@ -0,0 +1,44 @@
+use std::{error::Error, time::Duration};
+
+use tokio::time;
+use tracing::{info, warn};
+
+use crate::common::task::{Hook, StartOptions, Task};
+use crate::process;
+
+/// Run the `name` hook of the task and wait for it, `Err` contains the reason of the failure
+pub async fn run(
+    task: &Task,
+    name: &str,
+    hook: &Hook,
+    opts: &StartOptions,
+) -> Result<(), Box<dyn Error>> {
+    info!("Run {} hook of task [{}]: {}", name, task.id, hook.command);
+    let mut child = task.spawn_hook(hook, opts)?;
+    let timeout = Duration::from_secs(hook.timeout);
+    match time::timeout(timeout, child.wait()).await {
+        Ok(status) => {
+            let status = status?;
+            if status.success() {
+                Ok(())
+            } else {
+                Err(format!("{} hook exited with {}", name, status).into())
+            }
+        }
+        Err(_) => {
+            // 钩子命令可能启动了子进程，结束整个进程组
+            if let Some(pid) = child.id() {
+                process::signal_pgid(pid, libc::SIGKILL)?;
+            }
+            child.wait().await?;
+            Err(format!("{} hook timed out after {:?}", name, timeout).into())
+        }
+    }
+}
+
+/// Run the `name` hook of the task, a failure is only logged
+pub async fn run_logged(task: &Task, name: &str, hook: &Hook, opts: &StartOptions) {
+    if let Err(e) = run(task, name, hook, opts).await {
+        warn!("Task [{}] {} hook failed: {}", task.id, name, e);
+    }
+}
@@ -1,35 +1,55 @@
+pub mod cgroup;
 pub mod command;
 pub mod common {
     include!("../../common.rs");
 }
 pub mod engine;
+pub mod health;
+pub mod hook;
+pub mod logrotate;
 pub mod monitor;
+pub mod notify;
+pub mod process;
 pub mod utils;
 pub mod scheduled_task;
+pub mod watch;
+pub mod watchdog;
 
 pub mod global {
 
     use std::{
-        collections::HashMap,
+        collections::{BTreeMap, HashMap},
         error::Error,
         path::Path,
-        process::Stdio,
-        time::{SystemTime, UNIX_EPOCH},
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            OnceLock,
+        },
+        time::{Duration, SystemTime, UNIX_EPOCH},
     };
     
     use crate::common::{
-        config::{get_with_home, get_with_home_path},
+        config::{get_with_home, get_with_home_path, NotifyEvent},
+        credential::Credential,
         handle::{Data, Response, Status},
-        task::{AsyncTask, Task, TaskFlag, TaskType},
+        task::{
+            AsyncTask, ConcurrencyPolicy, InheritEnv, RestartPolicy, StartOptions, Task,
+            TaskFlag, TaskType,
+        },
     };
+    use crate::cgroup;
+    use crate::health;
+    use crate::hook;
+    use crate::notify;
+    use crate::process::{self, DEFAULT_STOP_SIGNAL, DEFAULT_STOP_TIMEOUT};
     use lazy_static::lazy_static;
-    use log::info;
+    use log::{info, warn};
     use regex::Regex;
     
     use sysinfo::Pid;
     use tokio::{
         io::AsyncWriteExt,
-        process::{Child, Command},
+        process::Child,
         sync::{mpsc, RwLock},
         task::JoinHandle,
     };
@@ -40,8 +60,77 @@ pub mod global {
         task: Task,
         joinhandle: Option<JoinHandle<Option<i32>>>,
         tx: Option<mpsc::Sender<Vec<u8>>>,
+        /// Executing runs of the periodic task, oldest first
+        runs: Vec<RunProcess>,
+        /// Processes of the task with `instances`, keyed by instance number
+        instances: BTreeMap<u32, TaskProcess>,
     }
 
+    impl TaskProcess {
+        fn new(task: Task) -> Self {
+            TaskProcess {
+                task,
+                joinhandle: None,
+                tx: None,
+                runs: Vec::new(),
+                instances: BTreeMap::new(),
+            }
+        }
+
+        /// The task itself or its instance running process `pid`
+        fn by_pid(&self, pid: u32) -> &TaskProcess {
+            self.instances
+                .values()
+                .find(|it| it.task.pid == Some(pid))
+                .unwrap_or(self)
+        }
+
+        fn by_pid_mut(&mut self, pid: u32) -> &mut TaskProcess {
+            match self.instances.iter().find(|(_, it)| it.task.pid == Some(pid)) {
+                Some((i, _)) => {
+                    let i = *i;
+                    self.instances.get_mut(&i).unwrap()
+                }
+                None => self,
+            }
+        }
+
+        /// Process `pid` is run by the task, one of its instances or periodic runs,
+        /// and the pid was not reused
+        fn owns(&self, pid: u32) -> bool {
+            let tp = self.by_pid(pid);
+            if tp.task.pid == Some(pid) {
+                return !process::is_replaced(pid, tp.task.pid_start_time);
+            }
+            self.runs
+                .iter()
+                .any(|run| run.pid == pid && !process::is_replaced(pid, run.starttime))
+        }
+
+        /// Status of the task with its instances
+        fn status(&self) -> Status {
+            let mut status: Status = self.task.clone().into();
+            status.instances = self
+                .instances
+                .values()
+                .map(|it| it.task.clone().into())
+                .collect();
+            status
+        }
+    }
+
+    /// One execution of a periodic task
+    struct RunProcess {
+        pid: u32,
+        starttime: Option<u64>,
+        joinhandle: JoinHandle<Option<i32>>,
+    }
+
+    static SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+
+    // 未配置 inherit_env 的任务继承的守护进程环境变量
+    static INHERIT_ENV: OnceLock<InheritEnv> = OnceLock::new();
+
     lazy_static! {
         static ref CACHE: RwLock<Option<String>> = RwLock::new(None);
         static ref TASKS: RwLock<HashMap<i64, TaskProcess>> = RwLock::new(HashMap::new());
@@ -79,28 +168,404 @@ pub mod global {
     }
 
     pub async fn cache() -> Result<(), Box<dyn Error>> {
+        // 守护进程退出时已写入最终缓存，之后的状态变化不再写入
+        if SHUTTING_DOWN.load(Ordering::SeqCst) {
+            return Ok(());
+        }
         // 启动协程写入缓存文件，避免阻塞对其他任务的操作
         tokio::spawn(async move {
-            let path_mutex = CACHE.read().await;
-            let path = path_mutex.clone();
-            drop(path_mutex); // 释放锁，避免阻塞对其他任务的操作
-            if let Some(path) = path {
-                let path = get_with_home(path.as_str());
-                let path = Path::new(path.as_str());
-                let parent = path.parent().unwrap();
-                if !parent.exists() {
-                    std::fs::create_dir_all(parent).unwrap();
+            write_cache().await;
+        });
+        Ok(())
+    }
+
+    async fn write_cache() {
+        let path_mutex = CACHE.read().await;
+        let path = path_mutex.clone();
+        drop(path_mutex); // 释放锁，避免阻塞对其他任务的操作
+        if let Some(path) = path {
+            let path = get_with_home(path.as_str());
+            let path = Path::new(path.as_str());
+            let parent = path.parent().unwrap();
+            if !parent.exists() {
+                std::fs::create_dir_all(parent).unwrap();
+            }
+            let tasks = TASKS.read().await;
+            // 实例紧跟在所属任务之后写入，加载时用来接管仍在运行的实例进程
+            let tasks_cache: Vec<Task> = tasks
+                .values()
+                .flat_map(|tp| {
+                    std::iter::once(tp.task.clone())
+                        .chain(tp.instances.values().map(|it| it.task.clone()))
+                })
+                .collect();
+            drop(tasks); // 释放锁，避免阻塞对其他任务的操作
+            let tasks_cache_str = serde_json::to_string(&tasks_cache).unwrap();
+            tokio::fs::write(path, tasks_cache_str).await.unwrap();
+        }
+    }
+
+    /// Stop all running tasks before the daemon exits
+    ///
+    /// Tasks are stopped in reverse dependency order. The cache is written before
+    /// stopping, so tasks running now are started again when the daemon loads the cache.
+    pub async fn shutdown() -> Result<(), Box<dyn Error>> {
+        write_cache().await;
+        SHUTTING_DOWN.store(true, Ordering::SeqCst);
+
+        let tasks = TASKS.read().await;
+        let ids: Vec<i64> = tasks.keys().copied().collect();
+        let mut order = dependency_order(&tasks, &ids)?;
+        drop(tasks);
+        order.reverse();
+        for id in order {
+            if is_stoppable(id).await {
+                info!("Stop task [{}] before exit", id);
+                if let Err(e) = stop_task(flag(id), false).await {
+                    info!("Stop task [{}] failed: {}", id, e);
                 }
-                let tasks = TASKS.read().await;
-                let tasks_cache: Vec<Task> = tasks.values().map(|tp| tp.task.clone()).collect();
-                drop(tasks); // 释放锁，避免阻塞对其他任务的操作
-                let tasks_cache_str = serde_json::to_string(&tasks_cache).unwrap();
-                tokio::fs::write(path, tasks_cache_str).await.unwrap();
             }
-        });
+        }
+        Ok(())
+    }
+
+    fn flag(id: i64) -> TaskFlag {
+        TaskFlag {
+            id,
+            name: None,
+            group: None,
+            mat: false,
+        }
+    }
+
+    /// Find ids of tasks matched by task flag: id, name (regex when `mat`) or group
+    async fn resolve(tf: &TaskFlag) -> Result<Vec<i64>, Box<dyn Error>> {
+        if tf.id > 0 {
+            return Ok(vec![tf.id]);
+        }
+        let tasks = TASKS.read().await;
+        let mut ids: Vec<i64> = if let Some(name) = tf.name.as_ref().filter(|n| !n.is_empty()) {
+            if tf.mat {
+                let regex: Regex = Regex::new(name)?;
+                tasks
+                    .iter()
+                    .filter(|(_, tp)| regex.is_match(&tp.task.name))
+                    .map(|(id, _)| *id)
+                    .collect()
+            } else {
+                tasks
+                    .iter()
+                    .filter(|(_, tp)| &tp.task.name == name)
+                    .map(|(id, _)| *id)
+                    .collect()
+            }
+        } else if let Some(group) = &tf.group {
+            tasks
+                .iter()
+                .filter(|(_, tp)| tp.task.group.as_ref() == Some(group))
+                .map(|(id, _)| *id)
+                .collect()
+        } else {
+            return Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "Task id or name or group is required",
+            )));
+        };
+        if ids.is_empty() {
+            return Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                format!(
+                    "Task [{}:{}] not exists",
+                    tf.name.clone().unwrap_or_default(),
+                    tf.group.clone().unwrap_or_default()
+                ),
+            )));
+        }
+        ids.sort();
+        Ok(ids)
+    }
+
+    /// Order tasks so that every task comes after the tasks it depends on,
+    /// including dependencies not in `ids`, removed tasks are skipped
+    fn dependency_order(
+        tasks: &HashMap<i64, TaskProcess>,
+        ids: &[i64],
+    ) -> Result<Vec<i64>, Box<dyn Error>> {
+        fn visit(
+            tasks: &HashMap<i64, TaskProcess>,
+            id: i64,
+            visiting: &mut Vec<i64>,
+            order: &mut Vec<i64>,
+        ) -> Result<(), Box<dyn Error>> {
+            if order.contains(&id) {
+                return Ok(());
+            }
+            if visiting.contains(&id) {
+                return Err(Box::new(std::io::Error::new(
+                    std::io::ErrorKind::Other,
+                    format!("Task dependency cycle: {:?}", visiting),
+                )));
+            }
+            // 依赖的任务可能已被移除
+            let tp = match tasks.get(&id) {
+                Some(tp) => tp,
+                None => return Ok(()),
+            };
+            visiting.push(id);
+            for dep in &tp.task.depends_on {
+                visit(tasks, *dep, visiting, order)?;
+            }
+            visiting.pop();
+            order.push(id);
+            Ok(())
+        }
+
+        let mut order = Vec::new();
+        for id in ids {
+            visit(tasks, *id, &mut Vec::new(), &mut order)?;
+        }
+        Ok(order)
+    }
+
+    /// Task process `pid` is alive and managed by the task
+    fn is_current(tp: &TaskProcess, pid: u32) -> bool {
+        tp.task.pid == Some(pid)
+            && (tp.task.status == Some("running".to_string())
+                || tp.task.status == Some("starting".to_string()))
+    }
+
+    /// Mark the task whose process could not be stopped as errored, so it is never
+    /// left stopping without an exit watcher
+    fn stop_failed(task: &mut Task, e: &str) {
+        warn!("Stop task [{}] failed: {}", task.id, e);
+        task.status = Some("errored".to_string());
+        task.pid = None;
+        task.health = None;
+        task.restart_reason = Some(format!("stop failed: {}", e));
+    }
+
+    /// Task is still running process `pid`
+    pub async fn is_managed(id: i64, pid: u32) -> bool {
+        let tasks = TASKS.read().await;
+        tasks
+            .get(&id)
+            .map(|tp| is_current(tp.by_pid(pid), pid))
+            .unwrap_or(false)
+    }
+
+    /// Record health of the task, `false` when the task no longer runs `pid`
+    pub async fn set_health(id: i64, pid: u32, health: &str) -> bool {
+        let mut tasks = TASKS.write().await;
+        match tasks.get_mut(&id).map(|tp| tp.by_pid_mut(pid)) {
+            Some(tp) if is_current(tp, pid) => {
+                tp.task.health = Some(health.to_string());
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Mark the task running once process `pid` is ready
+    pub async fn set_ready(id: i64, pid: u32) -> Result<(), Box<dyn Error>> {
+        let mut tasks = TASKS.write().await;
+        if let Some(tp) = tasks.get_mut(&id).map(|tp| tp.by_pid_mut(pid)) {
+            if tp.task.pid == Some(pid) && tp.task.status == Some("starting".to_string()) {
+                info!("Task [{}] is ready", id);
+                tp.task.status = Some("running".to_string());
+                drop(tasks);
+                cache().await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Record the next fire time of the cron task
+    pub async fn set_next_run(id: i64, next_run: Option<i64>) -> Result<(), Box<dyn Error>> {
+        let mut tasks = TASKS.write().await;
+        if let Some(tp) = tasks.get_mut(&id) {
+            if let TaskType::Scheduled(tt) = &mut tp.task.task_type {
+                if tt.next_run != next_run {
+                    tt.next_run = next_run;
+                    drop(tasks);
+                    cache().await?;
+                }
+            }
+        }
         Ok(())
     }
 
+    /// Stop the failing (unhealthy, never ready or over resource limits) process `pid`
+    /// of the task and restart it like a crashed task
+    ///
+    /// # params
+    ///
+    /// - `id`: task id
+    /// - `pid`: process id checked, nothing is done when the task no longer runs it
+    /// - `reason`: reason of the restart shown in task list
+    pub async fn restart_failed(id: i64, pid: u32, reason: String) -> Result<(), Box<dyn Error>> {
+        let mut tasks = TASKS.write().await;
+        let tp = match tasks.get_mut(&id).map(|tp| tp.by_pid_mut(pid)) {
+            Some(tp) if is_current(tp, pid) => tp,
+            _ => return Ok(()),
+        };
+        info!("Restart task [{}]: {}", id, reason);
+        let instance = tp.task.instance;
+        tp.task.restart_reason = Some(reason);
+        let sig = process::parse_signal(
+            tp.task
+                .stop_signal
+                .as_deref()
+                .unwrap_or(DEFAULT_STOP_SIGNAL),
+        )?;
+        let timeout = Duration::from_secs(tp.task.stop_timeout.unwrap_or(DEFAULT_STOP_TIMEOUT));
+        let sweep = tp.task.kill_descendants;
+        let starttime = tp.task.pid_start_time;
+
+        // 与 stop 相同，stopping 状态下退出监控协程不会修改任务状态
+        tp.task.status = Some("stopping".to_string());
+        let jh = tp.joinhandle.take();
+        tp.tx = None;
+        drop(tasks);
+
+        let code = match process::terminate(pid, starttime, sig, timeout, jh, sweep)
+            .await
+            .map_err(|e| e.to_string())
+        {
+            Ok(code) => code,
+            Err(e) => {
+                let mut tasks = TASKS.write().await;
+                if let Some(tp) = tasks.get_mut(&id).map(|tp| tp.by_pid_mut(pid)) {
+                    if tp.task.pid == Some(pid) {
+                        stop_failed(&mut tp.task, &e);
+                    }
+                }
+                drop(tasks);
+                cache().await?;
+                return Err(Box::new(std::io::Error::new(std::io::ErrorKind::Other, e)));
+            }
+        };
+        info!("Failed task [{}] stopped with code: {:?}", id, code);
+
+        // 走自动重启流程，遵循退避时间和最大重启次数
+        update_instance(
+            id,
+            instance,
+            Some(None),
+            Some(Some("auto restart".to_string())),
+            Some(code),
+            Some(true),
+            Some(vec!["stopping"]),
+        )
+        .await?;
+        cache().await
+    }
+
+    /// Restart the async task because files it watches changed, a stopped task is not started
+    pub async fn restart_changed(id: i64) -> Result<(), Box<dyn Error>> {
+        let is_async = match TASKS.read().await.get(&id) {
+            Some(tp) => matches!(tp.task.task_type, TaskType::Async(_)),
+            None => false,
+        };
+        if !is_async || !is_stoppable(id).await {
+            return Ok(());
+        }
+        info!("Restart task [{}]: restarted by file change", id);
+        let res = restart(flag(id)).await?;
+        if !res.is_success() {
+            return Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                res.msg,
+            )));
+        }
+        let mut tasks = TASKS.write().await;
+        if let Some(tp) = tasks.get_mut(&id) {
+            let reason = Some("restarted by file change".to_string());
+            tp.task.restart_reason = reason.clone();
+            for it in tp.instances.values_mut() {
+                it.task.restart_reason = reason.clone();
+            }
+        }
+        drop(tasks);
+        cache().await
+    }
+
+    /// Record reason of the automatic restart when the task still runs `pid`
+    async fn set_restart_reason(id: i64, pid: Option<u32>, reason: String) {
+        let mut tasks = TASKS.write().await;
+        if let (Some(tp), Some(pid)) = (tasks.get_mut(&id), pid) {
+            let tp = tp.by_pid_mut(pid);
+            // 已被 stop 或 restart_failed 接管时不覆盖原因
+            if is_current(tp, pid) {
+                tp.task.restart_reason = Some(reason);
+            }
+        }
+    }
+
+    /// Dependencies of the task must exist and must not form a cycle
+    fn check_depends(
+        tasks: &HashMap<i64, TaskProcess>,
+        task: &Task,
+    ) -> Result<(), Box<dyn Error>> {
+        for dep in &task.depends_on {
+            if *dep == task.id {
+                return Err(Box::new(std::io::Error::new(
+                    std::io::ErrorKind::Other,
+                    format!("Task [{}] depends on itself", task.id),
+                )));
+            }
+            if !tasks.contains_key(dep) {
+                return Err(Box::new(std::io::Error::new(
+                    std::io::ErrorKind::Other,
+                    format!("Task [{}] depends on unknown task [{}]", task.id, dep),
+                )));
+            }
+        }
+        if dependency_order(tasks, &task.depends_on)?.contains(&task.id) {
+            return Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                format!("Task [{}] dependency cycle", task.id),
+            )));
+        }
+        Ok(())
+    }
+
+    async fn is_stoppable(id: i64) -> bool {
+        let tasks = TASKS.read().await;
+        match tasks.get(&id) {
+            Some(tp) => {
+                tp.task.status == Some("running".to_string())
+                    || tp.task.status == Some("starting".to_string())
+                    || tp.task.status == Some("auto restart".to_string())
+            }
+            None => false,
+        }
+    }
+
+    /// Wait until the task is running
+    async fn wait_running(id: i64, timeout: u64) -> Result<(), Box<dyn Error>> {
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(timeout);
+        loop {
+            let tasks = TASKS.read().await;
+            let status = tasks.get(&id).and_then(|tp| tp.task.status.clone());
+            drop(tasks);
+            if status == Some("running".to_string()) {
+                return Ok(());
+            }
+            if tokio::time::Instant::now() >= deadline {
+                return Err(Box::new(std::io::Error::new(
+                    std::io::ErrorKind::Other,
+                    format!(
+                        "Dependency [{}] is not running after {}s, status: {}",
+                        id,
+                        timeout,
+                        status.unwrap_or_default()
+                    ),
+                )));
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+
     pub async fn load(path: &str) -> Result<(), Box<dyn Error>> {
         let path = get_with_home(path);
         let path = Path::new(path.as_str());
@@ -115,91 +580,790 @@ pub mod global {
         let tasks_cache: Vec<Task> = serde_json::from_str(&std::fs::read_to_string(path).unwrap())?;
         let mut tasks = TASKS.write().await;
         for task in tasks_cache {
-            let mut tp = TaskProcess {
-                task: task.clone(),
-                joinhandle: None,
-                tx: None,
+            match task.instance {
+                Some(i) => {
+                    if let Some(tp) = tasks.get_mut(&task.id) {
+                        tp.instances.insert(i, TaskProcess::new(task));
+                    }
+                }
+                None => {
+                    tasks.insert(task.id, TaskProcess::new(task));
+                }
+            }
+        }
+        let ids: Vec<i64> = tasks.keys().copied().collect();
+        let order = dependency_order(&tasks, &ids)?;
+        drop(tasks);
+
+        // 按照依赖顺序启动任务，启动失败的任务标记为 errored，不影响其他任务
+        for id in order {
+            if let Err(e) = load_task(id).await.map_err(|e| e.to_string()) {
+                warn!("Start task [{}] failed: {}", id, e);
+                if let Some(tp) = TASKS.write().await.get_mut(&id) {
+                    tp.task.status = Some("errored".to_string());
+                    tp.task.pid = None;
+                    tp.task.restart_reason = Some(format!("start failed: {}", e));
+                }
+            }
+        }
+
+        // 按照错过执行策略处理守护进程停止期间错过的定时任务和周期任务
+        let mut tasks = TASKS.write().await;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Failed to get timestamp")
+            .as_secs() as i64;
+        let mut catch_ups = Vec::new();
+        for (id, tp) in tasks.iter_mut() {
+            // 上次执行中的进程已无法监控，恢复为等待执行
+            match (&tp.task.task_type, tp.task.status.as_deref()) {
+                (TaskType::Scheduled(_), Some("processing")) => {
+                    tp.task.status = Some("waiting".to_string());
+                }
+                (TaskType::Periodic(_), Some("executing")) => {
+                    tp.task.status = Some("interval".to_string());
+                }
+                _ => {}
+            }
+            let runs = crate::monitor::misfire(&mut tp.task, now);
+            if runs > 0 {
+                catch_ups.push((*id, runs));
+            }
+        }
+        drop(tasks);
+        cache().await?;
+        for (id, runs) in catch_ups {
+            catch_up(id, runs);
+        }
+        Ok(())
+    }
+
+    /// Adopt or start again the async task loaded from the cache, which was running
+    /// when the daemon exited
+    ///
+    /// The process is spawned without holding `TASKS`.
+    async fn load_task(id: i64) -> Result<(), Box<dyn Error>> {
+        let mut tasks = TASKS.write().await;
+        let tp = match tasks.get_mut(&id) {
+            Some(tp) => tp,
+            None => return Ok(()),
+        };
+        // 上次运行状态为 running 的常驻任务加载后直接启动，
+        // always 策略的任务即使被手动停止也重新启动
+        let status = tp.task.status.clone().unwrap_or_default();
+        let policy = tp.task.restart_policy();
+        if !(status == "running"
+            || status == "starting"
+            || (policy == RestartPolicy::Always && status == "stopped"))
+        {
+            return Ok(());
+        }
+        if !matches!(tp.task.task_type, TaskType::Async(_)) {
+            return Ok(());
+        }
+        if tp.task.instances.is_some() {
+            drop(tasks);
+            return load_instances(id).await;
+        }
+        // 守护进程异常退出时子进程可能仍在运行，确认是同一个进程后直接接管
+        let running = adoptable(&tp.task);
+        if let Some((pid, starttime)) = running.filter(|_| tp.task.log_rotate.is_none()) {
+            info!("Adopt running process {} of task [{}]", pid, id);
+            watch_adopted(tp, pid, starttime);
+            return Ok(());
+        }
+        if let TaskType::Async(tt) = &mut tp.task.task_type {
+            tt.has_restart = 0;
+            tt.next_restart_at = 0;
+        }
+        let task = tp.task.clone();
+        drop(tasks);
+
+        if let Some((pid, starttime)) = running {
+            stop_unadoptable(&task, pid, starttime).await?;
+        }
+        let child = spawn(&task).await?;
+        match TASKS.write().await.get_mut(&id) {
+            Some(tp) => watch_async(tp, child, false),
+            None => discard(child),
+        }
+        Ok(())
+    }
+
+    /// Process `(pid, start time)` of the cached task which was running and still runs
+    fn adoptable(task: &Task) -> Option<(u32, u64)> {
+        if !matches!(task.status.as_deref(), Some("running") | Some("starting")) {
+            return None;
+        }
+        let (pid, starttime) = (task.pid?, task.pid_start_time?);
+        if process::is_same(pid, starttime, &task.command, &task.args) {
+            Some((pid, starttime))
+        } else {
+            None
+        }
+    }
+
+    /// Stop the still running process of a task with `log_rotate` before starting it
+    /// again
+    ///
+    /// Its output was piped to the exited daemon, the process would be killed by
+    /// SIGPIPE on its next write, so it is never adopted.
+    async fn stop_unadoptable(task: &Task, pid: u32, starttime: u64) -> Result<(), Box<dyn Error>> {
+        let sig =
+            process::parse_signal(task.stop_signal.as_deref().unwrap_or(DEFAULT_STOP_SIGNAL))?;
+        let timeout = Duration::from_secs(task.stop_timeout.unwrap_or(DEFAULT_STOP_TIMEOUT));
+        info!(
+            "Stop process {} of task [{}] writing to closed log pipes",
+            pid, task.id
+        );
+        process::terminate(
+            pid,
+            Some(starttime),
+            sig,
+            timeout,
+            None,
+            task.kill_descendants,
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Adopt or start the instances of the task loaded from the cache, without
+    /// holding `TASKS` while spawning
+    async fn load_instances(id: i64) -> Result<(), Box<dyn Error>> {
+        let (task, running) = match TASKS.read().await.get(&id) {
+            Some(tp) => {
+                let running: HashMap<u32, (u32, u64)> = tp
+                    .instances
+                    .iter()
+                    .filter_map(|(i, it)| adoptable(&it.task).map(|p| (*i, p)))
+                    .collect();
+                (tp.task.clone(), running)
+            }
+            None => return Ok(()),
+        };
+        let count = task.instances.unwrap_or(0);
+        for i in 0..count {
+            let running = running.get(&i).copied();
+            if let Some((pid, starttime)) = running.filter(|_| task.log_rotate.is_none()) {
+                if let Some(it) = TASKS
+                    .write()
+                    .await
+                    .get_mut(&id)
+                    .and_then(|tp| tp.instances.get_mut(&i))
+                {
+                    info!(
+                        "Adopt running process {} of task [{}] instance [{}]",
+                        pid, id, i
+                    );
+                    watch_adopted(it, pid, starttime);
+                }
+                continue;
+            }
+            let instance = task
+                .instance(i)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+            if let Some((pid, starttime)) = running {
+                stop_unadoptable(&instance, pid, starttime).await?;
+            }
+            let child = spawn(&instance).await?;
+            match TASKS.write().await.get_mut(&id) {
+                Some(tp) => {
+                    let mut it = TaskProcess::new(instance);
+                    watch_async(&mut it, child, false);
+                    tp.instances.insert(i, it);
+                }
+                None => {
+                    discard(child);
+                    return Ok(());
+                }
+            }
+        }
+        if let Some(tp) = TASKS.write().await.get_mut(&id) {
+            // 缩容前缓存的多余实例
+            tp.instances.retain(|i, _| *i < count);
+            tp.task.status = Some("running".to_string());
+            tp.task.pid = None;
+            tp.task.restart_reason = None;
+            if let TaskType::Async(tt) = &mut tp.task.task_type {
+                tt.stopped_by_user = false;
+            }
+        }
+        Ok(())
+    }
+
+    /// Kill the process spawned for a task removed in the meantime
+    fn discard(mut child: Child) {
+        if let Some(pid) = child.id() {
+            let _ = process::signal_pgid(pid, libc::SIGKILL);
+        }
+        tokio::spawn(async move {
+            let _ = child.wait().await;
+        });
+    }
+
+    /// Run the scheduled or periodic task `runs` times one after another
+    fn catch_up(id: i64, runs: usize) {
+        tokio::spawn(async move {
+            for run in 1..=runs {
+                // 等待上一次执行结束
+                loop {
+                    tokio::time::sleep(Duration::from_millis(500)).await;
+                    let tasks = TASKS.read().await;
+                    match tasks.get(&id).and_then(|tp| tp.task.status.clone()) {
+                        Some(status) if status == "waiting" || status == "interval" => break,
+                        Some(status) if status == "processing" || status == "executing" => {}
+                        // 任务被删除或暂停时不再补执行
+                        _ => return,
+                    }
+                }
+                info!("Run missed task [{}] ({}/{})", id, run, runs);
+                if let Err(e) = start(flag(id)).await.map_err(|e| e.to_string()) {
+                    warn!("Run missed task [{}] failed: {}", id, e);
+                    return;
+                }
+            }
+        });
+    }
+
+    /// Instances must be of an async task, at least one, with valid templates
+    fn check_instances(task: &Task, instances: u32) -> Result<(), Box<dyn Error>> {
+        if !matches!(task.task_type, TaskType::Async(_)) {
+            return Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                format!("Task [{}] instances requires an async task", task.id),
+            )));
+        }
+        if instances == 0 {
+            return Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                format!("Task [{}] instances must be at least 1", task.id),
+            )));
+        }
+        task.instance(0)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+        Ok(())
+    }
+
+    /// Start the instances of the task with `instances` that are not running, without
+    /// holding `TASKS` while spawning, instances share the cgroup of the task
+    ///
+    /// Every instance is tried, the first error is returned after all of them.
+    async fn start_instances(id: i64) -> Result<(), Box<dyn Error>> {
+        let mut tasks = TASKS.write().await;
+        let tp = match tasks.get_mut(&id) {
+            Some(tp) => tp,
+            None => return Ok(()),
+        };
+        // 先以 starting 状态占位，同时启动的请求不会重复启动同一个实例
+        let mut pending = Vec::new();
+        for i in 0..tp.task.instances.unwrap_or(0) {
+            if let Some(it) = tp.instances.get(&i) {
+                if it.task.status == Some("running".to_string())
+                    || it.task.status == Some("starting".to_string())
+                    || it.task.status == Some("auto restart".to_string())
+                {
+                    continue;
+                }
+            }
+            let task = tp
+                .task
+                .instance(i)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+            let mut it = TaskProcess::new(task.clone());
+            it.task.status = Some("starting".to_string());
+            tp.instances.insert(i, it);
+            pending.push((i, task));
+        }
+        tp.task.status = Some("running".to_string());
+        tp.task.pid = None;
+        tp.task.restart_reason = None;
+        if let TaskType::Async(tt) = &mut tp.task.task_type {
+            tt.stopped_by_user = false;
+        }
+        drop(tasks);
+
+        let mut res = Ok(());
+        for (i, task) in pending {
+            let spawned = spawn(&task).await.map_err(|e| e.to_string());
+            let mut tasks = TASKS.write().await;
+            // 启动期间实例可能已被停止或缩容移除
+            let it = tasks
+                .get_mut(&id)
+                .and_then(|tp| tp.instances.get_mut(&i))
+                .filter(|it| {
+                    it.task.pid.is_none() && it.task.status == Some("starting".to_string())
+                });
+            match (spawned, it) {
+                (Ok(child), Some(it)) => watch_async(it, child, false),
+                (Ok(child), None) => discard(child),
+                (Err(e), it) => {
+                    warn!("Start task [{}] instance [{}] failed: {}", id, i, e);
+                    if let Some(it) = it {
+                        it.task.status = Some("errored".to_string());
+                        it.task.restart_reason = Some(format!("start failed: {}", e));
+                    }
+                    if res.is_ok() {
+                        res = Err(e);
+                    }
+                }
+            }
+        }
+        res.map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e).into())
+    }
+
+    /// Stop `instances` of the task, `remove` drops them from the task (scale down)
+    async fn stop_instances(
+        id: i64,
+        instances: Vec<u32>,
+        remove: bool,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut tasks = TASKS.write().await;
+        let tp = match tasks.get_mut(&id) {
+            Some(tp) => tp,
+            None => return Ok(()),
+        };
+        let mut stops = Vec::new();
+        for i in &instances {
+            let it = match tp.instances.get_mut(i) {
+                Some(it) => it,
+                None => continue,
             };
-            match &tp.task.task_type {
-                TaskType::Async(tt) => {
-                    // 上次运行状态为 running 的染污加载后直接启动
-                    if tp.task.status == Some("running".to_string()) {
-                        let child = tp.task.start().await?;
-
-                        // 配置了 stdin 时，启动一个协程用于向子进程 stdin 写入数据
-                        let rx = if Some(true) == tp.task.stdin {
-                            let (tx, rx) = mpsc::channel::<Vec<u8>>(CHANNEL_SIZE);
-                            tp.tx = Some(tx);
-                            Some(rx)
-                        } else {
-                            None
-                        };
-
-                        // 更新任务状态等数据
-                        tp.task.pid = child.id();
-                        tp.task.status = Some("running".to_string());
-                        let now: u64 = SystemTime::now()
-                            .duration_since(UNIX_EPOCH)
-                            .expect("Failed to get timestamp")
-                            .as_secs();
-
-                        tp.task.task_type = TaskType::Async(AsyncTask {
-                            max_restart: tt.max_restart,
-                            has_restart: 0,
-                            started_at: now,
-                            stopped_at: 0,
-                        });
-
-                        // 启动协程等待子进程退出
-                        let jh: JoinHandle<Option<i32>> = tokio::spawn(async move {
-                            let mut child = child;
-
-                            // 接收到数据时写入子进程 stdin
-                            let cjh = if let Some(mut rx) = rx {
-                                let mut child_stdin = child.stdin.take().unwrap();
-                                // let mut stdin_writer = tokio::io::BufWriter::new(child_stdin);
-                                let cjh = tokio::spawn(async move {
-                                    while let Some(message) = rx.recv().await {
-                                        child_stdin.write_all(&message).await.unwrap();
-                                        child_stdin.flush().await.unwrap();
-                                    }
-                                });
-                                Some(cjh)
-                            } else {
-                                None
-                            };
-
-                            // 等待子进程退出
-                            let res = child.wait().await.unwrap();
-
-                            update(
-                                task.id,
-                                Some(None),
-                                Some(Some("stopped".to_string())),
-                                Some(res.code()),
-                                None,
-                                None,
-                            )
-                            .await
-                            .unwrap();
-
-                            // 等待 stdin 写入协程退出
-                            if let Some(cjh) = cjh {
-                                cjh.await.unwrap();
+            // 与 stop 相同，stopping 状态下退出监控协程不会修改实例状态
+            it.task.status = Some("stopping".to_string());
+            if let TaskType::Async(tt) = &mut it.task.task_type {
+                tt.stopped_by_user = true;
+            }
+            it.tx = None;
+            if let Some(pid) = it.task.pid {
+                let sig = process::parse_signal(
+                    it.task
+                        .stop_signal
+                        .as_deref()
+                        .unwrap_or(DEFAULT_STOP_SIGNAL),
+                )?;
+                let timeout =
+                    Duration::from_secs(it.task.stop_timeout.unwrap_or(DEFAULT_STOP_TIMEOUT));
+                let starttime = it.task.pid_start_time;
+                let sweep = it.task.kill_descendants;
+                let jh = it.joinhandle.take();
+                let i = *i;
+                stops.push(tokio::spawn(async move {
+                    let code = process::terminate(pid, starttime, sig, timeout, jh, sweep)
+                        .await
+                        .map_err(|e| e.to_string());
+                    (i, code)
+                }));
+            }
+        }
+        if remove {
+            for i in &instances {
+                tp.instances.remove(i);
+            }
+        }
+        drop(tasks); // 释放锁，同时等待各个实例退出
+
+        // 等待全部实例停止后再更新状态，某个实例停止失败不影响其他实例
+        let mut codes = HashMap::new();
+        for stop in stops {
+            let (i, code) = match stop.await {
+                Ok(res) => res,
+                Err(e) => {
+                    warn!("Wait task [{}] instance stopping failed: {}", id, e);
+                    continue;
+                }
+            };
+            if let Ok(code) = &code {
+                info!(
+                    "Task [{}] instance [{}] stopped with code: {:?}",
+                    id, i, code
+                );
+            }
+            codes.insert(i, code);
+        }
+
+        let mut tasks = TASKS.write().await;
+        let mut res = Ok(());
+        if let Some(tp) = tasks.get_mut(&id) {
+            for i in &instances {
+                if let Some(it) = tp.instances.get_mut(i) {
+                    match codes.remove(i) {
+                        Some(Err(e)) => {
+                            stop_failed(&mut it.task, &e);
+                            if res.is_ok() {
+                                res = Err(e);
                             }
+                        }
+                        code => {
+                            it.task.status = Some("stopped".to_string());
+                            it.task.pid = None;
+                            it.task.health = None;
+                            it.task.code = code.and_then(|code| code.ok()).flatten();
+                        }
+                    }
+                }
+            }
+        }
+        res.map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e).into())
+    }
+
+    /// Instances of all tasks with `instances`
+    pub async fn get_instances() -> Vec<Task> {
+        let tasks = TASKS.read().await;
+        tasks
+            .values()
+            .flat_map(|tp| tp.instances.values().map(|it| it.task.clone()))
+            .collect()
+    }
+
+    /// Instance `instance` of the running task waiting for the automatic restart
+    fn restarting(
+        tasks: &mut HashMap<i64, TaskProcess>,
+        id: i64,
+        instance: u32,
+    ) -> Option<&mut TaskProcess> {
+        tasks
+            .get_mut(&id)
+            .filter(|tp| tp.task.status == Some("running".to_string()))
+            .and_then(|tp| tp.instances.get_mut(&instance))
+            .filter(|it| it.task.status == Some("auto restart".to_string()))
+    }
+
+    /// Restart instance `instance` of the running task after it crashed
+    pub async fn restart_instance(id: i64, instance: u32) -> Result<(), Box<dyn Error>> {
+        let mut tasks = TASKS.write().await;
+        let task = match restarting(&mut tasks, id, instance) {
+            Some(it) => it.task.clone(),
+            None => return Ok(()),
+        };
+        drop(tasks); // 释放锁，启动进程期间不阻塞对其他任务的操作
+
+        let child = spawn(&task).await?;
+        let mut tasks = TASKS.write().await;
+        // 启动期间实例可能已被停止、缩容或由其他调用重启
+        let it = match restarting(&mut tasks, id, instance) {
+            Some(it) => it,
+            None => {
+                discard(child);
+                return Ok(());
+            }
+        };
+        notify_restarted(&it.task);
+        watch_async(it, child, false);
+        drop(tasks);
+        cache().await
+    }
+
+    /// Notify the automatic restart of the task, `task` is the state before the restart
+    fn notify_restarted(task: &Task) {
+        let message = task
+            .restart_reason
+            .clone()
+            .unwrap_or_else(|| exit_message(task.code));
+        notify::send(NotifyEvent::Restarted, task, task.code, message);
+    }
+
+    /// Change the number of instances of tasks matched by task flag
+    ///
+    /// A running task starts the added instances and stops the removed ones, the
+    /// other instances keep running.
+    pub async fn scale(tf: TaskFlag, instances: u32) -> Result<Response, Box<dyn Error>> {
+        let ids = resolve(&tf).await?;
+        let mut scaled = Vec::new();
+        for id in ids {
+            let mut tasks = TASKS.write().await;
+            let tp = match tasks.get_mut(&id) {
+                Some(tp) => tp,
+                None => {
+                    return Err(Box::new(std::io::Error::new(
+                        std::io::ErrorKind::Other,
+                        format!("Task [{}] not exists", id),
+                    )))
+                }
+            };
+            check_instances(&tp.task, instances)?;
+            let running = tp.task.status == Some("running".to_string());
+            if running && tp.task.instances.is_none() {
+                return Ok(Response::wrong(format!(
+                    "Task [{}] is running without instances, please stop it first",
+                    id
+                )));
+            }
+            tp.task.instances = Some(instances);
+            let removed: Vec<u32> = tp
+                .instances
+                .keys()
+                .copied()
+                .filter(|i| *i >= instances)
+                .collect();
+            drop(tasks);
+            if running {
+                start_instances(id).await?;
+            }
+            stop_instances(id, removed, true).await?;
+            info!("Task [{}] scaled to {} instances", id, instances);
+            scaled.push(id.to_string());
+        }
+        cache().await?;
+        Ok(Response::success(Some(Data::String(format!(
+            "Task [{}] scaled to {} instances",
+            scaled.join(","),
+            instances
+        )))))
+    }
 
-                            return res.code();
-                        });
+    /// Set daemon environment variables inherited by tasks without `inherit_env`
+    pub fn set_inherit_env(inherit: InheritEnv) {
+        let _ = INHERIT_ENV.set(inherit);
+    }
 
-                        // 保存监控进程结束协程的句柄
-                        tp.joinhandle = Some(jh);
+    fn inherit_env() -> InheritEnv {
+        INHERIT_ENV.get().cloned().unwrap_or_default()
+    }
+
+    /// Options of hook commands, hooks run outside the cgroup of the task
+    fn hook_options() -> StartOptions {
+        StartOptions {
+            cgroup: None,
+            inherit_env: inherit_env(),
+        }
+    }
+
+    /// Run the `pre_start` hook of the async task about to start, without holding `TASKS`
+    ///
+    /// Returns the failed response when the hook fails. A task restarting automatically
+    /// waits for the next restart following the restart policy.
+    async fn pre_start(id: i64) -> Result<Option<Response>, Box<dyn Error>> {
+        let task = match TASKS.read().await.get(&id) {
+            Some(tp) => tp.task.clone(),
+            None => return Ok(None),
+        };
+        let hook = match (&task.task_type, &task.pre_start) {
+            (TaskType::Async(_), Some(hook)) => hook.clone(),
+            _ => return Ok(None),
+        };
+        // 已经运行的任务由 start_task 返回错误
+        if task.status == Some("running".to_string())
+            || task.status == Some("starting".to_string())
+        {
+            return Ok(None);
+        }
+        let reason = match hook::run(&task, "pre_start", &hook, &hook_options()).await {
+            Ok(_) => return Ok(None),
+            Err(e) => format!("pre_start hook failed: {}", e),
+        };
+        warn!("Task [{}] {}", id, reason);
+        if task.status == Some("auto restart".to_string()) {
+            if let Some(tp) = TASKS.write().await.get_mut(&id) {
+                tp.task.restart_reason = Some(reason.clone());
+            }
+            update_instance(
+                id,
+                None,
+                None,
+                None,
+                None,
+                Some(true),
+                Some(vec!["auto restart"]),
+            )
+            .await?;
+            cache().await?;
+        }
+        Ok(Some(Response::failed(format!("Task [{}] {}", id, reason))))
+    }
+
+    /// Spawn the task process inside its cgroup
+    async fn spawn(task: &Task) -> Result<Child, Box<dyn Error>> {
+        let opts = StartOptions {
+            cgroup: cgroup::prepare(task.id, task.limits.as_ref())?,
+            inherit_env: inherit_env(),
+        };
+        // 先打开日志文件，打开失败时不启动进程
+        let writers = crate::logrotate::Writers::open(task)?;
+        let mut child = task.start(&opts).await?;
+        writers.pipe(&mut child);
+        Ok(child)
+    }
+
+    /// 启动协程等待常驻任务的子进程退出，按照重启策略更新任务状态
+    ///
+    /// 调用时需要持有 `TASKS` 写锁，任务状态直接在 `tp` 上更新，
+    /// `ready` 为已确认就绪的进程（滚动重载）
+    fn watch_async(tp: &mut TaskProcess, child: Child, ready: bool) {
+        // 配置了 stdin 时，启动一个协程用于向子进程 stdin 写入数据
+        let rx = if Some(true) == tp.task.stdin {
+            let (tx, rx) = mpsc::channel::<Vec<u8>>(CHANNEL_SIZE);
+            tp.tx = Some(tx);
+            Some(rx)
+        } else {
+            None
+        };
+
+        // 更新任务状态等数据
+        tp.task.pid = child.id();
+        tp.task.pid_start_time = child.id().and_then(process::start_time);
+        if let TaskType::Async(tt) = &mut tp.task.task_type {
+            tt.started_at = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("Failed to get timestamp")
+                .as_secs();
+        }
+        watch_started(tp, ready);
+
+        let task = tp.task.clone();
+        let jh: JoinHandle<Option<i32>> = tokio::spawn(async move {
+            let mut child = child;
+
+            // 接收到数据时写入子进程 stdin
+            let cjh = if let Some(mut rx) = rx {
+                let mut child_stdin = child.stdin.take().unwrap();
+                let cjh = tokio::spawn(async move {
+                    while let Some(message) = rx.recv().await {
+                        child_stdin.write_all(&message).await.unwrap();
+                        child_stdin.flush().await.unwrap();
                     }
+                });
+                Some(cjh)
+            } else {
+                None
+            };
+
+            // 等待子进程退出
+            let res = child.wait().await.unwrap();
+            let code = process::exit_code(&res);
+            exited(&task, code).await;
+
+            // 等待 stdin 写入协程退出
+            if let Some(cjh) = cjh {
+                cjh.await.unwrap();
+            }
+
+            code
+        });
+
+        // 保存监控进程结束协程的句柄
+        tp.joinhandle = Some(jh);
+    }
+
+    /// 接管守护进程重启前启动、仍在运行的任务进程
+    ///
+    /// 进程不是当前守护进程的子进程，无法获取退出码，也无法再向其 stdin 写入数据
+    fn watch_adopted(tp: &mut TaskProcess, pid: u32, starttime: u64) {
+        tp.tx = None;
+        // 已就绪的进程不再重新等待就绪条件，例如日志匹配只检查新写入的内容
+        let ready = tp.task.status == Some("running".to_string());
+        watch_started(tp, ready);
+
+        let task = tp.task.clone();
+        let jh: JoinHandle<Option<i32>> = tokio::spawn(async move {
+            process::wait_exit(pid, starttime).await;
+            // 不是当前守护进程的子进程，无法获取退出码
+            exited(&task, None).await;
+            None
+        });
+        tp.joinhandle = Some(jh);
+    }
+
+    /// Update the status of the task whose process just started or got adopted,
+    /// and start readiness and health checks of it
+    ///
+    /// `ready` skips the readiness check of a process known to be ready.
+    fn watch_started(tp: &mut TaskProcess, ready: bool) {
+        // 配置了就绪条件时，满足条件之前状态为 starting
+        tp.task.status = match (&tp.task.readiness, tp.task.pid) {
+            (Some(readiness), Some(pid)) if !ready => {
+                health::watch_ready(tp.task.id, pid, readiness.clone(), &tp.task);
+                Some("starting".to_string())
+            }
+            _ => Some("running".to_string()),
+        };
+        if let TaskType::Async(tt) = &mut tp.task.task_type {
+            tt.stopped_by_user = false;
+        }
+        tp.task.health = None;
+        if let (Some(hc), Some(pid)) = (tp.task.health_check.clone(), tp.task.pid) {
+            tp.task.health = Some("starting".to_string());
+            health::watch(tp.task.id, pid, hc, tp.task.dir.clone());
+        }
+        crate::watch::register(&tp.task);
+    }
+
+    fn exit_message(code: Option<i32>) -> String {
+        match code {
+            Some(code) => format!("exited with code {}", code),
+            None => "exited (code unknown)".to_string(),
+        }
+    }
+
+    /// Update the status of the task after its process exited with `code`, `None`
+    /// when the exit code is unknown
+    async fn exited(task: &Task, code: Option<i32>) {
+        info!("Task [{}:{}] exited with code: {:?}", task.id, task.name, code);
+
+        // 被停止或被滚动重载替换的进程由调用方更新任务状态
+        if let Some(pid) = task.pid {
+            if !is_managed(task.id, pid).await {
+                return;
+            }
+        }
+
+        // 退出码未知（接管的进程）时无法判断是否异常退出，不发送通知
+        if code.is_some_and(|code| code != 0) {
+            notify::send(NotifyEvent::Exited, task, code, exit_message(code));
+        }
+
+        // 正在被 stop 停止的任务状态为 stopping，不会被修改
+        let (status, restart) = if task.should_restart(code) {
+            ("auto restart", Some(true))
+        } else {
+            ("stopped", None)
+        };
+        if restart.is_some() {
+            set_restart_reason(task.id, task.pid, exit_message(code)).await;
+        }
+        update_instance(
+            task.id,
+            task.instance,
+            Some(None),
+            Some(Some(status.to_string())),
+            Some(code),
+            restart,
+            Some(vec!["running", "starting"]),
+        )
+        .await
+        .unwrap();
+        cache().await.unwrap();
+    }
+
+    /// Update the periodic task after its run `pid` exited with `code`
+    async fn run_exited(id: i64, pid: Option<u32>, code: Option<i32>) {
+        let mut tasks = TASKS.write().await;
+        let tp = match tasks.get_mut(&id) {
+            Some(tp) => tp,
+            None => return,
+        };
+        // 被替换的执行已经从列表中移除，不再修改任务状态
+        let count = tp.runs.len();
+        tp.runs.retain(|run| Some(run.pid) != pid);
+        if tp.runs.len() == count {
+            return;
+        }
+        tp.task.code = code;
+        match tp.runs.last() {
+            Some(run) => {
+                tp.task.pid = Some(run.pid);
+                tp.task.pid_start_time = run.starttime;
+            }
+            None => {
+                tp.task.pid = None;
+                tp.task.pid_start_time = None;
+                if tp.task.status == Some("executing".to_string()) {
+                    tp.task.status = Some("interval".to_string());
                 }
-                _ => {}
             }
-            tasks.insert(task.id, tp);
         }
-        Ok(())
+        drop(tasks);
+        cache().await.unwrap();
     }
 
     pub async fn update(
@@ -209,31 +1373,61 @@ pub mod global {
         code: Option<Option<i32>>,
         restart: Option<bool>,
         from_status: Option<Vec<&str>>,
+    ) -> Result<Response, Box<dyn Error>> {
+        update_instance(id, None, pid, status, code, restart, from_status).await
+    }
+
+    /// Same as [`update`], for instance `instance` of the task when it is set
+    async fn update_instance(
+        id: i64,
+        instance: Option<u32>,
+        pid: Option<Option<u32>>,
+        status: Option<Option<String>>,
+        code: Option<Option<i32>>,
+        restart: Option<bool>,
+        from_status: Option<Vec<&str>>,
     ) -> Result<Response, Box<dyn Error>> {
         let mut tasks = TASKS.write().await;
-        if !tasks.contains_key(&id) {
-            return Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!("Task [{}] not exists", id),
-            )));
-        }
-        let tp = tasks.get_mut(&id).unwrap();
+        let tp = match (tasks.get_mut(&id), instance) {
+            (Some(tp), Some(i)) => match tp.instances.get_mut(&i) {
+                Some(it) => it,
+                // 缩容时移除的实例不再更新
+                None => {
+                    return Ok(Response::wrong(format!(
+                        "Task [{}] instance [{}] not exists",
+                        id, i
+                    )))
+                }
+            },
+            (Some(tp), None) => tp,
+            (None, _) => {
+                return Err(Box::new(std::io::Error::new(
+                    std::io::ErrorKind::Other,
+                    format!("Task [{}] not exists", id),
+                )))
+            }
+        };
         if let Some(pid) = pid {
             tp.task.pid = pid;
+            tp.task.pid_start_time = pid.and_then(process::start_time);
         }
+        // 状态不在 from_status 中时（例如正在被 stop 停止），不修改状态也不触发重启
+        let matched = match (&from_status, &tp.task.status) {
+            (Some(from), Some(now_status)) => from.contains(&now_status.as_str()),
+            _ => true,
+        };
         if let Some(status) = status {
             match status.clone() {
                 Some(s) => match s.as_str() {
                     "running" => match tp.task.task_type.clone() {
                         TaskType::Async(tmp) => {
                             tp.task.task_type = TaskType::Async(AsyncTask {
-                                max_restart: tmp.max_restart,
-                                has_restart: tmp.has_restart,
-                                started_at: tmp.started_at,
-                                stopped_at: SystemTime::now()
+                                started_at: SystemTime::now()
                                     .duration_since(UNIX_EPOCH)
                                     .expect("Failed to get timestamp")
                                     .as_secs(),
+                                stopped_by_user: false,
+                                ..tmp
                             });
                         }
                         TaskType::Periodic(tmp) => {
@@ -243,10 +1437,8 @@ pub mod global {
                                 .as_secs();
                             tp.task.task_type =
                                 TaskType::Periodic(crate::common::task::PeriodicTask {
-                                    interval: tmp.interval,
                                     last_run: now,
-                                    started_after: tmp.started_after,
-                                    sync: tmp.sync,
+                                    ..tmp
                                 });
                         }
                         _ => {}
@@ -255,39 +1447,48 @@ pub mod global {
                 },
                 None => {}
             }
-            if let Some(from) = from_status {
-                let now_status = tp.task.status.as_ref().unwrap().as_str();
-                if from.contains(&now_status) {
-                    tp.task.status = status;
-                }
-            } else {
+            if matched {
                 tp.task.status = status;
+                if tp.task.status != Some("running".to_string())
+                    && tp.task.status != Some("starting".to_string())
+                {
+                    tp.task.health = None;
+                }
             }
         }
-        if let Some(restart) = restart {
+        if let (Some(restart), true) = (restart, matched) {
             match tp.task.task_type.clone() {
-                TaskType::Async(tmp) => {
-                    let has = if restart {
-                        if let Some(max) = tmp.max_restart {
-                            if tmp.has_restart >= max {
-                                tp.task.status = Some("stopped".to_string());
-                                tmp.has_restart
-                            } else {
-                                tmp.has_restart + 1
+                TaskType::Async(mut tmp) => {
+                    let now = SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .expect("Failed to get timestamp")
+                        .as_secs();
+                    // 运行时间超过 min_uptime 视为稳定运行，重新开始计算重启次数
+                    tmp.exited_at(now);
+                    if restart {
+                        // 未配置 max_restart 时一直重启，但同样遵循退避时间
+                        if !tmp.schedule_restart(now) {
+                            // 短时间内反复崩溃，不再重启
+                            info!(
+                                "Task [{}] crashed {} times in a row, mark as errored",
+                                id, tmp.has_restart
+                            );
+                            let max = tmp.max_restart.unwrap_or_default();
+                            let status = if max == 0 { "stopped" } else { "errored" };
+                            tp.task.status = Some(status.to_string());
+                            if max > 0 {
+                                notify::send(
+                                    NotifyEvent::GaveUp,
+                                    &tp.task,
+                                    code.unwrap_or(tp.task.code),
+                                    format!("crashed {} times in a row", tmp.has_restart),
+                                );
                             }
-                        } else {
-                            0
                         }
                     } else {
                         tp.task.status = Some("stopped".to_string());
-                        tmp.has_restart
-                    };
-                    tp.task.task_type = TaskType::Async(AsyncTask {
-                        max_restart: tmp.max_restart,
-                        has_restart: has,
-                        started_at: tmp.started_at,
-                        stopped_at: tmp.stopped_at,
-                    });
+                    }
+                    tp.task.task_type = TaskType::Async(tmp);
                 }
                 _ => {}
             }
@@ -308,6 +1509,8 @@ pub mod global {
             )));
         }
 
+        check_task(&tasks, &task)?;
+
         match task.task_type {
             TaskType::Scheduled(_) => {
                 task.status = Some("waiting".to_string());
@@ -317,6 +1520,58 @@ pub mod global {
             }
         }
 
+        expand_paths(&mut task)?;
+        let tn = task.name.clone();
+        tasks.insert(id, TaskProcess::new(task));
+        cache().await?;
+        Ok(Response::success(Some(Data::String(format!(
+            "Task [{}] added",
+            tn
+        )))))
+    }
+
+    /// Check the configuration of the task added or reloaded
+    fn check_task(
+        tasks: &HashMap<i64, TaskProcess>,
+        task: &Task,
+    ) -> Result<(), Box<dyn Error>> {
+        check_depends(tasks, task)?;
+        if let Some(hc) = &task.health_check {
+            hc.validate()
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+        }
+        if let Some(limits) = &task.limits {
+            limits
+                .validate()
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+        }
+        // 守护进程没有权限切换到任务配置的用户时拒绝添加
+        if let Some(credential) = Credential::from_task(task)? {
+            credential.without_current().check()?;
+        }
+        if let Some(readiness) = &task.readiness {
+            readiness
+                .validate()
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+        }
+        if let TaskType::Scheduled(tt) = &task.task_type {
+            tt.validate()
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+        }
+        if let Some(rotate) = &task.log_rotate {
+            rotate
+                .validate()
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+        }
+        if let Some(instances) = task.instances {
+            check_instances(task, instances)?;
+        }
+        Ok(())
+    }
+
+    /// Interpolate variables of the task and expand `~` in output files and args
+    fn expand_paths(task: &mut Task) -> Result<(), Box<dyn Error>> {
+        task.interpolate(&inherit_env())?;
         if let Some(so) = &task.stdout {
             let stdout = get_with_home_path(so);
             let parent = stdout.parent().unwrap();
@@ -339,21 +1594,19 @@ pub mod global {
             args[i] = get_with_home(&args[i]);
         }
         task.args = args;
-        let tn = task.name.clone();
-        let tp = TaskProcess {
-            task,
-            joinhandle: None,
-            tx: None,
-        };
-        tasks.insert(id, tp);
-        cache().await?;
-        Ok(Response::success(Some(Data::String(format!(
-            "Task [{}] added",
-            tn
-        )))))
+        Ok(())
     }
 
+    /// Reload the task with its new configuration
+    ///
+    /// A running async task is reloaded without downtime, see [`rolling_reload`],
+    /// other tasks are removed and added again.
     pub async fn reload(task: Task) -> Result<Response, Box<dyn Error>> {
+        // 先检查新配置，避免移除旧任务后无法添加新任务
+        check_task(&*TASKS.read().await, &task)?;
+        if is_stoppable(task.id).await {
+            return rolling_reload(task).await;
+        }
         remove(
             TaskFlag {
                 id: task.id,
@@ -367,6 +1620,228 @@ pub mod global {
         add(task).await
     }
 
+    /// Reload the running async task without downtime
+    ///
+    /// The process, or each instance one after another, is replaced by a process of the
+    /// new configuration: the old process is stopped only after the new one is ready.
+    /// When a new instance fails, the instances already replaced are rolled back to the
+    /// old configuration.
+    async fn rolling_reload(mut task: Task) -> Result<Response, Box<dyn Error>> {
+        let id = task.id;
+        let tasks = TASKS.read().await;
+        let (old, current) = match tasks.get(&id) {
+            Some(tp) => (
+                tp.task.clone(),
+                tp.instances.keys().copied().collect::<Vec<u32>>(),
+            ),
+            None => {
+                return Err(Box::new(std::io::Error::new(
+                    std::io::ErrorKind::Other,
+                    format!("Task [{}] not exists", id),
+                )))
+            }
+        };
+        check_task(&tasks, &task)?;
+        drop(tasks);
+        if !matches!(old.task_type, TaskType::Async(_))
+            || !matches!(task.task_type, TaskType::Async(_))
+            || old.instances.is_some() != task.instances.is_some()
+        {
+            return Ok(Response::wrong(format!(
+                "Task [{}] is running, please stop it before changing its type or instances",
+                id
+            )));
+        }
+        expand_paths(&mut task)?;
+        task.created_at = old.created_at;
+
+        let instances = match task.instances {
+            Some(instances) => instances,
+            None => {
+                return match replace(id, None, task).await {
+                    Ok(()) => Ok(Response::success(Some(Data::String(format!(
+                        "Task [{}] reloaded",
+                        id
+                    ))))),
+                    Err(e) => Ok(Response::wrong(format!(
+                        "Task [{}] reload failed: {}, the old process keeps running",
+                        id, e
+                    ))),
+                };
+            }
+        };
+
+        // 逐个替换实例，失败时将已替换的实例回滚到旧配置
+        let rolling: Vec<u32> = current.into_iter().filter(|i| *i < instances).collect();
+        for (n, i) in rolling.iter().enumerate() {
+            let res = match task.instance(*i) {
+                Ok(it) => replace(id, Some(*i), it).await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = res {
+                warn!("Reload task [{}] instance [{}] failed: {}", id, i, e);
+                for j in rolling[..n].iter().rev() {
+                    info!("Roll back task [{}] instance [{}]", id, j);
+                    let res = match old.instance(*j) {
+                        Ok(it) => replace(id, Some(*j), it).await,
+                        Err(e) => Err(e),
+                    };
+                    if let Err(e) = res {
+                        warn!("Roll back task [{}] instance [{}] failed: {}", id, j, e);
+                    }
+                }
+                return Ok(Response::wrong(format!(
+                    "Task [{}] instance [{}] reload failed: {}, rolled back",
+                    id, i, e
+                )));
+            }
+        }
+
+        // 全部替换后更新任务配置，按照新的实例数启动或停止实例
+        let mut tasks = TASKS.write().await;
+        let tp = match tasks.get_mut(&id) {
+            Some(tp) => tp,
+            None => return Ok(Response::wrong(format!("Task [{}] not exists", id))),
+        };
+        task.status = tp.task.status.clone();
+        tp.task = task;
+        let removed: Vec<u32> = tp
+            .instances
+            .keys()
+            .copied()
+            .filter(|i| *i >= instances)
+            .collect();
+        let running = tp.task.status == Some("running".to_string());
+        drop(tasks);
+        if running {
+            start_instances(id).await?;
+        }
+        stop_instances(id, removed, true).await?;
+        cache().await?;
+        Ok(Response::success(Some(Data::String(format!(
+            "Task [{}] reloaded",
+            id
+        )))))
+    }
+
+    /// Replace the process of the task, or of its instance `instance`, by a process of
+    /// `task`, the old process is stopped after the new one is ready
+    ///
+    /// A readiness condition also met by the old process (e.g. `tcp` on the same
+    /// address) is met by the new one at once.
+    async fn replace(id: i64, instance: Option<u32>, task: Task) -> Result<(), String> {
+        // 在启动之前记录日志位置，只匹配新进程写入的日志
+        let probe = task
+            .readiness
+            .as_ref()
+            .map(|readiness| health::ReadyProbe::new(readiness, &task));
+        let mut child = spawn(&task).await.map_err(|e| e.to_string())?;
+        info!("Task [{}] started process {:?} for reload", id, child.id());
+        if let Some(mut probe) = probe {
+            if let Err(e) = wait_new_ready(&mut probe, &mut child).await {
+                // 新进程未就绪，停止新进程，旧进程继续运行
+                stop_child(&task, child).await;
+                return Err(e);
+            }
+        } else {
+            // 未配置就绪检查时，确认新进程没有在启动后立即退出
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            if let Ok(Some(status)) = child.try_wait() {
+                return Err(format!(
+                    "new process exited with code {:?}",
+                    process::exit_code(&status)
+                ));
+            }
+        }
+
+        let mut tasks = TASKS.write().await;
+        let tp = match (tasks.get_mut(&id), instance) {
+            (Some(tp), Some(i)) => tp.instances.get_mut(&i),
+            (tp, None) => tp,
+            (None, _) => None,
+        };
+        let tp = match tp {
+            Some(tp) => tp,
+            None => {
+                drop(tasks);
+                stop_child(&task, child).await;
+                return Err("task removed during reload".to_string());
+            }
+        };
+        let old = match tp.task.pid {
+            Some(pid) => Some((
+                pid,
+                tp.task.pid_start_time,
+                process::parse_signal(
+                    tp.task
+                        .stop_signal
+                        .as_deref()
+                        .unwrap_or(DEFAULT_STOP_SIGNAL),
+                )
+                .map_err(|e| e.to_string())?,
+                Duration::from_secs(tp.task.stop_timeout.unwrap_or(DEFAULT_STOP_TIMEOUT)),
+                tp.joinhandle.take(),
+                tp.task.kill_descendants,
+            )),
+            None => None,
+        };
+        // 新进程已就绪，直接替换任务，旧进程的退出监控不再修改任务状态
+        tp.tx = None;
+        tp.task = task;
+        watch_async(tp, child, true);
+        drop(tasks);
+
+        if let Some((pid, starttime, sig, timeout, jh, sweep)) = old {
+            info!("Stop old process {} of task [{}]", pid, id);
+            process::terminate(pid, starttime, sig, timeout, jh, sweep)
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+        cache().await.map_err(|e| e.to_string())
+    }
+
+    /// Wait until the new process `child` started for reload is ready
+    async fn wait_new_ready(
+        probe: &mut health::ReadyProbe,
+        child: &mut Child,
+    ) -> Result<(), String> {
+        loop {
+            if let Ok(Some(status)) = child.try_wait() {
+                return Err(format!(
+                    "new process exited with code {:?}",
+                    process::exit_code(&status)
+                ));
+            }
+            if probe.ready().await {
+                return Ok(());
+            }
+            if probe.expired() {
+                return Err("new process is not ready in time".to_string());
+            }
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        }
+    }
+
+    /// Stop the new process `child` started for reload
+    async fn stop_child(task: &Task, mut child: Child) {
+        let pid = match child.id() {
+            Some(pid) => pid,
+            None => return,
+        };
+        let sig = process::parse_signal(task.stop_signal.as_deref().unwrap_or(DEFAULT_STOP_SIGNAL))
+            .unwrap_or(libc::SIGTERM);
+        let timeout = Duration::from_secs(task.stop_timeout.unwrap_or(DEFAULT_STOP_TIMEOUT));
+        let starttime = process::start_time(pid);
+        let jh: JoinHandle<Option<i32>> = tokio::spawn(async move {
+            child.wait().await.ok().and_then(|res| process::exit_code(&res))
+        });
+        if let Err(e) =
+            process::terminate(pid, starttime, sig, timeout, Some(jh), task.kill_descendants).await
+        {
+            warn!("Stop process {} of task [{}] failed: {}", pid, task.id, e);
+        }
+    }
+
     pub async fn remove(tf: TaskFlag, to_cache: bool) -> Result<Response, Box<dyn Error>> {
         let mut tasks = TASKS.write().await;
         if tf.id > 0 {
@@ -377,12 +1852,16 @@ pub mod global {
                 )));
             }
             let tp = tasks.get(&tf.id).unwrap();
-            if Some("running".to_string()) == tp.task.status {
+            if Some("running".to_string()) == tp.task.status
+                    || Some("starting".to_string()) == tp.task.status
+                {
                 return Ok(Response::wrong(
                     "Task is running, please stop it first".to_string(),
                 ));
             }
             if let Some(removed) = tasks.remove(&tf.id) {
+                cgroup::remove(tf.id);
+                crate::watch::unregister(tf.id);
                 if to_cache {
                     cache().await?;
                 }
@@ -401,12 +1880,16 @@ pub mod global {
                 .map(|(id, _)| *id);
             if let Some(id) = id {
                 let tp = tasks.get(&id).unwrap();
-                if Some("running".to_string()) == tp.task.status {
+                if Some("running".to_string()) == tp.task.status
+                    || Some("starting".to_string()) == tp.task.status
+                {
                     return Ok(Response::wrong(
                         "Task is running, please stop it first".to_string(),
                     ));
                 }
                 if let Some(removed) = tasks.remove(&id) {
+                    cgroup::remove(id);
+                    crate::watch::unregister(id);
                     if to_cache {
                         cache().await?;
                     }
@@ -444,12 +1927,16 @@ pub mod global {
             let mut removed = vec![];
             for id in ids {
                 let tp = tasks.get(&id).unwrap();
-                if Some("running".to_string()) == tp.task.status {
+                if Some("running".to_string()) == tp.task.status
+                    || Some("starting".to_string()) == tp.task.status
+                {
                     return Ok(Response::wrong(
                         "Task is running, please stop it first".to_string(),
                     ));
                 }
                 if let Some(r) = tasks.remove(&id) {
+                    cgroup::remove(id);
+                    crate::watch::unregister(id);
                     removed.push(format!("{}:{}", r.task.id, r.task.name));
                 }
             }
@@ -474,21 +1961,105 @@ pub mod global {
             )));
         }
         let tp = tasks.get(&tf.id).unwrap();
-        if tp.task.pid.is_some() {
+        if tp.task.pid.is_some() || tp.instances.values().any(|it| it.task.pid.is_some()) {
+            // stop 需要获取任务锁并等待进程退出，先释放锁
+            drop(tasks);
             stop(tf.clone(), true).await?;
+            tasks = TASKS.write().await;
         }
-        if let Some(jh) = &tp.joinhandle {
-            jh.abort();
+        if let Some(tp) = tasks.remove(&tf.id) {
+            cgroup::remove(tf.id);
+            crate::watch::unregister(tf.id);
+            if let Some(jh) = &tp.joinhandle {
+                jh.abort();
+            }
+            for run in &tp.runs {
+                run.joinhandle.abort();
+            }
+            for it in tp.instances.values() {
+                if let Some(jh) = &it.joinhandle {
+                    jh.abort();
+                }
+            }
         }
-        let tn = tf.id.clone();
-        tasks.remove(&tf.id);
+        let tn = tf.id;
         Ok(Response::success(Some(Data::String(format!(
             "Task [{}] deleted",
             tn
         )))))
     }
 
+    /// Start tasks matched by task flag
+    ///
+    /// Tasks they depend on are started first when not running. With `depends_wait`
+    /// configured, a task is started only after its dependencies are running.
     pub async fn start(tf: TaskFlag) -> Result<Response, Box<dyn Error>> {
+        let ids = resolve(&tf).await?;
+        let tasks = TASKS.read().await;
+        if ids.len() == 1 && !tasks.contains_key(&ids[0]) {
+            return Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                format!("Task [{}] not exists", ids[0]),
+            )));
+        }
+        let order = dependency_order(&tasks, &ids)?;
+        drop(tasks);
+
+        let mut started = Vec::new();
+        for id in order {
+            // 启动依赖期间任务可能已被删除
+            let task = match TASKS.read().await.get(&id) {
+                Some(tp) => tp.task.clone(),
+                None => {
+                    return Err(Box::new(std::io::Error::new(
+                        std::io::ErrorKind::Other,
+                        format!("Task [{}] not exists", id),
+                    )));
+                }
+            };
+            let requested = ids.contains(&id);
+            if !requested {
+                // 依赖的常驻任务未运行时先启动
+                if !matches!(task.task_type, TaskType::Async(_))
+                    || task.status == Some("running".to_string())
+                    || task.status == Some("starting".to_string())
+                    || task.status == Some("auto restart".to_string())
+                {
+                    continue;
+                }
+                info!("Start task [{}] required by task [{:?}]", id, ids);
+            }
+            for dep in &task.depends_on {
+                // 依赖配置了就绪条件时，默认等待其就绪
+                let tasks = TASKS.read().await;
+                let readiness = tasks.get(dep).and_then(|tp| tp.task.readiness.clone());
+                drop(tasks);
+                if let Some(timeout) = task.depends_wait.or(readiness.map(|r| r.timeout)) {
+                    wait_running(*dep, timeout).await?;
+                }
+            }
+            if requested && ids.len() == 1 {
+                return start_task(flag(id)).await;
+            }
+            if requested && is_stoppable(id).await {
+                continue;
+            }
+            let res = start_task(flag(id)).await?;
+            if !res.is_success() {
+                return Ok(res);
+            }
+            started.push(id.to_string());
+        }
+        Ok(Response::success(Some(Data::String(format!(
+            "Task [{}] started",
+            started.join(",")
+        )))))
+    }
+
+    async fn start_task(tf: TaskFlag) -> Result<Response, Box<dyn Error>> {
+        if let Some(res) = pre_start(tf.id).await? {
+            return Ok(res);
+        }
         let mut tasks = TASKS.write().await;
         if !tasks.contains_key(&tf.id) {
             return Err(Box::new(std::io::Error::new(
@@ -498,28 +2069,50 @@ pub mod global {
         }
         let tp = tasks.get_mut(&tf.id).unwrap();
 
+        // 记录定时任务和周期任务的执行时间，用于计算执行间隔和错过的执行
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Failed to get timestamp")
+            .as_secs();
+        match &mut tp.task.task_type {
+            TaskType::Scheduled(tt) => tt.last_run = Some(now as i64),
+            TaskType::Periodic(tt) => tt.last_run = now,
+            _ => {}
+        }
+
         match &tp.task.task_type {
             TaskType::Scheduled(_) => {
                 let id = tf.id;
                 let name = tf.name.clone();
 
-                let mut child = tp.task.start().await?;
+                let mut child = match spawn(&tp.task).await {
+                    Ok(child) => child,
+                    Err(e) => {
+                        let message = format!("start failed: {}", e);
+                        notify::send(NotifyEvent::ScheduleFailed, &tp.task, None, message);
+                        return Err(e);
+                    }
+                };
                 let pid = child.id();
+                let task = tp.task.clone();
                 let jh: JoinHandle<Option<i32>> = tokio::spawn(async move {
                     let res = child.wait().await.unwrap();
-                    let code = res.code();
+                    let code = process::exit_code(&res);
                     info!(
                         "Task [{}:{}] exited with code: {:?}",
                         id,
                         name.unwrap_or(String::new()),
                         code
                     );
+                    if code != Some(0) {
+                        notify::send(NotifyEvent::ScheduleFailed, &task, code, exit_message(code));
+                    }
 
                     update(
                         tf.id,
                         Some(None),
                         Some(Some("waiting".to_string())),
-                        Some(res.code()),
+                        Some(code),
                         Some(false),
                         Some(vec!["processing"]),
                     )
@@ -528,7 +2121,7 @@ pub mod global {
 
                     cache().await.unwrap();
 
-                    return res.code();
+                    return code;
                 });
 
                 tp.joinhandle = Some(jh);
@@ -550,153 +2143,152 @@ pub mod global {
                     id
                 )))))
             }
-            TaskType::Async(tt) => {
-                let max = tt.max_restart;
-                if tp.task.status == Some("running".to_string()) {
+            TaskType::Async(_) => {
+                if tp.task.status == Some("running".to_string())
+                    || tp.task.status == Some("starting".to_string())
+                {
                     return Err(Box::new(std::io::Error::new(
                         std::io::ErrorKind::Other,
                         format!("Task [{}] is running", tf.id),
                     )));
                 }
 
-                let child = tp.task.start().await?;
-
-                let rx = if Some(true) == tp.task.stdin {
-                    let (tx, rx) = mpsc::channel::<Vec<u8>>(CHANNEL_SIZE);
-                    tp.tx = Some(tx);
-                    Some(rx)
-                } else {
-                    None
-                };
-
-                let pid = child.id();
-                let status = Some("running".to_string());
-
-                let jh: JoinHandle<Option<i32>> = tokio::spawn(async move {
-                    let mut child = child;
-
-                    let cjh = if let Some(mut rx) = rx {
-                        let mut child_stdin = child.stdin.take().unwrap();
-                        // let mut stdin_writer = tokio::io::BufWriter::new(child_stdin);
-                        let cjh = tokio::spawn(async move {
-                            while let Some(message) = rx.recv().await {
-                                child_stdin.write_all(&message).await.unwrap();
-                                child_stdin.flush().await.unwrap();
-                            }
-                        });
-                        Some(cjh)
-                    } else {
-                        None
-                    };
-
-                    let res = child.wait().await.unwrap();
-                    let code = res.code();
-                    let exit = if let Some(max) = max {
-                        if let Some(code) = code {
-                            code == 0 || code == -15 || max == 0
-                        } else {
-                            true
-                        }
-                    } else {
-                        true
-                    };
-                    if exit {
-                        update(
-                            tf.id,
-                            Some(None),
-                            Some(Some("stopped".to_string())),
-                            Some(res.code()),
-                            None,
-                            None,
-                        )
-                        .await
-                        .unwrap();
-                        cache().await.unwrap();
+                // 多实例任务启动全部实例，任务本身没有进程
+                if tp.task.instances.is_some() {
+                    tp.instances.clear();
+                    drop(tasks);
+                    start_instances(tf.id).await?;
+                    if let Some(tp) = TASKS.read().await.get(&tf.id) {
+                        post_start(&tp.task);
+                    }
+                    let id = tf.id;
+                    cache().await?;
+                    return Ok(Response::success(Some(Data::String(format!(
+                        "Task [{}] started",
+                        id
+                    )))));
+                }
 
-                        if let Some(cjh) = cjh {
-                            cjh.await.unwrap();
-                        }
-                    } else {
-                        update(
-                            tf.id,
-                            Some(None),
-                            Some(Some("auto restart".to_string())),
-                            Some(res.code()),
-                            Some(true),
-                            None,
-                        )
-                        .await
-                        .unwrap();
-                        cache().await.unwrap();
+                let child = spawn(&tp.task).await?;
 
-                        if let Some(cjh) = cjh {
-                            cjh.await.unwrap();
-                        }
+                // 手动启动（不是自动重启）时重新计算连续重启次数
+                if tp.task.status != Some("auto restart".to_string()) {
+                    if let TaskType::Async(tt) = &mut tp.task.task_type {
+                        tt.has_restart = 0;
                     }
+                    tp.task.restart_reason = None;
+                } else {
+                    notify_restarted(&tp.task);
+                }
 
-                    return res.code();
-                });
-
-                tp.joinhandle = Some(jh);
+                watch_async(tp, child, false);
+                post_start(&tp.task);
 
                 let id = tf.id;
-                tokio::spawn(async move {
-                    update(id, Some(pid), Some(status), None, None, None)
-                        .await
-                        .unwrap();
-                });
-
                 cache().await?;
                 Ok(Response::success(Some(Data::String(format!(
                     "Task [{}] started",
                     id
                 )))))
             }
-            TaskType::Periodic(_) => {
+            TaskType::Periodic(tt) => {
                 let id = tf.id;
                 let name = tf.name.clone();
 
-                let mut child = tp.task.start().await?;
+                // 上一次执行仍未结束时按照并发策略处理
+                if !tp.runs.is_empty() {
+                    match tt.concurrency_policy {
+                        ConcurrencyPolicy::Forbid => {
+                            info!("Skip periodic task [{}]: previous run is executing", id);
+                            cache().await?;
+                            return Ok(Response::wrong(format!(
+                                "Task [{}] is executing",
+                                id
+                            )));
+                        }
+                        ConcurrencyPolicy::Allow => {
+                            if let Some(max) = tt.max_concurrent {
+                                if tp.runs.len() as u64 >= max {
+                                    info!(
+                                        "Skip periodic task [{}]: {} runs are executing",
+                                        id,
+                                        tp.runs.len()
+                                    );
+                                    cache().await?;
+                                    return Ok(Response::wrong(format!(
+                                        "Task [{}] reached max_concurrent {}",
+                                        id, max
+                                    )));
+                                }
+                            }
+                        }
+                        ConcurrencyPolicy::Replace => {
+                            let runs = std::mem::take(&mut tp.runs);
+                            let sig = process::parse_signal(
+                                tp.task
+                                    .stop_signal
+                                    .as_deref()
+                                    .unwrap_or(DEFAULT_STOP_SIGNAL),
+                            )?;
+                            let timeout = Duration::from_secs(
+                                tp.task.stop_timeout.unwrap_or(DEFAULT_STOP_TIMEOUT),
+                            );
+                            let sweep = tp.task.kill_descendants;
+                            drop(tasks); // 释放锁，等待旧的执行退出
+
+                            // 某个执行停止失败时继续停止其余的执行
+                            for run in runs {
+                                info!("Replace run {} of periodic task [{}]", run.pid, id);
+                                if let Err(e) = process::terminate(
+                                    run.pid,
+                                    run.starttime,
+                                    sig,
+                                    timeout,
+                                    Some(run.joinhandle),
+                                    sweep,
+                                )
+                                .await
+                                .map_err(|e| e.to_string())
+                                {
+                                    warn!(
+                                        "Stop run {} of periodic task [{}] failed: {}",
+                                        run.pid, id, e
+                                    );
+                                }
+                            }
+                            return Box::pin(start_task(tf)).await;
+                        }
+                    }
+                }
+
+                let mut child = spawn(&tp.task).await?;
                 let pid = child.id();
                 let jh: JoinHandle<Option<i32>> = tokio::spawn(async move {
                     let res = child.wait().await.unwrap();
-                    let code = res.code();
+                    let code = process::exit_code(&res);
                     info!(
                         "Task [{}:{}] exited with code: {:?}",
                         id,
                         name.unwrap_or(String::new()),
                         code
                     );
-
-                    let _ = update(
-                        tf.id,
-                        Some(None),
-                        Some(Some("interval".to_string())),
-                        Some(res.code()),
-                        Some(false),
-                        Some(vec!["executing"]),
-                    )
-                    .await;
-
-                    cache().await.unwrap();
-
-                    return res.code();
+                    run_exited(id, pid, code).await;
+                    code
                 });
 
-                tp.joinhandle = Some(jh);
-
-                tokio::spawn(async move {
-                    update(
-                        id,
-                        Some(pid),
-                        Some(Some("executing".to_string())),
-                        None,
-                        None,
-                        None,
-                    )
-                    .await
-                    .unwrap();
-                });
+                // 每次执行单独记录，任务的进程号为最近一次执行的进程
+                if let Some(pid) = pid {
+                    let starttime = process::start_time(pid);
+                    tp.runs.push(RunProcess {
+                        pid,
+                        starttime,
+                        joinhandle: jh,
+                    });
+                    tp.task.pid = Some(pid);
+                    tp.task.pid_start_time = starttime;
+                    tp.task.status = Some("executing".to_string());
+                }
+                cache().await?;
                 Ok(Response::success(Some(Data::String(format!(
                     "Task [{}] started",
                     id
@@ -732,7 +2324,68 @@ pub mod global {
         }
     }
 
+    /// Stop tasks matched by task flag, a group is stopped in reverse dependency order
     pub async fn stop(tf: TaskFlag, to_cache: bool) -> Result<Response, Box<dyn Error>> {
+        let ids = resolve(&tf).await?;
+        if ids.len() == 1 {
+            return stop_task(flag(ids[0]), to_cache).await;
+        }
+        let tasks = TASKS.read().await;
+        let mut order: Vec<i64> = dependency_order(&tasks, &ids)?
+            .into_iter()
+            .filter(|id| ids.contains(id))
+            .collect();
+        drop(tasks);
+        order.reverse();
+
+        let mut stopped = Vec::new();
+        for id in order {
+            if !is_stoppable(id).await {
+                continue;
+            }
+            let res = stop_task(flag(id), to_cache).await?;
+            if !res.is_success() {
+                return Ok(res);
+            }
+            stopped.push(id.to_string());
+        }
+        Ok(Response::success(Some(Data::String(format!(
+            "Task [{}] stopped",
+            stopped.join(",")
+        )))))
+    }
+
+    /// Run the `post_start` hook of the task started just now in background
+    fn post_start(task: &Task) {
+        if let Some(hook) = task.post_start.clone() {
+            let task = task.clone();
+            tokio::spawn(async move {
+                hook::run_logged(&task, "post_start", &hook, &hook_options()).await;
+            });
+        }
+    }
+
+    /// Stop the task, `pre_stop` and `post_stop` hooks of a running async task run
+    /// before and after its processes are stopped
+    async fn stop_task(tf: TaskFlag, to_cache: bool) -> Result<Response, Box<dyn Error>> {
+        let task = match TASKS.read().await.get(&tf.id) {
+            Some(tp) => tp.task.clone(),
+            None => return stop_processes(tf, to_cache).await,
+        };
+        let hooked = matches!(task.task_type, TaskType::Async(_))
+            && (task.status == Some("running".to_string())
+                || task.status == Some("starting".to_string()));
+        if let (true, Some(hook)) = (hooked, &task.pre_stop) {
+            hook::run_logged(&task, "pre_stop", hook, &hook_options()).await;
+        }
+        let res = stop_processes(tf, to_cache).await?;
+        if let (true, true, Some(hook)) = (hooked, res.is_success(), &task.post_stop) {
+            hook::run_logged(&task, "post_stop", hook, &hook_options()).await;
+        }
+        Ok(res)
+    }
+
+    async fn stop_processes(tf: TaskFlag, to_cache: bool) -> Result<Response, Box<dyn Error>> {
         let mut tasks = TASKS.write().await;
         if !tasks.contains_key(&tf.id) {
             return Err(Box::new(std::io::Error::new(
@@ -743,6 +2396,7 @@ pub mod global {
         let tp = tasks.get_mut(&tf.id).unwrap();
 
         if tp.task.status != Some("running".to_string())
+            && tp.task.status != Some("starting".to_string())
             && tp.task.status != Some("auto restart".to_string())
         {
             return Err(Box::new(std::io::Error::new(
@@ -751,22 +2405,93 @@ pub mod global {
             )));
         }
 
+        if tp.task.instances.is_some() {
+            tp.task.status = Some("stopping".to_string());
+            if let TaskType::Async(tt) = &mut tp.task.task_type {
+                tt.stopped_by_user = true;
+            }
+            let instances: Vec<u32> = tp.instances.keys().copied().collect();
+            drop(tasks);
+            let res = stop_instances(tf.id, instances, false)
+                .await
+                .map_err(|e| e.to_string());
+
+            // 有实例停止失败时任务标记为 errored，不会停留在 stopping
+            let mut tasks = TASKS.write().await;
+            if let Some(tp) = tasks.get_mut(&tf.id) {
+                let status = if res.is_ok() { "stopped" } else { "errored" };
+                tp.task.status = Some(status.to_string());
+            }
+            drop(tasks);
+            if to_cache || res.is_err() {
+                cache().await?;
+            }
+            res.map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+            return Ok(Response::success(Some(Data::String(format!(
+                "Task [{}] stopped",
+                tf.id
+            )))));
+        }
+
         let pid = tp.task.pid;
 
         if let Some(pid) = pid {
-            let mut child: Child = Command::new("kill")
-                .arg("-15")
-                .arg(pid.to_string())
-                .envs(std::env::vars())
-                .stdin(Stdio::null())
-                .stdout(Stdio::null())
-                .stderr(Stdio::null())
-                .spawn()?;
-            child.wait().await?;
-            tp.task.status = Some("stopped".to_string());
-            tp.task.code = Some(9);
-            tp.joinhandle = None;
+            let sig = process::parse_signal(
+                tp.task
+                    .stop_signal
+                    .as_deref()
+                    .unwrap_or(DEFAULT_STOP_SIGNAL),
+            )?;
+            let timeout =
+                Duration::from_secs(tp.task.stop_timeout.unwrap_or(DEFAULT_STOP_TIMEOUT));
+            let sweep = tp.task.kill_descendants;
+            let starttime = tp.task.pid_start_time;
+
+            // 标记为 stopping，退出监控协程看到该状态后不会再触发自动重启
+            tp.task.status = Some("stopping".to_string());
+            if let TaskType::Async(tt) = &mut tp.task.task_type {
+                tt.stopped_by_user = true;
+            }
+            let jh = tp.joinhandle.take();
             tp.tx = None;
+            drop(tasks); // 释放锁，等待进程退出期间退出监控协程需要更新任务
+
+            let code = match process::terminate(pid, starttime, sig, timeout, jh, sweep)
+                .await
+                .map_err(|e| e.to_string())
+            {
+                Ok(code) => code,
+                Err(e) => {
+                    if let Some(tp) = TASKS.write().await.get_mut(&tf.id) {
+                        stop_failed(&mut tp.task, &e);
+                    }
+                    cache().await?;
+                    return Err(Box::new(std::io::Error::new(std::io::ErrorKind::Other, e)));
+                }
+            };
+            info!("Task [{}] stopped with code: {:?}", tf.id, code);
+
+            let mut tasks = TASKS.write().await;
+            if let Some(tp) = tasks.get_mut(&tf.id) {
+                tp.task.status = Some("stopped".to_string());
+                tp.task.pid = None;
+                tp.task.code = code;
+                tp.task.health = None;
+            }
+            drop(tasks);
+            if to_cache {
+                cache().await?;
+            }
+            Ok(Response::success(Some(Data::String(format!(
+                "Task [{}] stopped",
+                tf.id
+            )))))
+        } else if tp.task.status == Some("auto restart".to_string()) {
+            // 等待自动重启的任务没有进程，取消重启即可
+            tp.task.status = Some("stopped".to_string());
+            if let TaskType::Async(tt) = &mut tp.task.task_type {
+                tt.stopped_by_user = true;
+            }
             drop(tasks);
             if to_cache {
                 cache().await?;
@@ -790,17 +2515,29 @@ pub mod global {
         }
         let tp = tasks.get_mut(&tf.id).unwrap();
 
-        if tp.task.status != Some("running".to_string()) {
+        if tp.task.status != Some("running".to_string())
+            && tp.task.status != Some("starting".to_string())
+        {
             return Err(Box::new(std::io::Error::new(
                 std::io::ErrorKind::Other,
                 format!("Task [{}] is not running", tf.id),
             )));
         }
 
-        let tx = &tp.tx.clone().unwrap();
-
         let data: Vec<u8> = data.into_bytes();
 
+        // 多实例任务写入每个实例
+        if tp.task.instances.is_some() {
+            for it in tp.instances.values() {
+                if let Some(tx) = &it.tx {
+                    tx.send(data.clone()).await?;
+                }
+            }
+            return Ok(Response::success(None));
+        }
+
+        let tx = &tp.tx.clone().unwrap();
+
         tx.send(data).await?;
 
         Ok(Response::success(None))
@@ -815,14 +2552,14 @@ pub mod global {
                 if condition.id > 0 {
                     if tasks.contains_key(&condition.id) {
                         let tp = tasks.get(&condition.id).unwrap();
-                        status.push(tp.task.clone().into());
+                        status.push(tp.status());
                     }
                 } else if condition.mat {
                     let name = condition.name.unwrap_or(String::new());
                     for (_id, tp) in tasks.iter() {
                         let regex: Regex = Regex::new(&name)?;
                         if regex.is_match(&tp.task.name) {
-                            status.push(tp.task.clone().into());
+                            status.push(tp.status());
                         }
                     }
                 } else if condition.group.is_some() {
@@ -832,14 +2569,14 @@ pub mod global {
                         if tp.task.group.is_some()
                             && regex.is_match(&tp.task.group.clone().unwrap())
                         {
-                            status.push(tp.task.clone().into());
+                            status.push(tp.status());
                         }
                     }
                 } else {
                     let name = condition.name.unwrap_or(String::new());
                     for (_id, tp) in tasks.iter() {
                         if tp.task.name == name {
-                            status.push(tp.task.clone().into());
+                            status.push(tp.status());
                         }
                     }
                 }
@@ -848,7 +2585,7 @@ pub mod global {
             None => {
                 let mut status: Vec<Status> = Vec::new();
                 for (_, tp) in tasks.iter() {
-                    status.push(tp.task.clone().into());
+                    status.push(tp.status());
                 }
                 status
             }
@@ -913,8 +2650,22 @@ pub mod global {
     use crate::common::task::Matrix;
 
     // 获取监控指标 CPU使用率、内存使用率、网络流量、磁盘IO
-    pub(crate) fn matrix(id: usize) -> Result<Matrix, Box<dyn Error>> {
-        let matrix = Matrix::new(Pid::from(id));
+    pub async fn matrix(id: usize) -> Result<Matrix, Box<dyn Error>> {
+        // 只返回任务进程的指标，进程号被复用时不是原来的任务进程
+        let tasks = TASKS.read().await;
+        let owned = tasks.values().any(|tp| tp.owns(id as u32));
+        drop(tasks);
+        if !owned {
+            return Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                format!("Process {} is not a running task", id),
+            )));
+        }
+        let mut matrix = Matrix::new(Pid::from(id));
+        // 任务在独立 cgroup 中时使用 cgroup 统计的整个进程树的用量
+        if let Some(usage) = cgroup::usage(id as u32) {
+            matrix.task_matrix = usage;
+        }
         Ok(matrix)
     }
 }
\ No newline at end of file
@@ -0,0 +1,228 @@
+use std::{
+    ffi::OsString,
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Local, NaiveDate};
+use flate2::{write::GzEncoder, Compression};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt},
+    process::Child,
+    sync::mpsc,
+};
+use tracing::warn;
+
+use crate::common::task::{LogRotate, Task};
+
+static CHANNEL_SIZE: usize = 1024;
+
+/// Log file written by the daemon, rotated by size and/or daily
+pub struct RotatingFile {
+    path: PathBuf,
+    rotate: LogRotate,
+    file: File,
+    size: u64,
+    date: NaiveDate,
+}
+
+impl RotatingFile {
+    pub fn open(path: &Path, rotate: LogRotate) -> io::Result<Self> {
+        if let Some(dir) = path.parent() {
+            if !dir.exists() {
+                std::fs::create_dir_all(dir)?;
+            }
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let meta = file.metadata()?;
+        // 已有的日志文件按最后修改日期判断是否需要按天轮转
+        let date = meta
+            .modified()
+            .map(|t| DateTime::<Local>::from(t).date_naive())
+            .unwrap_or_else(|_| Local::now().date_naive());
+        Ok(RotatingFile {
+            path: path.to_path_buf(),
+            rotate,
+            file,
+            size: meta.len(),
+            date,
+        })
+    }
+
+    /// Writing `len` bytes more needs a rotation first, an empty file is never rotated
+    pub fn should_rotate(&self, len: usize) -> bool {
+        if self.size == 0 {
+            return false;
+        }
+        let full = match self.rotate.max_size {
+            Some(max) => self.size + len as u64 > max,
+            None => false,
+        };
+        full || (self.rotate.daily && Local::now().date_naive() != self.date)
+    }
+
+    /// Write `buf` to the file, rotating it first when needed
+    pub fn write(&mut self, buf: &[u8]) -> io::Result<()> {
+        if self.should_rotate(buf.len()) {
+            self.rotate()?;
+        }
+        self.append(buf)
+    }
+
+    fn append(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.file.write_all(buf)?;
+        self.size += buf.len() as u64;
+        self.date = Local::now().date_naive();
+        Ok(())
+    }
+
+    /// Move the file to `<file>.1` and start a new file, older files are shifted and
+    /// the one beyond `max_files` is removed
+    pub fn rotate(&mut self) -> io::Result<()> {
+        let ext = if self.rotate.compress { ".gz" } else { "" };
+        let rotated = |n: u32| numbered(&self.path, n, ext);
+        let last = rotated(self.rotate.max_files);
+        if last.exists() {
+            std::fs::remove_file(&last)?;
+        }
+        for n in (1..self.rotate.max_files).rev() {
+            let from = rotated(n);
+            if from.exists() {
+                std::fs::rename(&from, rotated(n + 1))?;
+            }
+        }
+        let plain = numbered(&self.path, 1, "");
+        std::fs::rename(&self.path, &plain)?;
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.size = 0;
+        self.date = Local::now().date_naive();
+        if self.rotate.compress {
+            gzip(&plain, &rotated(1))?;
+        }
+        Ok(())
+    }
+}
+
+/// `<path>.<n><ext>`
+fn numbered(path: &Path, n: u32, ext: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(format!(".{}{}", n, ext));
+    PathBuf::from(name)
+}
+
+/// Compress `from` into `to` and remove `from`
+fn gzip(from: &Path, to: &Path) -> io::Result<()> {
+    let mut input = File::open(from)?;
+    let mut encoder = GzEncoder::new(File::create(to)?, Compression::default());
+    io::copy(&mut input, &mut encoder)?;
+    encoder.finish()?;
+    std::fs::remove_file(from)
+}
+
+/// Writers of the rotating log files of a task, opened before the process is
+/// started so a file which can't be opened doesn't leave a process behind
+#[derive(Default)]
+pub struct Writers {
+    stdout: Option<mpsc::Sender<Vec<u8>>>,
+    stderr: Option<mpsc::Sender<Vec<u8>>>,
+}
+
+impl Writers {
+    /// Open the log files of the task, no writer when the task has no `log_rotate`
+    ///
+    /// stdout and stderr sharing one file are written by the same writer, so rotations
+    /// of the file don't race.
+    pub fn open(task: &Task) -> io::Result<Self> {
+        let rotate = match &task.log_rotate {
+            Some(rotate) => rotate,
+            None => return Ok(Writers::default()),
+        };
+        let stdout = task.stdout.as_deref().filter(|path| !path.is_empty());
+        let stderr = task.stderr.as_deref().filter(|path| !path.is_empty());
+        let out_tx = match stdout {
+            Some(path) => Some(writer(path, rotate)?),
+            None => None,
+        };
+        let err_tx = match stderr {
+            Some(path) if stdout == Some(path) => out_tx.clone(),
+            Some(path) => Some(writer(path, rotate)?),
+            None => None,
+        };
+        Ok(Writers {
+            stdout: out_tx,
+            stderr: err_tx,
+        })
+    }
+
+    /// Write the piped stdout and stderr of the child to the log files
+    pub fn pipe(self, child: &mut Child) {
+        if let Some(tx) = self.stdout {
+            if let Some(reader) = child.stdout.take() {
+                forward(reader, tx);
+            }
+        }
+        if let Some(tx) = self.stderr {
+            if let Some(reader) = child.stderr.take() {
+                forward(reader, tx);
+            }
+        }
+    }
+}
+
+/// Start a coroutine writing received output to the rotating file at `path`, it exits
+/// after all senders are dropped
+fn writer(path: &str, rotate: &LogRotate) -> io::Result<mpsc::Sender<Vec<u8>>> {
+    let mut file = RotatingFile::open(Path::new(path), rotate.clone())?;
+    let (tx, mut rx) = mpsc::channel::<Vec<u8>>(CHANNEL_SIZE);
+    let path = path.to_string();
+    tokio::spawn(async move {
+        while let Some(buf) = rx.recv().await {
+            if file.should_rotate(buf.len()) {
+                // 压缩可能耗时较长，在阻塞线程中轮转
+                let res = tokio::task::spawn_blocking(move || {
+                    let res = file.rotate();
+                    (file, res)
+                })
+                .await;
+                file = match res {
+                    Ok((f, res)) => {
+                        if let Err(e) = res {
+                            warn!("Rotate log {} failed: {}", path, e);
+                        }
+                        f
+                    }
+                    Err(e) => {
+                        warn!("Rotate log {} failed: {}", path, e);
+                        return;
+                    }
+                };
+            }
+            // 轮转失败时继续写入当前文件
+            if let Err(e) = file.append(&buf) {
+                warn!("Write log {} failed: {}", path, e);
+            }
+        }
+    });
+    Ok(tx)
+}
+
+/// Start a coroutine sending output read from the pipe until it is closed
+fn forward<R: AsyncRead + Unpin + Send + 'static>(mut reader: R, tx: mpsc::Sender<Vec<u8>>) {
+    tokio::spawn(async move {
+        let mut buf = vec![0u8; 8192];
+        loop {
+            match reader.read(&mut buf).await {
+                Ok(0) | Err(_) => break,
+                Ok(n) => {
+                    if tx.send(buf[..n].to_vec()).await.is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    });
+}
@@ -1,42 +1,49 @@
-use crate::common::task::TaskFlag;
+use crate::common::cron::{resolve_local, Cron};
+use crate::common::task::{
+    MisfirePolicy, ScheduledTask, Task, TaskFlag, TaskType, DEFAULT_MISFIRE_MAX,
+};
 use chrono::Datelike;
 use chrono::Timelike;
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
 use std::time::{Duration, SystemTime};
 use tokio::time;
 use tracing::{error, info};
 
-use crate::global::{get_all, start};
+use crate::global::{get_all, get_instances, restart_instance, set_next_run, start};
+use crate::watchdog;
 
 pub async fn rerun_tasks(delay: u64) -> Result<(), Box<dyn std::error::Error>> {
     let tasks = get_all().await?;
-    let now = chrono::Local::now();
     for (id, task) in tasks {
         match task.task_type {
             crate::common::task::TaskType::Scheduled(scheduled) => {
-                let nd = chrono::NaiveDate::from_ymd_opt(
-                    scheduled.year.unwrap_or(now.year()),
-                    scheduled.month.unwrap_or(now.month()),
-                    scheduled.day.unwrap_or(now.day()),
-                );
-                let nt = chrono::NaiveTime::from_hms_opt(
-                    scheduled.hour.unwrap_or(now.hour()),
-                    scheduled.minute.unwrap_or(now.minute()),
-                    scheduled.second.unwrap_or(now.second()),
-                );
-                match (nd, nt) {
-                    (Some(nd), Some(nt)) => {
-                        let exec = chrono::NaiveDateTime::new(nd, nt);
-                        let exec_timestamp_utc = exec.and_utc().timestamp();
-                        let now_timestamp_utc = now.naive_local().and_utc().timestamp();
-                        let diff = (exec_timestamp_utc - now_timestamp_utc).abs() as u64;
-                        if diff < delay && exec_timestamp_utc <= now_timestamp_utc {
+                let tz = match scheduled.tz() {
+                    Ok(tz) => tz,
+                    Err(e) => {
+                        error!("Invalid scheduled task {}: {}", id, e);
+                        continue;
+                    }
+                };
+                if let Some(cron) = &scheduled.cron {
+                    schedule_cron(id, cron, tz, scheduled.next_run, delay).await?;
+                    continue;
+                }
+                // 在任务时区中计算本次执行时间，未配置的字段使用该时区的当前值
+                let now = Utc::now();
+                let exec = match tz {
+                    Some(tz) => fixed_time(&scheduled, &now.with_timezone(&tz)),
+                    None => fixed_time(&scheduled, &now.with_timezone(&Local)),
+                };
+                match exec {
+                    Some(exec) => {
+                        let now = now.timestamp();
+                        let diff = (exec - now).unsigned_abs();
+                        if diff < delay && exec <= now {
                             if let Some(status) = task.status {
                                 if status == "waiting" {
                                     tokio::spawn(async move {
                                         info!("Execute scheduled task: {}", id);
-                                        let mut interval =
-                                            time::interval(Duration::from_secs(diff));
-                                        interval.tick().await;
                                         let _ = start(TaskFlag {
                                             id,
                                             name: None,
@@ -49,15 +56,19 @@ pub async fn rerun_tasks(delay: u64) -> Result<(), Box<dyn std::error::Error>> {
                             }
                         }
                     }
-                    _ => {
+                    None => {
                         error!("Invalid scheduled task: {}", id);
                     }
                 }
             }
-            crate::common::task::TaskType::Async(_) => {
-                if let Some(status) = task.status {
-                    // 用来处理启动时的重试
-                    if status == "auto restart" {
+            crate::common::task::TaskType::Async(ref tt) => {
+                if let Some(status) = task.status.clone() {
+                    // 用来处理启动时的重试，需要等待退避时间结束
+                    let now = SystemTime::now()
+                        .duration_since(SystemTime::UNIX_EPOCH)
+                        .expect("Failed to get timestamp")
+                        .as_secs();
+                    if status == "auto restart" && now >= tt.next_restart_at {
                         info!("Restart task: {}", id);
                         start(TaskFlag {
                             id,
@@ -67,10 +78,8 @@ pub async fn rerun_tasks(delay: u64) -> Result<(), Box<dyn std::error::Error>> {
                         })
                         .await?;
                     }
-                    // 用来处理意外关闭的重启。 143 对应 `kill -15` 也就是说 kill -15 会被处理为正常关闭
-                    // 因为 watchmen 的网页控制台，停止进程是通过 kill -15 来停止进程
-                    // 如果是 143，说明是 kill -15，那么就不需要重启了
-                    if status == "stopped" && task.code != Some(143) {
+                    // 用来处理意外关闭的重启，重启次数已用完（例如 max_restart = 0）的任务不再恢复
+                    if status == "stopped" && task.should_recover() {
                         info!("Recover task: {} from unexpected shutdown", id);
                         start(TaskFlag {
                             id,
@@ -89,28 +98,16 @@ pub async fn rerun_tasks(delay: u64) -> Result<(), Box<dyn std::error::Error>> {
                     .as_secs();
                 if now >= tt.started_after && now - tt.last_run >= tt.interval {
                     if let Some(status) = task.status {
-                        if tt.sync {
-                            if status == "interval" || status == "executing" {
-                                info!("Execute periodic task: {}", id);
-                                start(TaskFlag {
-                                    id,
-                                    name: None,
-                                    group: None,
-                                    mat: false,
-                                })
-                                .await?;
-                            }
-                        } else {
-                            if status == "interval" {
-                                info!("Execute periodic task: {}", id);
-                                start(TaskFlag {
-                                    id,
-                                    name: None,
-                                    group: None,
-                                    mat: false,
-                                })
-                                .await?;
-                            }
+                        // 上一次执行未结束时由并发策略决定是否执行
+                        if status == "interval" || status == "executing" {
+                            info!("Execute periodic task: {}", id);
+                            start(TaskFlag {
+                                id,
+                                name: None,
+                                group: None,
+                                mat: false,
+                            })
+                            .await?;
                         }
                     }
                 }
@@ -118,9 +115,211 @@ pub async fn rerun_tasks(delay: u64) -> Result<(), Box<dyn std::error::Error>> {
             crate::common::task::TaskType::None => {}
         }
     }
+
+    // 多实例任务的实例单独按照退避时间重启
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .expect("Failed to get timestamp")
+        .as_secs();
+    for task in get_instances().await {
+        if let (Some(instance), TaskType::Async(tt)) = (task.instance, &task.task_type) {
+            if task.status.as_deref() == Some("auto restart") && now >= tt.next_restart_at {
+                info!("Restart task: {} instance: {}", task.id, instance);
+                restart_instance(task.id, instance).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Fire time of the fixed-field schedule as unix timestamp, fields not set take
+/// their current value in the time zone of `now`
+fn fixed_time<Tz: TimeZone>(scheduled: &ScheduledTask, now: &DateTime<Tz>) -> Option<i64> {
+    let nd = NaiveDate::from_ymd_opt(
+        scheduled.year.unwrap_or(now.year()),
+        scheduled.month.unwrap_or(now.month()),
+        scheduled.day.unwrap_or(now.day()),
+    )?;
+    let nt = NaiveTime::from_hms_opt(
+        scheduled.hour.unwrap_or(now.hour()),
+        scheduled.minute.unwrap_or(now.minute()),
+        scheduled.second.unwrap_or(now.second()),
+    )?;
+    resolve_local(&now.timezone(), NaiveDateTime::new(nd, nt)).map(|t| t.timestamp())
+}
+
+/// Next fire time of the cron expression in time zone `tz` after unix timestamp `after`
+fn next_fire<Tz: TimeZone>(cron: &Cron, tz: &Tz, after: i64) -> Option<i64> {
+    let after = tz.timestamp_opt(after, 0).single()?;
+    cron.next_after_in(&after).map(|t| t.timestamp())
+}
+
+/// Arm a timer firing the cron task at its exact next fire time when it is due
+/// before the next check
+async fn schedule_cron(
+    id: i64,
+    expr: &str,
+    tz: Option<Tz>,
+    next_run: Option<i64>,
+    delay: u64,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let cron: Cron = expr.parse()?;
+    let next = |after: i64| match &tz {
+        Some(tz) => next_fire(&cron, tz, after),
+        None => next_fire(&cron, &Local, after),
+    };
+    let now = Utc::now();
+    // 守护进程停止期间错过的执行不再补执行
+    let next_run = match next_run {
+        Some(ts) if ts + delay as i64 >= now.timestamp() => ts,
+        _ => match next(now.timestamp()) {
+            Some(ts) => ts,
+            None => return set_next_run(id, None).await,
+        },
+    };
+    if next_run - now.timestamp() > delay as i64 {
+        return set_next_run(id, Some(next_run)).await;
+    }
+
+    // 定时器负责本次执行，记录的下次执行时间推进到之后的一次
+    set_next_run(id, next(next_run)).await?;
+    let at = DateTime::from_timestamp(next_run, 0).unwrap_or(now);
+    let wait = (at - now).to_std().unwrap_or(Duration::ZERO);
+    tokio::spawn(async move {
+        time::sleep(wait).await;
+        let status = match get_all().await.map(|mut tasks| tasks.remove(&id)) {
+            Ok(Some(task)) => task.status,
+            _ => return,
+        };
+        if status.as_deref() != Some("waiting") {
+            info!("Skip scheduled task [{}] in status {:?}", id, status);
+            return;
+        }
+        info!("Execute scheduled task: {}", id);
+        if let Err(e) = start(TaskFlag {
+            id,
+            name: None,
+            group: None,
+            mat: false,
+        })
+        .await
+        .map_err(|e| e.to_string())
+        {
+            error!("Execute scheduled task [{}] failed: {}", id, e);
+        }
+    });
     Ok(())
 }
 
+/// Fire times of the task missed between its last run and `now`, oldest first
+///
+/// Only times within `misfire_grace` are counted, at most `limit` times are returned.
+pub fn missed_runs(task: &Task, now: i64, limit: usize) -> Vec<i64> {
+    let grace_start = task
+        .misfire_grace
+        .map(|grace| now - grace as i64)
+        .unwrap_or(i64::MIN);
+    match &task.task_type {
+        TaskType::Scheduled(tt) => {
+            let from = tt.last_run.unwrap_or(task.created_at as i64).max(grace_start);
+            let cron = match tt.to_cron() {
+                Ok(cron) => cron,
+                Err(_) => return Vec::new(),
+            };
+            match tt.tz() {
+                Ok(Some(tz)) => scheduled_runs(&cron, &tz, tt.year, from, now, limit),
+                Ok(None) => scheduled_runs(&cron, &Local, tt.year, from, now, limit),
+                Err(_) => Vec::new(),
+            }
+        }
+        TaskType::Periodic(tt) if tt.last_run > 0 && tt.interval > 0 => {
+            let interval = tt.interval as i64;
+            let mut t = tt.last_run as i64 + interval;
+            if t < grace_start {
+                t += (grace_start - t + interval - 1) / interval * interval;
+            }
+            let mut runs = Vec::new();
+            while t <= now && runs.len() < limit {
+                if t >= tt.started_after as i64 {
+                    runs.push(t);
+                }
+                t += interval;
+            }
+            runs
+        }
+        _ => Vec::new(),
+    }
+}
+
+fn scheduled_runs<Tz: TimeZone>(
+    cron: &Cron,
+    tz: &Tz,
+    year: Option<i32>,
+    from: i64,
+    now: i64,
+    limit: usize,
+) -> Vec<i64> {
+    let mut runs = Vec::new();
+    let mut after = match tz.timestamp_opt(from, 0).single() {
+        Some(after) => after,
+        None => return runs,
+    };
+    while runs.len() < limit {
+        let t = match cron.next_after_in(&after) {
+            Some(t) if t.timestamp() <= now => t,
+            _ => break,
+        };
+        match year {
+            Some(year) if t.year() > year => break,
+            Some(year) if t.year() < year => {}
+            _ => runs.push(t.timestamp()),
+        }
+        after = t;
+    }
+    runs
+}
+
+/// Decide how many runs of the task missed while the daemon was down are executed
+/// now, following its misfire policy
+///
+/// The last run of the task is moved forward so that the monitor does not run the
+/// missed runs again.
+pub fn misfire(task: &mut Task, now: i64) -> usize {
+    let max = task.misfire_max.unwrap_or(DEFAULT_MISFIRE_MAX) as usize;
+    let missed = match (&task.task_type, task.status.as_deref()) {
+        (TaskType::Scheduled(_), Some("waiting")) | (TaskType::Periodic(_), Some("interval")) => {
+            missed_runs(task, now, max.max(1))
+        }
+        _ => return 0,
+    };
+    match &mut task.task_type {
+        TaskType::Scheduled(tt) => tt.last_run = Some(now),
+        // 对齐到最近一次应执行的时间，之后按原来的间隔继续执行
+        TaskType::Periodic(tt) if tt.last_run > 0 && tt.interval > 0 => {
+            let elapsed = (now as u64).saturating_sub(tt.last_run);
+            tt.last_run += elapsed / tt.interval * tt.interval;
+        }
+        _ => {}
+    }
+    if missed.is_empty() {
+        return 0;
+    }
+    let policy = task.misfire_policy();
+    let runs = match policy {
+        MisfirePolicy::Skip => 0,
+        MisfirePolicy::RunOnce => 1,
+        MisfirePolicy::RunAll => missed.len().min(max),
+    };
+    info!(
+        "Task [{}] missed {} runs while the daemon was down, misfire {:?}, run {} times",
+        task.id,
+        missed.len(),
+        policy,
+        runs
+    );
+    runs
+}
+
 pub async fn run_monitor(delay: Option<u64>) -> Result<(), Box<dyn std::error::Error>> {
     let delay = delay.unwrap_or(5);
     let mut interval = time::interval(Duration::from_secs(delay));
@@ -131,6 +330,9 @@ pub async fn run_monitor(delay: Option<u64>) -> Result<(), Box<dyn std::error::E
                 error!("Monitor tasks error: {}", e);
             }
         }
+        if let Err(e) = watchdog::check().await {
+            error!("Watchdog error: {}", e);
+        }
         interval.tick().await;
     }
 }
@@ -0,0 +1,168 @@
+use std::{
+    error::Error,
+    sync::OnceLock,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::Serialize;
+use tokio::time;
+use tracing::warn;
+
+use crate::common::config::{Notifier, NotifyEvent};
+use crate::common::task::Task;
+
+static DEFAULT_RETRIES: u32 = 3;
+static DEFAULT_BACKOFF_MS: u64 = 1000;
+static REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+static NOTIFIERS: OnceLock<Vec<Notifier>> = OnceLock::new();
+
+/// Event of a task posted to notifiers
+#[derive(Debug, Clone, Serialize)]
+pub struct Notification {
+    pub event: NotifyEvent,
+    pub id: i64,
+    pub name: String,
+    pub group: Option<String>,
+    pub code: Option<i32>,
+    pub message: String,
+    pub timestamp: u64,
+}
+
+impl Notification {
+    pub fn new(event: NotifyEvent, task: &Task, code: Option<i32>, message: String) -> Self {
+        Notification {
+            event,
+            id: task.id,
+            name: task.name.clone(),
+            group: task.group.clone(),
+            code,
+            message,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("Failed to get timestamp")
+                .as_secs(),
+        }
+    }
+
+    /// Request body of the notifier, see [`render`]
+    fn body(&self, notifier: &Notifier) -> String {
+        match &notifier.body {
+            Some(template) => render(template, self),
+            None => serde_json::to_string(self).unwrap_or_default(),
+        }
+    }
+}
+
+/// Replace `{{ key }}` in the JSON template with fields of the notification
+///
+/// Strings are JSON escaped without the quotes, so they can be placed inside a JSON
+/// string. Unknown keys are kept as is.
+pub fn render(template: &str, notification: &Notification) -> String {
+    let escape = |s: &str| {
+        let quoted = serde_json::to_string(s).unwrap_or_default();
+        quoted[1..quoted.len() - 1].to_string()
+    };
+    let mut output = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        output.push_str(&rest[..start]);
+        let end = match rest[start..].find("}}") {
+            Some(end) => start + end,
+            None => break,
+        };
+        let value = match rest[start + 2..end].trim() {
+            "event" => notification.event.to_string(),
+            "id" => notification.id.to_string(),
+            "name" => escape(&notification.name),
+            "group" => escape(notification.group.as_deref().unwrap_or_default()),
+            "code" => match notification.code {
+                Some(code) => code.to_string(),
+                None => "null".to_string(),
+            },
+            "message" => escape(&notification.message),
+            "timestamp" => notification.timestamp.to_string(),
+            _ => rest[start..end + 2].to_string(),
+        };
+        output.push_str(&value);
+        rest = &rest[end + 2..];
+    }
+    output.push_str(rest);
+    output
+}
+
+/// Set notifiers of the daemon, the body template must render valid JSON
+pub fn init(notifiers: Vec<Notifier>) -> Result<(), Box<dyn Error>> {
+    let sample = Notification {
+        event: NotifyEvent::Exited,
+        id: 0,
+        name: String::new(),
+        group: None,
+        code: None,
+        message: String::new(),
+        timestamp: 0,
+    };
+    for notifier in &notifiers {
+        if !notifier.url.starts_with("http://") && !notifier.url.starts_with("https://") {
+            return Err(format!("Invalid notifier url: {}", notifier.url).into());
+        }
+        serde_json::from_str::<serde_json::Value>(&sample.body(notifier))
+            .map_err(|e| format!("Invalid body template of notifier {}: {}", notifier.url, e))?;
+    }
+    let _ = NOTIFIERS.set(notifiers);
+    Ok(())
+}
+
+/// Post the event of the task to notifiers subscribing it in background
+pub fn send(event: NotifyEvent, task: &Task, code: Option<i32>, message: String) {
+    let notifiers = match NOTIFIERS.get() {
+        Some(notifiers) => notifiers,
+        None => return,
+    };
+    let notification = Notification::new(event, task, code, message);
+    for notifier in notifiers {
+        if !notifier.events.is_empty() && !notifier.events.contains(&event) {
+            continue;
+        }
+        let notifier = notifier.clone();
+        let body = notification.body(&notifier);
+        let id = task.id;
+        tokio::spawn(async move {
+            if let Err(e) = post(&notifier, body).await {
+                warn!("Notify {} of task [{}] to {} failed: {}", event, id, notifier.url, e);
+            }
+        });
+    }
+}
+
+/// Post the body, failed requests are retried with exponential backoff
+async fn post(notifier: &Notifier, body: String) -> Result<(), Box<dyn Error>> {
+    let client = reqwest::Client::builder().timeout(REQUEST_TIMEOUT).build()?;
+    let retries = notifier.retries.unwrap_or(DEFAULT_RETRIES);
+    let mut backoff = Duration::from_millis(notifier.backoff_ms.unwrap_or(DEFAULT_BACKOFF_MS));
+    let mut attempt = 0;
+    loop {
+        let mut request = client
+            .post(&notifier.url)
+            .header("Content-Type", "application/json")
+            .body(body.clone());
+        for (key, value) in &notifier.headers {
+            request = request.header(key, value);
+        }
+        let err = match request.send().await {
+            Ok(res) if res.status().is_success() => return Ok(()),
+            Ok(res) => format!("responded {}", res.status()),
+            Err(e) => e.to_string(),
+        };
+        if attempt >= retries {
+            return Err(err.into());
+        }
+        attempt += 1;
+        warn!(
+            "Notify {} failed ({}/{}): {}, retry after {:?}",
+            notifier.url, attempt, retries, err, backoff
+        );
+        time::sleep(backoff).await;
+        backoff *= 2;
+    }
+}
@@ -0,0 +1,431 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+    os::unix::process::ExitStatusExt,
+    path::Path,
+    process::ExitStatus,
+    time::Duration,
+};
+
+use tokio::{io::unix::AsyncFd, task::JoinHandle, time};
+use tracing::{info, warn};
+
+/// Default time to wait for a task to exit after the stop signal, in seconds
+pub static DEFAULT_STOP_TIMEOUT: u64 = 10;
+
+/// Default stop signal
+pub static DEFAULT_STOP_SIGNAL: &str = "SIGTERM";
+
+static SIGNALS: [(&str, i32); 12] = [
+    ("HUP", libc::SIGHUP),
+    ("INT", libc::SIGINT),
+    ("QUIT", libc::SIGQUIT),
+    ("ABRT", libc::SIGABRT),
+    ("KILL", libc::SIGKILL),
+    ("USR1", libc::SIGUSR1),
+    ("USR2", libc::SIGUSR2),
+    ("PIPE", libc::SIGPIPE),
+    ("ALRM", libc::SIGALRM),
+    ("TERM", libc::SIGTERM),
+    ("CONT", libc::SIGCONT),
+    ("STOP", libc::SIGSTOP),
+];
+
+/// Parse signal name or number
+///
+/// Accepts `SIGTERM`, `TERM`, `term` or `15`
+pub fn parse_signal(input: &str) -> Result<i32, Box<dyn Error>> {
+    let input = input.trim();
+    if let Ok(num) = input.parse::<i32>() {
+        if num > 0 && num < 32 {
+            return Ok(num);
+        }
+    }
+    let name = input.to_uppercase();
+    let name = name.strip_prefix("SIG").unwrap_or(&name);
+    SIGNALS
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, s)| *s)
+        .ok_or_else(|| format!("Invalid signal: {}", input).into())
+}
+
+/// Send signal to process, `Ok(false)` when the process does not exist
+pub fn signal(pid: u32, sig: i32) -> Result<bool, Box<dyn Error>> {
+    let res = unsafe { libc::kill(pid as libc::pid_t, sig) };
+    if res == 0 {
+        return Ok(true);
+    }
+    let err = std::io::Error::last_os_error();
+    if err.raw_os_error() == Some(libc::ESRCH) {
+        Ok(false)
+    } else {
+        Err(Box::new(err))
+    }
+}
+
+/// Check process exists
+pub fn is_alive(pid: u32) -> bool {
+    signal(pid, 0).unwrap_or(true)
+}
+
+/// Exit code of the child, signal terminated process is recorded as `128 + signal` like shells do
+pub fn exit_code(status: &ExitStatus) -> Option<i32> {
+    match status.code() {
+        Some(code) => Some(code),
+        None => status.signal().map(|sig| 128 + sig),
+    }
+}
+
+/// Fields of `/proc/<pid>/stat` used by watchmen
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProcStat {
+    pub pid: u32,
+    pub ppid: u32,
+    pub pgrp: i32,
+    pub session: i32,
+    /// Time the process started after system boot, in clock ticks
+    pub starttime: u64,
+}
+
+/// Read `/proc/<pid>/stat`
+pub fn proc_stat(pid: u32) -> Option<ProcStat> {
+    let content = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // comm 字段可能包含空格和括号，从最后一个 `)` 之后开始解析
+    let rest = &content[content.rfind(')')? + 2..];
+    let fields: Vec<&str> = rest.split_whitespace().collect();
+    Some(ProcStat {
+        pid,
+        ppid: fields.get(1)?.parse().ok()?,
+        pgrp: fields.get(2)?.parse().ok()?,
+        session: fields.get(3)?.parse().ok()?,
+        starttime: fields.get(19)?.parse().ok()?,
+    })
+}
+
+/// Start time of the process after system boot, in clock ticks
+///
+/// Together with the pid it identifies a process, a reused pid has a different start time.
+pub fn start_time(pid: u32) -> Option<u64> {
+    proc_stat(pid).map(|stat| stat.starttime)
+}
+
+/// Arguments of the process from `/proc/<pid>/cmdline`
+pub fn cmdline(pid: u32) -> Option<Vec<String>> {
+    let content = std::fs::read(format!("/proc/{}/cmdline", pid)).ok()?;
+    Some(
+        content
+            .split(|b| *b == 0)
+            .filter(|arg| !arg.is_empty())
+            .map(|arg| String::from_utf8_lossy(arg).to_string())
+            .collect(),
+    )
+}
+
+/// Process `pid` is still the one started at `starttime` running `command` with `args`
+///
+/// Scripts started through a shebang have the interpreter before the command in
+/// their command line, so only the tail of the command line is compared.
+pub fn is_same(pid: u32, starttime: u64, command: &str, args: &[String]) -> bool {
+    if start_time(pid) != Some(starttime) {
+        return false;
+    }
+    let cmdline = match cmdline(pid) {
+        Some(cmdline) if cmdline.len() > args.len() => cmdline,
+        _ => return false,
+    };
+    let (head, tail) = cmdline.split_at(cmdline.len() - args.len());
+    let name = |c: &str| Path::new(c).file_name().map(|n| n.to_os_string());
+    tail == args && name(&head[head.len() - 1]) == name(command)
+}
+
+/// Wait until the process `pid` started at `starttime` exits
+///
+/// Used for processes that are not children of the daemon (e.g. adopted after a
+/// daemon restart), their exit code can not be collected.
+pub async fn wait_exit(pid: u32, starttime: u64) {
+    if let Some(fd) = pidfd_open(pid) {
+        // pidfd 打开后进程号不会被复用，再次确认是同一个进程
+        if start_time(pid) != Some(starttime) {
+            return;
+        }
+        // SAFETY: pidfd 由 AsyncFd 持有，注册期间不会被关闭或替换
+        match unsafe { AsyncFd::register(fd) } {
+            // 进程退出时 pidfd 变为可读
+            Ok(fd) => {
+                let _ = fd.readable().await;
+                return;
+            }
+            Err(e) => warn!("Watch pidfd of process {} failed: {}", pid, e),
+        }
+    }
+    // 内核不支持 pidfd 时轮询进程是否存在
+    while start_time(pid) == Some(starttime) {
+        time::sleep(Duration::from_millis(500)).await;
+    }
+}
+
+/// Open a pidfd referring to the process, `None` when the kernel does not support it
+pub fn pidfd_open(pid: u32) -> Option<OwnedFd> {
+    let fd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid as libc::pid_t, 0) };
+    if fd < 0 {
+        return None;
+    }
+    Some(unsafe { OwnedFd::from_raw_fd(fd as RawFd) })
+}
+
+/// All descendants of the process found by walking `/proc`, including those that
+/// escaped into another process group or session
+pub fn descendants(pid: u32) -> Vec<u32> {
+    descendants_in(&children(), pid)
+}
+
+/// Children of every process found by walking `/proc` once, keyed by parent pid
+pub fn children() -> HashMap<u32, Vec<u32>> {
+    let mut children: HashMap<u32, Vec<u32>> = HashMap::new();
+    if let Ok(entries) = std::fs::read_dir("/proc") {
+        for entry in entries.flatten() {
+            let child = match entry.file_name().to_str().and_then(|s| s.parse::<u32>().ok()) {
+                Some(child) => child,
+                None => continue,
+            };
+            if let Some(stat) = proc_stat(child) {
+                children.entry(stat.ppid).or_default().push(child);
+            }
+        }
+    }
+    children
+}
+
+/// Descendants of the process in the map built by [`children`]
+pub fn descendants_in(children: &HashMap<u32, Vec<u32>>, pid: u32) -> Vec<u32> {
+    let mut res = Vec::new();
+    let mut queue = vec![pid];
+    while let Some(parent) = queue.pop() {
+        if let Some(cs) = children.get(&parent) {
+            for c in cs {
+                res.push(*c);
+                queue.push(*c);
+            }
+        }
+    }
+    res
+}
+
+/// Process `pid` started at `starttime` exited and the pid belongs to another process now
+pub fn is_replaced(pid: u32, starttime: Option<u64>) -> bool {
+    match (starttime, start_time(pid)) {
+        (Some(starttime), Some(now)) => starttime != now,
+        _ => false,
+    }
+}
+
+/// Process of a task identified by its pid and start time
+///
+/// Signals are sent through a pidfd when the kernel supports it, otherwise the
+/// start time is checked before each signal, so a process reusing the pid is
+/// never signalled.
+pub struct ProcessHandle {
+    pub pid: u32,
+    /// `None` for processes recorded by older versions, the pid is trusted
+    starttime: Option<u64>,
+    pidfd: Option<OwnedFd>,
+}
+
+impl ProcessHandle {
+    pub fn new(pid: u32, starttime: Option<u64>) -> Self {
+        let pidfd = pidfd_open(pid);
+        let mut handle = ProcessHandle {
+            pid,
+            starttime,
+            pidfd,
+        };
+        // pidfd 打开之后再确认启动时间，此后 pidfd 始终指向同一个进程
+        if !handle.is_same() {
+            handle.pidfd = None;
+        }
+        handle
+    }
+
+    /// The pid still belongs to the process, `false` when it exited or the pid was reused
+    fn is_same(&self) -> bool {
+        match self.starttime {
+            Some(starttime) => start_time(self.pid) == Some(starttime),
+            None => true,
+        }
+    }
+
+    /// The pid belongs to another process now
+    pub fn is_replaced(&self) -> bool {
+        is_replaced(self.pid, self.starttime)
+    }
+
+    /// Send signal to the process, `Ok(false)` when the process does not exist
+    pub fn signal(&self, sig: i32) -> Result<bool, Box<dyn Error>> {
+        if let Some(fd) = &self.pidfd {
+            let res = unsafe {
+                libc::syscall(
+                    libc::SYS_pidfd_send_signal,
+                    fd.as_raw_fd(),
+                    sig,
+                    std::ptr::null::<libc::siginfo_t>(),
+                    0,
+                )
+            };
+            if res == 0 {
+                return Ok(true);
+            }
+            let err = std::io::Error::last_os_error();
+            return if err.raw_os_error() == Some(libc::ESRCH) {
+                Ok(false)
+            } else {
+                Err(Box::new(err))
+            };
+        }
+        if !self.is_same() {
+            return Ok(false);
+        }
+        signal(self.pid, sig)
+    }
+
+    /// Check process exists
+    pub fn is_alive(&self) -> bool {
+        self.signal(0).unwrap_or(true)
+    }
+
+    /// Send signal to the process group led by the process, fall back to the process
+    /// itself when it is not a group leader (e.g. started by an older watchmen)
+    pub fn signal_group(&self, sig: i32) -> Result<bool, Box<dyn Error>> {
+        // 进程组存在时组号不会被分配给新进程，进程号被复用说明进程组已不存在
+        if self.is_replaced() {
+            return Ok(false);
+        }
+        match proc_stat(self.pid) {
+            Some(stat) if stat.pgrp == self.pid as i32 => signal_pgid(self.pid, sig),
+            Some(_) => self.signal(sig),
+            // 组长已退出，组内可能还有其他进程
+            None => signal_pgid(self.pid, sig),
+        }
+    }
+}
+
+/// Send signal to all processes in the process group `pgid`
+pub fn signal_pgid(pgid: u32, sig: i32) -> Result<bool, Box<dyn Error>> {
+    let res = unsafe { libc::kill(-(pgid as libc::pid_t), sig) };
+    if res == 0 {
+        return Ok(true);
+    }
+    let err = std::io::Error::last_os_error();
+    if err.raw_os_error() == Some(libc::ESRCH) {
+        Ok(false)
+    } else {
+        Err(Box::new(err))
+    }
+}
+
+/// Stop process tree gracefully
+///
+/// Send `sig` to the process group of the task (and the escaped descendants when
+/// `sweep` is set) and wait for them to exit. Whatever is still alive after
+/// `timeout` gets `SIGKILL`.
+///
+/// # params
+///
+/// - `pid`: process id of the task, also the process group id
+/// - `starttime`: start time of the process, nothing is signalled when the pid was reused
+/// - `sig`: signal sent first
+/// - `timeout`: time to wait before `SIGKILL`
+/// - `joinhandle`: handle of the coroutine waiting for the child, used to get the real exit code
+/// - `sweep`: also signal descendants found in `/proc` that left the process group
+pub async fn terminate(
+    pid: u32,
+    starttime: Option<u64>,
+    sig: i32,
+    timeout: Duration,
+    joinhandle: Option<JoinHandle<Option<i32>>>,
+    sweep: bool,
+) -> Result<Option<i32>, Box<dyn Error>> {
+    let deadline = time::Instant::now() + timeout;
+    let process = ProcessHandle::new(pid, starttime);
+    if process.is_replaced() {
+        warn!("Process {} exited and its pid was reused, skip signalling", pid);
+        return Ok(match joinhandle {
+            Some(jh) => jh.await.unwrap_or(None),
+            None => None,
+        });
+    }
+
+    // 必须在发送信号之前收集子孙进程，父进程退出后子进程会被 init 收养
+    let escaped: Vec<ProcessHandle> = if sweep {
+        descendants(pid)
+            .into_iter()
+            .filter_map(|p| proc_stat(p).filter(|s| s.pgrp != pid as i32))
+            .map(|s| ProcessHandle::new(s.pid, Some(s.starttime)))
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    if !process.signal_group(sig)? {
+        info!("Process {} already exited", pid);
+    }
+    // 逃逸的子孙进程可能已经切换了用户，信号发送失败时继续停止任务
+    for p in &escaped {
+        if let Err(e) = p.signal(sig) {
+            warn!(
+                "Signal descendant {} of process {} failed: {}",
+                p.pid, pid, e
+            );
+        }
+    }
+
+    let code = match joinhandle {
+        Some(mut jh) => match time::timeout_at(deadline, &mut jh).await {
+            Ok(res) => res.unwrap_or(None),
+            Err(_) => {
+                warn!("Process {} still alive after {:?}, send SIGKILL", pid, timeout);
+                process.signal_group(libc::SIGKILL)?;
+                jh.await.unwrap_or(None)
+            }
+        },
+        None => {
+            // 不是当前守护进程的子进程，无法获取退出码，只能轮询进程是否存在
+            let mut code = None;
+            while process.is_alive() {
+                if time::Instant::now() >= deadline {
+                    warn!("Process {} still alive after {:?}, send SIGKILL", pid, timeout);
+                    process.signal_group(libc::SIGKILL)?;
+                    code = Some(128 + libc::SIGKILL);
+                    break;
+                }
+                time::sleep(Duration::from_millis(100)).await;
+            }
+            code
+        }
+    };
+
+    // 主进程退出后，继续等待同组及逃逸的子孙进程退出
+    loop {
+        let group_alive = !process.is_replaced() && signal_pgid(pid, 0).unwrap_or(false);
+        let alive: Vec<&ProcessHandle> = escaped.iter().filter(|p| p.is_alive()).collect();
+        if !group_alive && alive.is_empty() {
+            break;
+        }
+        if time::Instant::now() >= deadline {
+            warn!("Descendants of process {} still alive, send SIGKILL", pid);
+            if group_alive {
+                signal_pgid(pid, libc::SIGKILL)?;
+            }
+            for p in alive {
+                if let Err(e) = p.signal(libc::SIGKILL) {
+                    warn!("Kill descendant {} of process {} failed: {}", p.pid, pid, e);
+                }
+            }
+            break;
+        }
+        time::sleep(Duration::from_millis(100)).await;
+    }
+
+    Ok(code)
+}
@@ -0,0 +1,207 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    path::{Component, Path, PathBuf},
+    sync::Mutex,
+    time::Duration,
+};
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use lazy_static::lazy_static;
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::{sync::mpsc, time};
+use tracing::{info, warn};
+
+use crate::common::task::Task;
+use crate::global;
+
+/// Default milliseconds to wait for more changes before the restart
+pub const DEFAULT_WATCH_DEBOUNCE_MS: u64 = 500;
+
+lazy_static! {
+    // 每个任务一个文件监控，多实例任务共用
+    static ref WATCHERS: Mutex<HashMap<i64, Watching>> = Mutex::new(HashMap::new());
+}
+
+/// Watch config of a task, the watcher is kept while it is unchanged
+type WatchConfig = (Vec<String>, Vec<String>, Option<u64>, Option<String>);
+
+struct Watching {
+    config: WatchConfig,
+    // 丢弃后停止监控，事件通道随之关闭
+    _watcher: RecommendedWatcher,
+}
+
+/// Files matched by `watch` and not by `watch_ignore` of a task
+pub struct Matcher {
+    base: PathBuf,
+    roots: Vec<(PathBuf, RecursiveMode)>,
+    include: GlobSet,
+    ignore: GlobSet,
+    ignore_names: GlobSet,
+}
+
+impl Matcher {
+    pub fn new(task: &Task) -> Result<Self, Box<dyn Error>> {
+        let base = match &task.dir {
+            Some(dir) => PathBuf::from(dir),
+            None => std::env::current_dir()?,
+        };
+        let mut roots = Vec::new();
+        let mut include = GlobSetBuilder::new();
+        for entry in task.watch.iter().flatten() {
+            let path = base.join(entry);
+            // 第一个含通配符的部分之前的目录为监控目录
+            let mut root = PathBuf::new();
+            let mut pattern = false;
+            for component in path.components() {
+                if is_glob(&component.as_os_str().to_string_lossy()) {
+                    pattern = true;
+                    break;
+                }
+                root.push(component);
+            }
+            if pattern {
+                include.add(glob(&path)?);
+                roots.push((root, RecursiveMode::Recursive));
+            } else if path.is_dir() {
+                include.add(glob(&path.join("**"))?);
+                roots.push((path, RecursiveMode::Recursive));
+            } else {
+                // 编辑器保存时可能替换文件，监控所在目录
+                include.add(glob(&path)?);
+                let parent = path.parent().map(Path::to_path_buf).unwrap_or_default();
+                roots.push((parent, RecursiveMode::NonRecursive));
+            }
+        }
+        let mut ignore = GlobSetBuilder::new();
+        let mut ignore_names = GlobSetBuilder::new();
+        for entry in &task.watch_ignore {
+            // 不含路径分隔符时匹配任意一级文件或目录名
+            if entry.contains('/') {
+                ignore.add(glob(&base.join(entry))?);
+            } else {
+                ignore_names.add(glob(Path::new(entry))?);
+            }
+        }
+        Ok(Matcher {
+            base,
+            roots,
+            include: include.build()?,
+            ignore: ignore.build()?,
+            ignore_names: ignore_names.build()?,
+        })
+    }
+
+    /// Whether a change of `path` restarts the task
+    pub fn is_match(&self, path: &Path) -> bool {
+        if !self.include.is_match(path) || self.ignore.is_match(path) {
+            return false;
+        }
+        let relative = path.strip_prefix(&self.base).unwrap_or(path);
+        !relative.components().any(|c| match c {
+            Component::Normal(name) => self.ignore_names.is_match(name),
+            _ => false,
+        })
+    }
+}
+
+fn is_glob(s: &str) -> bool {
+    s.contains(['*', '?', '[', '{'])
+}
+
+fn glob(path: &Path) -> Result<Glob, Box<dyn Error>> {
+    let glob = globset::GlobBuilder::new(&path.to_string_lossy())
+        .literal_separator(true)
+        .build()?;
+    Ok(glob)
+}
+
+/// Watch files of the task, a change restarts the running task after
+/// `watch_debounce_ms` without further changes
+///
+/// Called each time a process of the task starts, the watcher is kept when
+/// the config is unchanged and replaced otherwise.
+pub fn register(task: &Task) {
+    if task.watch.as_ref().map(|w| w.is_empty()).unwrap_or(true) {
+        unregister(task.id);
+        return;
+    }
+    let config: WatchConfig = (
+        task.watch.clone().unwrap_or_default(),
+        task.watch_ignore.clone(),
+        task.watch_debounce_ms,
+        task.dir.clone(),
+    );
+    let mut watchers = WATCHERS.lock().unwrap();
+    if watchers.get(&task.id).map(|w| w.config == config) == Some(true) {
+        return;
+    }
+    watchers.remove(&task.id);
+    match watch(task) {
+        Ok(watcher) => {
+            watchers.insert(
+                task.id,
+                Watching {
+                    config,
+                    _watcher: watcher,
+                },
+            );
+        }
+        Err(e) => warn!("Watch files of task [{}] failed: {}", task.id, e),
+    }
+}
+
+/// Stop watching files of the task
+pub fn unregister(id: i64) {
+    WATCHERS.lock().unwrap().remove(&id);
+}
+
+fn watch(task: &Task) -> Result<RecommendedWatcher, Box<dyn Error>> {
+    let id = task.id;
+    let matcher = Matcher::new(task)?;
+    let roots = matcher.roots.clone();
+    let (tx, mut rx) = mpsc::unbounded_channel::<PathBuf>();
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<Event>| {
+        let event = match res {
+            Ok(event) => event,
+            Err(e) => {
+                warn!("Watch files of task [{}] failed: {}", id, e);
+                return;
+            }
+        };
+        // 读取文件不算变化
+        if event.kind.is_access() {
+            return;
+        }
+        for path in event.paths {
+            if matcher.is_match(&path) {
+                let _ = tx.send(path);
+            }
+        }
+    })?;
+    for (root, mode) in &roots {
+        if let Err(e) = watcher.watch(root, *mode) {
+            warn!("Watch {} of task [{}] failed: {}", root.display(), id, e);
+        }
+    }
+
+    let debounce = Duration::from_millis(task.watch_debounce_ms.unwrap_or(DEFAULT_WATCH_DEBOUNCE_MS));
+    tokio::spawn(async move {
+        while let Some(path) = rx.recv().await {
+            // 等待一段时间内没有新的变化后再重启
+            loop {
+                match time::timeout(debounce, rx.recv()).await {
+                    Ok(Some(_)) => continue,
+                    Ok(None) => return,
+                    Err(_) => break,
+                }
+            }
+            info!("Task [{}] watched file {} changed", id, path.display());
+            if let Err(e) = global::restart_changed(id).await {
+                warn!("Restart task [{}] on file change failed: {}", id, e);
+            }
+        }
+    });
+    Ok(watcher)
+}
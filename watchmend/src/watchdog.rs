@@ -0,0 +1,158 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use lazy_static::lazy_static;
+use sysinfo::{Pid, ProcessRefreshKind, ProcessesToUpdate, System};
+use tracing::warn;
+
+use crate::common::task::{Task, TaskType};
+use crate::{global, process};
+
+/// Default seconds CPU usage must stay above `max_cpu_restart` before restarting
+pub const DEFAULT_MAX_CPU_SECONDS: u64 = 60;
+
+lazy_static! {
+    // 保留上次刷新的进程信息，CPU 使用率按两次检查之间的间隔计算
+    static ref SYSTEM: Mutex<System> = Mutex::new(System::new());
+    // 任务进程 CPU 使用率开始超过阈值的时间
+    static ref CPU_HIGH_SINCE: Mutex<HashMap<(i64, u32), Instant>> = Mutex::new(HashMap::new());
+}
+
+/// Resource usage of a task process tree
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Usage {
+    /// Memory in bytes
+    pub memory: u64,
+    /// CPU usage in percent of one core
+    pub cpu: f32,
+}
+
+/// Compare the usage with the thresholds of the task, returns the reason when the
+/// task should be restarted
+///
+/// `high_since` is the time CPU usage started to exceed `max_cpu_restart`, it is
+/// updated with `now` and cleared when the usage drops.
+pub fn exceeded(
+    task: &Task,
+    usage: Usage,
+    high_since: &mut Option<Instant>,
+    now: Instant,
+) -> Option<String> {
+    if let Some(max) = task.max_memory_restart {
+        if usage.memory > max {
+            return Some(format!(
+                "memory {} exceeds {}",
+                format_size(usage.memory),
+                format_size(max)
+            ));
+        }
+    }
+    match task.max_cpu_restart {
+        Some(max) if usage.cpu > max => {
+            let since = *high_since.get_or_insert(now);
+            let seconds = task.max_cpu_seconds.unwrap_or(DEFAULT_MAX_CPU_SECONDS);
+            if now.duration_since(since) >= Duration::from_secs(seconds) {
+                return Some(format!("cpu above {}% for {}s", max, seconds));
+            }
+        }
+        _ => *high_since = None,
+    }
+    None
+}
+
+fn format_size(size: u64) -> String {
+    for (unit, base) in [("G", 1u64 << 30), ("M", 1 << 20), ("K", 1 << 10)] {
+        if size >= base {
+            return format!("{:.1}{}", size as f64 / base as f64, unit);
+        }
+    }
+    format!("{}B", size)
+}
+
+/// Check running async tasks with resource thresholds and restart those exceeding them
+pub async fn check() -> Result<(), Box<dyn Error>> {
+    let mut targets = Vec::new();
+    let instances = global::get_instances().await;
+    for task in global::get_all().await?.into_values().chain(instances) {
+        if !matches!(task.task_type, TaskType::Async(_))
+            || task.status.as_deref() != Some("running")
+            || (task.max_memory_restart.is_none() && task.max_cpu_restart.is_none())
+        {
+            continue;
+        }
+        if let Some(pid) = task.pid {
+            targets.push((task, pid));
+        }
+    }
+
+    let now = Instant::now();
+    let mut restarts = Vec::new();
+    {
+        let mut high = CPU_HIGH_SINCE.lock().unwrap();
+        high.retain(|key, _| targets.iter().any(|(t, pid)| (t.id, *pid) == *key));
+        if targets.is_empty() {
+            return Ok(());
+        }
+        let usages = sample(&targets);
+        for ((task, pid), usage) in targets.iter().zip(usages) {
+            let mut since = high.get(&(task.id, *pid)).copied();
+            if let Some(reason) = exceeded(task, usage, &mut since, now) {
+                restarts.push((task.id, *pid, reason));
+            }
+            match since {
+                Some(since) => high.insert((task.id, *pid), since),
+                None => high.remove(&(task.id, *pid)),
+            };
+        }
+    }
+
+    for (id, pid, reason) in restarts {
+        warn!("Task [{}] {}, restart it", id, reason);
+        if let Err(e) = global::restart_failed(id, pid, reason).await {
+            warn!("Restart task [{}] failed: {}", id, e);
+        }
+    }
+    Ok(())
+}
+
+/// Usage of the process trees of the task processes
+///
+/// Memory is the resident memory of the processes, the page cache counted by the
+/// cgroup is not caused by the task using too much memory.
+fn sample(targets: &[(Task, u32)]) -> Vec<Usage> {
+    // 每次检查只遍历一次 /proc
+    let children = process::children();
+    let trees: Vec<Vec<u32>> = targets
+        .iter()
+        .map(|(_, pid)| {
+            let mut tree = vec![*pid];
+            tree.extend(process::descendants_in(&children, *pid));
+            tree
+        })
+        .collect();
+    let all: Vec<Pid> = trees.iter().flatten().map(|pid| Pid::from_u32(*pid)).collect();
+
+    let mut system = SYSTEM.lock().unwrap();
+    system.refresh_processes_specifics(
+        ProcessesToUpdate::Some(&all),
+        true,
+        ProcessRefreshKind::nothing().with_cpu().with_memory(),
+    );
+    trees
+        .iter()
+        .map(|tree| {
+            let mut usage = Usage::default();
+            for pid in tree {
+                if let Some(p) = system.process(Pid::from_u32(*pid)) {
+                    usage.cpu += p.cpu_usage();
+                    usage.memory += p.memory();
+                }
+            }
+            usage
+        })
+        .collect()
+}
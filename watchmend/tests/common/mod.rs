@@ -0,0 +1,96 @@
+//! Fixtures shared by the integration tests, every test file uses a part of them
+#![allow(dead_code)]
+
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use watchmend::common::task::{Task, TaskFlag, Tasks};
+use watchmend::global;
+
+/// Time to wait for a task or a file to reach the expected state
+pub static TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Task named `<prefix>-<id>` with the other fields of its toml `[[task]]` table
+pub fn task(id: i64, prefix: &str, fields: &str) -> Task {
+    let tasks: Tasks = toml::from_str(&format!(
+        "[[task]]\nid = {}\nname = \"{}-{}\"\n{}",
+        id, prefix, id, fields
+    ))
+    .unwrap();
+    tasks.task[0].clone()
+}
+
+pub fn flag(id: i64) -> TaskFlag {
+    TaskFlag {
+        id,
+        name: None,
+        group: None,
+        mat: false,
+    }
+}
+
+pub async fn get(id: i64) -> Task {
+    global::get_all().await.unwrap().remove(&id).unwrap()
+}
+
+/// Poll `check` until it returns `true`, panics after [`TIMEOUT`]
+pub async fn wait_until<F, Fut>(what: &str, mut check: F)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = bool>,
+{
+    let deadline = Instant::now() + TIMEOUT;
+    while !check().await {
+        assert!(Instant::now() < deadline, "timed out waiting for {}", what);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+}
+
+/// Check that `check` keeps returning `true` for `duration`, used for changes that
+/// must not happen
+pub async fn assert_stays<F, Fut>(what: &str, duration: Duration, mut check: F)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = bool>,
+{
+    let deadline = Instant::now() + duration;
+    while Instant::now() < deadline {
+        assert!(check().await, "{} changed", what);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+}
+
+/// Wait until the task has `status`
+pub async fn wait_status(id: i64, status: &str) {
+    wait_until(&format!("task [{}] {}", id, status), || async move {
+        get(id).await.status.as_deref() == Some(status)
+    })
+    .await;
+}
+
+/// Wait until the content of the file at `path` satisfies `check`, returns the content
+pub async fn wait_file(path: &Path, check: impl Fn(&str) -> bool) -> String {
+    let mut content = String::new();
+    wait_until(&path.display().to_string(), || {
+        content = std::fs::read_to_string(path).unwrap_or_default();
+        let done = check(&content);
+        async move { done }
+    })
+    .await;
+    content
+}
+
+/// Stop the task when it still runs and remove it, so no process outlives the test
+pub async fn cleanup(id: i64) {
+    let _ = global::stop(flag(id), false).await;
+    global::remove(flag(id), false).await.unwrap();
+}
+
+/// Empty directory `watchmen-<name>-<pid>` in the temp dir
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("watchmen-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
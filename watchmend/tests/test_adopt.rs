@@ -0,0 +1,152 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::time::Duration;
+
+    use watchmend::common::task::Tasks;
+    use watchmend::global;
+    use watchmend::process;
+
+    use crate::common::{self, cleanup, get, wait_status, wait_until};
+
+    /// Start `sleep <seconds>` like a process left by the previous daemon, returns
+    /// its pid and start time after it executed `sleep`
+    async fn spawn_sleep(seconds: &str) -> (std::process::Child, u32, u64) {
+        let child = std::process::Command::new("sleep")
+            .arg(seconds)
+            .spawn()
+            .unwrap();
+        let pid = child.id();
+        let starttime = process::start_time(pid).unwrap();
+        let args = vec![seconds.to_string()];
+        // 等待子进程 exec 完成后再读取命令行
+        wait_until(&format!("exec of process {}", pid), || {
+            let same = process::is_same(pid, starttime, "sleep", &args);
+            async move { same }
+        })
+        .await;
+        (child, pid, starttime)
+    }
+
+    /// Write the tasks as the cache file `path` and load it
+    async fn load(path: &Path, tasks: &Tasks) {
+        std::fs::write(path, serde_json::to_string(&tasks.task).unwrap()).unwrap();
+        global::load(path.to_str().unwrap()).await.unwrap();
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_is_same() {
+        let (mut child, pid, starttime) = spawn_sleep("5").await;
+        let args = vec!["5".to_string()];
+        assert!(process::is_same(pid, starttime, "sleep", &args));
+        assert!(process::is_same(pid, starttime, "/bin/sleep", &args));
+        assert!(!process::is_same(pid, starttime + 1, "sleep", &args));
+        assert!(!process::is_same(pid, starttime, "sleep", &["6".to_string()]));
+        assert!(!process::is_same(pid, starttime, "python3", &args));
+        child.kill().unwrap();
+        child.wait().unwrap();
+        assert!(!process::is_same(pid, starttime, "sleep", &args));
+    }
+
+    #[tokio::test]
+    async fn test_wait_exit() {
+        let mut child = std::process::Command::new("sleep").arg("0.3").spawn().unwrap();
+        let pid = child.id();
+        let starttime = process::start_time(pid).unwrap();
+        let waiter = tokio::spawn(process::wait_exit(pid, starttime));
+        tokio::time::timeout(Duration::from_secs(5), waiter)
+            .await
+            .unwrap()
+            .unwrap();
+        child.wait().unwrap();
+
+        // 启动时间不一致时视为已退出
+        tokio::time::timeout(Duration::from_secs(1), process::wait_exit(std::process::id(), 0))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_load_failed_task() {
+        let (mut child, pid, starttime) = spawn_sleep("30").await;
+        let mut tasks: Tasks = toml::from_str(
+            r#"
+            [[task]]
+            id = 1301
+            name = "adopt-1301"
+            command = "/nonexistent/watchmen-test"
+            task_type = { Async = {} }
+
+            [[task]]
+            id = 1302
+            name = "adopt-1302"
+            command = "sleep"
+            args = ["30"]
+            depends_on = [1301]
+            task_type = { Async = {} }
+
+            [[task]]
+            id = 1303
+            name = "adopt-1303"
+            command = "sleep"
+            args = ["30"]
+            depends_on = [1301]
+            task_type = { Async = {} }
+            "#,
+        )
+        .unwrap();
+        for task in tasks.task.iter_mut() {
+            task.status = Some("running".to_string());
+        }
+        tasks.task[2].pid = Some(pid);
+        tasks.task[2].pid_start_time = Some(starttime);
+
+        // 启动失败的任务标记为 errored，之后的任务照常启动或接管
+        load(&common::temp_dir("load").join("cache.json"), &tasks).await;
+        let failed = get(1301).await;
+        assert_eq!(failed.status.as_deref(), Some("errored"));
+        assert!(failed.restart_reason.unwrap().starts_with("start failed"));
+        let started = get(1302).await;
+        assert_eq!(started.status.as_deref(), Some("running"));
+        assert!(started.pid.is_some_and(|p| p != pid));
+        let adopted = get(1303).await;
+        assert_eq!(adopted.status.as_deref(), Some("running"));
+        assert_eq!(adopted.pid, Some(pid));
+
+        for id in [1301, 1302, 1303] {
+            cleanup(id).await;
+        }
+        child.wait().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_adopted_exit() {
+        let (mut child, pid, starttime) = spawn_sleep("1").await;
+        let task = common::task(
+            1304,
+            "adopt",
+            r#"command = "sleep"
+            args = ["1"]
+            restart_policy = "on-failure"
+            task_type = { Async = {} }"#,
+        );
+        let mut tasks = Tasks { task: vec![task] };
+        tasks.task[0].status = Some("running".to_string());
+        tasks.task[0].pid = Some(pid);
+        tasks.task[0].pid_start_time = Some(starttime);
+        load(&common::temp_dir("adopted-exit").join("cache.json"), &tasks).await;
+        assert_eq!(get(1304).await.pid, Some(pid));
+        let waiter = std::thread::spawn(move || child.wait().unwrap());
+
+        // 接管的进程退出码未知，不视为失败，on-failure 策略不重启
+        wait_status(1304, "stopped").await;
+        let exited = get(1304).await;
+        assert_eq!(exited.pid, None);
+        assert_eq!(exited.code, None);
+        assert!(waiter.join().unwrap().success());
+        cleanup(1304).await;
+    }
+}
@@ -0,0 +1,156 @@
+#[cfg(test)]
+mod tests {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use watchmend::common::task::{AsyncTask, Task, TaskFlag, TaskType, Tasks};
+    use watchmend::global;
+
+    fn async_task(restart: &str) -> AsyncTask {
+        let tasks: Tasks = toml::from_str(&format!(
+            r#"
+            [[task]]
+            id = 1
+            name = "backoff"
+            command = "sh"
+            task_type = {{ Async = {{ {} }} }}
+            "#,
+            restart
+        ))
+        .unwrap();
+        match tasks.task[0].task_type.clone() {
+            TaskType::Async(tt) => tt,
+            _ => unreachable!(),
+        }
+    }
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    #[test]
+    fn test_backoff_growth() {
+        let tt = async_task("restart_delay = 2, backoff_multiplier = 3.0, max_backoff = 60");
+        let delays: Vec<u64> = (0..=6).map(|n| tt.backoff(n)).collect();
+        // 每次连续重启延迟乘以 backoff_multiplier，不超过 max_backoff
+        assert_eq!(delays, vec![0, 2, 6, 18, 54, 60, 60]);
+        assert_eq!(tt.backoff(u64::MAX), 60);
+
+        let tt = async_task("restart_delay = 1");
+        assert_eq!(tt.backoff(1), 1);
+        assert_eq!(tt.backoff(11), 1024);
+        assert_eq!(tt.backoff(u64::MAX), u64::MAX);
+
+        // 未配置 restart_delay 时立即重启
+        assert_eq!(async_task("").backoff(5), 0);
+    }
+
+    #[test]
+    fn test_schedule_restart() {
+        let mut tt = async_task("max_restart = 2, restart_delay = 5");
+        assert!(tt.schedule_restart(100));
+        assert_eq!((tt.has_restart, tt.next_restart_at), (1, 105));
+        assert!(tt.schedule_restart(200));
+        assert_eq!((tt.has_restart, tt.next_restart_at), (2, 210));
+        // 用完 max_restart 次重启后不再重启
+        assert!(!tt.schedule_restart(300));
+        assert_eq!((tt.has_restart, tt.next_restart_at), (2, 210));
+
+        // 未配置 max_restart 时一直重启，延迟仍然增长
+        let mut tt = async_task("restart_delay = 5, max_backoff = 30");
+        for _ in 0..10 {
+            assert!(tt.schedule_restart(100));
+        }
+        assert_eq!((tt.has_restart, tt.next_restart_at), (10, 130));
+    }
+
+    #[test]
+    fn test_min_uptime_reset() {
+        let mut tt = async_task("max_restart = 3, min_uptime = 10");
+        tt.has_restart = 3;
+        tt.started_at = 100;
+        tt.exited_at(105);
+        assert_eq!((tt.has_restart, tt.stopped_at), (3, 105));
+        assert!(!tt.schedule_restart(105));
+
+        // 稳定运行 min_uptime 秒后重新计算连续重启次数
+        tt.exited_at(110);
+        assert_eq!(tt.has_restart, 0);
+        assert!(tt.schedule_restart(110));
+        assert_eq!(tt.has_restart, 1);
+    }
+
+    async fn crash(id: i64) -> Task {
+        global::update(
+            id,
+            Some(None),
+            Some(Some("auto restart".to_string())),
+            Some(Some(1)),
+            Some(true),
+            None,
+        )
+        .await
+        .unwrap();
+        global::get_all().await.unwrap().remove(&id).unwrap()
+    }
+
+    fn async_of(task: &Task) -> &AsyncTask {
+        match &task.task_type {
+            TaskType::Async(tt) => tt,
+            _ => unreachable!(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_crash_loop() {
+        let tasks: Tasks = toml::from_str(
+            r#"
+            [[task]]
+            id = 1101
+            name = "backoff-1101"
+            command = "sh"
+            task_type = { Async = { max_restart = 2, restart_delay = 30 } }
+
+            [[task]]
+            id = 1102
+            name = "backoff-1102"
+            command = "sh"
+            task_type = { Async = { restart_delay = 30, max_backoff = 60 } }
+            "#,
+        )
+        .unwrap();
+        for task in tasks.task {
+            global::add(task).await.unwrap();
+        }
+
+        let task = crash(1101).await;
+        assert_eq!(task.status.as_deref(), Some("auto restart"));
+        assert!(async_of(&task).next_restart_at >= now() + 29);
+        crash(1101).await;
+        // 连续崩溃 max_restart 次后进入 errored 状态
+        let task = crash(1101).await;
+        assert_eq!(task.status.as_deref(), Some("errored"));
+        assert_eq!(async_of(&task).has_restart, 2);
+
+        // 未配置 max_restart 的任务不会放弃，但每次重启都要等待退避时间
+        for _ in 0..3 {
+            crash(1102).await;
+        }
+        let task = crash(1102).await;
+        assert_eq!(task.status.as_deref(), Some("auto restart"));
+        assert_eq!(async_of(&task).has_restart, 4);
+        assert!(async_of(&task).next_restart_at >= now() + 59);
+
+        for id in [1101, 1102] {
+            let flag = TaskFlag {
+                id,
+                name: None,
+                group: None,
+                mat: false,
+            };
+            global::remove(flag, false).await.unwrap();
+        }
+    }
+}
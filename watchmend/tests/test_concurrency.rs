@@ -0,0 +1,115 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use watchmend::common::task::{ConcurrencyPolicy, Task, TaskType, Tasks};
+    use watchmend::global;
+
+    use crate::common::{flag, get, task, wait_status};
+
+    fn periodic(id: i64, policy: &str) -> Task {
+        task(
+            id,
+            "periodic",
+            &format!(
+                r#"command = "sleep"
+                args = ["2"]
+                task_type = {{ Periodic = {{ interval = 60, concurrency_policy = "{}", max_concurrent = 2 }} }}"#,
+                policy
+            ),
+        )
+    }
+
+    async fn pid(id: i64) -> Option<u32> {
+        get(id).await.pid
+    }
+
+    /// Wait for the runs of the periodic task to finish and remove it
+    async fn cleanup(id: i64) {
+        wait_status(id, "interval").await;
+        global::remove(flag(id), false).await.unwrap();
+    }
+
+    #[test]
+    fn test_concurrency_config() {
+        let tasks: Tasks = toml::from_str(
+            r#"
+            [[task]]
+            id = 1
+            name = "periodic"
+            command = "sleep"
+            task_type = { Periodic = { interval = 60 } }
+            "#,
+        )
+        .unwrap();
+        match &tasks.task[0].task_type {
+            TaskType::Periodic(tt) => {
+                assert_eq!(tt.concurrency_policy, ConcurrencyPolicy::Forbid);
+                assert_eq!(tt.max_concurrent, None);
+            }
+            _ => panic!("not a periodic task"),
+        }
+
+        let path = std::env::temp_dir().join(format!("watchmen-concurrency-{}.ini", std::process::id()));
+        std::fs::write(
+            &path,
+            "[periodic]\nid = 1\nname = periodic\ncommand = sleep\ntask_type = periodic\ninterval = 60\nconcurrency_policy = allow\nmax_concurrent = 3\n",
+        )
+        .unwrap();
+        let tasks = Task::from_ini(&path).unwrap();
+        match &tasks.task[0].task_type {
+            TaskType::Periodic(tt) => {
+                assert_eq!(tt.concurrency_policy, ConcurrencyPolicy::Allow);
+                assert_eq!(tt.max_concurrent, Some(3));
+            }
+            _ => panic!("not a periodic task"),
+        }
+        std::fs::remove_file(&path).unwrap();
+        assert!("parallel".parse::<ConcurrencyPolicy>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_concurrency_forbid() {
+        global::add(periodic(101, "forbid")).await.unwrap();
+        assert!(global::start(flag(101)).await.unwrap().is_success());
+        let first = pid(101).await;
+        // 上一次执行未结束，跳过本次执行
+        assert!(!global::start(flag(101)).await.unwrap().is_success());
+        assert_eq!(pid(101).await, first);
+        cleanup(101).await;
+    }
+
+    #[tokio::test]
+    async fn test_concurrency_allow() {
+        global::add(periodic(102, "allow")).await.unwrap();
+        assert!(global::start(flag(102)).await.unwrap().is_success());
+        let first = pid(102).await;
+        assert!(global::start(flag(102)).await.unwrap().is_success());
+        assert_ne!(pid(102).await, first);
+        assert!(watchmend::process::is_alive(first.unwrap()));
+        // 超过 max_concurrent 时跳过
+        assert!(!global::start(flag(102)).await.unwrap().is_success());
+        cleanup(102).await;
+        assert!(!watchmend::process::is_alive(first.unwrap()));
+    }
+
+    #[tokio::test]
+    async fn test_concurrency_replace() {
+        let mut t = periodic(103, "replace");
+        t.stop_timeout = Some(1);
+        global::add(t).await.unwrap();
+        assert!(global::start(flag(103)).await.unwrap().is_success());
+        let first = pid(103).await.unwrap();
+        assert!(global::start(flag(103)).await.unwrap().is_success());
+        let second = pid(103).await.unwrap();
+        assert_ne!(first, second);
+        assert!(!watchmend::process::is_alive(first));
+        assert!(watchmend::process::is_alive(second));
+
+        // 最后一次执行结束后恢复为等待下次执行
+        wait_status(103, "interval").await;
+        assert_eq!(pid(103).await, None);
+        assert!(!watchmend::process::is_alive(second));
+        global::remove(flag(103), false).await.unwrap();
+    }
+}
@@ -0,0 +1,63 @@
+#[cfg(test)]
+mod tests {
+    use watchmend::common::credential::{parse_capability, Credential};
+    use watchmend::common::task::Task;
+
+    #[test]
+    fn test_parse_capability() {
+        assert_eq!(parse_capability("CAP_NET_BIND_SERVICE").unwrap(), 10);
+        assert_eq!(parse_capability("net_raw").unwrap(), 13);
+        assert!(parse_capability("CAP_FLY").is_err());
+    }
+
+    #[test]
+    fn test_from_task() {
+        assert_eq!(Credential::from_task(&Task::default()).unwrap(), None);
+
+        let task = Task {
+            user: Some("root".to_string()),
+            user_group: Some("0".to_string()),
+            umask: Some("027".to_string()),
+            capabilities: Some(vec!["CAP_CHOWN".to_string(), "CAP_KILL".to_string()]),
+            ..Default::default()
+        };
+        let credential = Credential::from_task(&task).unwrap().unwrap();
+        assert_eq!(credential.uid, Some(0));
+        assert_eq!(credential.gid, Some(0));
+        assert!(credential.groups.unwrap().contains(&0));
+        assert_eq!(credential.umask, Some(0o027));
+        assert_eq!(credential.capabilities, Some(1 | 1 << 5));
+
+        // 与守护进程相同的用户和组不需要切换
+        let (uid, gid) = unsafe { (libc::geteuid(), libc::getegid()) };
+        let task = Task {
+            user: Some(uid.to_string()),
+            user_group: Some(gid.to_string()),
+            ..Default::default()
+        };
+        let credential = Credential::from_task(&task).unwrap().unwrap();
+        assert_eq!(credential.uid, Some(uid));
+        let credential = credential.without_current();
+        assert_eq!(credential.uid, None);
+        assert_eq!(credential.gid, None);
+        assert!(credential.check().is_ok());
+        let task = Task {
+            user: Some((uid + 1).to_string()),
+            ..Default::default()
+        };
+        let credential = Credential::from_task(&task).unwrap().unwrap();
+        assert_eq!(credential.without_current().uid, Some(uid + 1));
+
+        let task = Task {
+            user: Some("watchmen-no-such-user".to_string()),
+            ..Default::default()
+        };
+        assert!(Credential::from_task(&task).is_err());
+
+        let task = Task {
+            umask: Some("999".to_string()),
+            ..Default::default()
+        };
+        assert!(Credential::from_task(&task).is_err());
+    }
+}
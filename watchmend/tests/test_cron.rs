@@ -0,0 +1,92 @@
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDateTime;
+    use watchmend::common::cron::Cron;
+    use watchmend::common::task::{TaskType, Tasks};
+
+    fn at(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    fn next(expr: &str, after: &str) -> String {
+        let cron: Cron = expr.parse().unwrap();
+        cron.next_after(at(after))
+            .unwrap()
+            .format("%Y-%m-%d %H:%M:%S")
+            .to_string()
+    }
+
+    #[test]
+    fn test_cron_next() {
+        assert_eq!(next("0 2 * * *", "2024-03-01 01:59:59"), "2024-03-01 02:00:00");
+        assert_eq!(next("0 2 * * *", "2024-03-01 02:00:00"), "2024-03-02 02:00:00");
+        assert_eq!(next("*/15 * * * *", "2024-03-01 10:07:30"), "2024-03-01 10:15:00");
+        assert_eq!(next("30 */10 * * * *", "2024-03-01 10:07:30"), "2024-03-01 10:10:30");
+        assert_eq!(next("0 9-17/4 * * MON-FRI", "2024-03-01 18:00:00"), "2024-03-04 09:00:00");
+        assert_eq!(next("0 0 1,15 * *", "2024-03-02 00:00:00"), "2024-03-15 00:00:00");
+        assert_eq!(next("0 0 29 2 *", "2024-03-01 00:00:00"), "2028-02-29 00:00:00");
+        assert_eq!(next("0 12 * DEC 7", "2024-03-01 00:00:00"), "2024-12-01 12:00:00");
+        assert_eq!(next("5/20 * * * *", "2024-03-01 00:30:00"), "2024-03-01 00:45:00");
+        // 同时限制日期和星期时满足其一即可
+        assert_eq!(next("0 0 13 * 5", "2024-09-01 00:00:00"), "2024-09-06 00:00:00");
+    }
+
+    #[test]
+    fn test_cron_macros() {
+        assert_eq!(next("@daily", "2024-12-31 23:00:00"), "2025-01-01 00:00:00");
+        assert_eq!(next("@hourly", "2024-12-31 23:00:00"), "2025-01-01 00:00:00");
+        assert_eq!(next("@weekly", "2024-03-01 00:00:00"), "2024-03-03 00:00:00");
+        assert_eq!(next("@monthly", "2024-03-01 00:00:00"), "2024-04-01 00:00:00");
+        assert_eq!(next("@yearly", "2024-03-01 00:00:00"), "2025-01-01 00:00:00");
+    }
+
+    #[test]
+    fn test_cron_invalid() {
+        for expr in ["", "* * * *", "60 * * * *", "* 24 * * *", "*/0 * * * *", "5-1 * * * *", "@often"] {
+            assert!(expr.parse::<Cron>().is_err(), "{}", expr);
+        }
+        let cron: Cron = "0 0 30 2 *".parse().unwrap();
+        assert_eq!(cron.next_after(at("2024-01-01 00:00:00")), None);
+    }
+
+    #[test]
+    fn test_cron_task() {
+        let tasks: Tasks = toml::from_str(
+            r#"
+            [[task]]
+            id = 1
+            name = "backup"
+            command = "backup.sh"
+            task_type = { Scheduled = { cron = "0 2 * * *" } }
+            "#,
+        )
+        .unwrap();
+        match &tasks.task[0].task_type {
+            TaskType::Scheduled(tt) => {
+                assert_eq!(tt.cron.as_deref(), Some("0 2 * * *"));
+                assert!(tt.validate().is_ok());
+            }
+            _ => panic!("not a scheduled task"),
+        }
+
+        let path = std::env::temp_dir().join(format!("watchmen-cron-{}.ini", std::process::id()));
+        std::fs::write(
+            &path,
+            "[backup]\nid = 1\nname = backup\ncommand = backup.sh\ntask_type = scheduled\ncron = @daily\n",
+        )
+        .unwrap();
+        let tasks = watchmend::common::task::Task::from_ini(&path).unwrap();
+        match &tasks.task[0].task_type {
+            TaskType::Scheduled(tt) => assert_eq!(tt.cron.as_deref(), Some("@daily")),
+            _ => panic!("not a scheduled task"),
+        }
+
+        std::fs::write(
+            &path,
+            "[backup]\nid = 1\nname = backup\ncommand = backup.sh\ntask_type = scheduled\ncron = @daily\nhour = 2\n",
+        )
+        .unwrap();
+        assert!(watchmend::common::task::Task::from_ini(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
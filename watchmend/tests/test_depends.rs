@@ -0,0 +1,31 @@
+#[cfg(test)]
+mod tests {
+    use watchmend::common::task::{Task, TaskType};
+    use watchmend::global;
+
+    fn task(id: i64, depends_on: Vec<i64>) -> Task {
+        Task {
+            id,
+            name: format!("depends-{}", id),
+            command: "true".to_string(),
+            task_type: TaskType::None,
+            depends_on,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_add_depends() {
+        assert!(global::add(task(9001, vec![])).await.is_ok());
+        assert!(global::add(task(9002, vec![9001])).await.is_ok());
+        assert!(global::add(task(9003, vec![9002])).await.is_ok());
+
+        // 依赖不存在的任务
+        assert!(global::add(task(9004, vec![9999])).await.is_err());
+        // 依赖自身
+        assert!(global::add(task(9005, vec![9005])).await.is_err());
+        // 重新加载形成循环依赖时保留原任务
+        assert!(global::reload(task(9001, vec![9003])).await.is_err());
+        assert!(global::reload(task(9001, vec![])).await.is_ok());
+    }
+}
@@ -0,0 +1,154 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    use watchmend::common::env::{interpolate, parse_dotenv};
+    use watchmend::common::task::{InheritEnv, Task, TaskFlag};
+    use watchmend::global;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("watchmen-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(dir.join("conf")).unwrap();
+        dir
+    }
+
+    fn vars(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_interpolate() {
+        let vars = vars(&[("HOST", "localhost"), ("PORT", "8000"), ("EMPTY", "")]);
+        assert_eq!(interpolate("${HOST}:${PORT}", &vars), "localhost:8000");
+        assert_eq!(interpolate("${MISSING}/x", &vars), "/x");
+        assert_eq!(interpolate("${MISSING:-/var/log}/x", &vars), "/var/log/x");
+        assert_eq!(interpolate("${EMPTY:-default}", &vars), "default");
+        assert_eq!(interpolate("${PORT:-9000}", &vars), "8000");
+        assert_eq!(interpolate("${MISSING:-${HOST}}", &vars), "localhost");
+        // 不是合法的变量引用时保持原样
+        assert_eq!(interpolate("${1:-x} $HOST ${HOST", &vars), "${1:-x} $HOST ${HOST");
+    }
+
+    #[test]
+    fn test_parse_dotenv() {
+        let pairs = parse_dotenv(
+            "# comment\n\nexport HOST=localhost\nURL=http://${HOST}:${PORT:-80} # comment\nRAW='${HOST}'\nQUOTED=\"a b\\n#c\"\n",
+            &HashMap::new(),
+        )
+        .unwrap();
+        assert_eq!(
+            pairs,
+            vec![
+                ("HOST".to_string(), "localhost".to_string()),
+                ("URL".to_string(), "http://localhost:80".to_string()),
+                ("RAW".to_string(), "${HOST}".to_string()),
+                ("QUOTED".to_string(), "a b\n#c".to_string()),
+            ]
+        );
+        assert!(parse_dotenv("INVALID", &HashMap::new()).is_err());
+        assert!(parse_dotenv("1KEY=value", &HashMap::new()).is_err());
+        assert!(parse_dotenv("KEY=\"value", &HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn test_env_file() {
+        let dir = temp_dir("env-file");
+        std::fs::write(dir.join("conf/.env"), "A=file\nB=file\nC=${WATCHMEN_TEST_DAEMON}\n").unwrap();
+        std::fs::write(dir.join("conf/.env.local"), "B=local\n").unwrap();
+        std::fs::write(
+            dir.join("conf/tasks.toml"),
+            r#"
+            [[task]]
+            id = 1
+            name = "env"
+            command = "env"
+            env_file = [".env", ".env.local"]
+            env = { A = "inline-${B}" }
+            task_type = { Async = {} }
+            "#,
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("conf/tasks.ini"),
+            "[env]\nid = 1\nname = env\ncommand = env\nenv_file = .env\ntask_type = async\n",
+        )
+        .unwrap();
+
+        let conf = dir.canonicalize().unwrap().join("conf");
+        let task = Task::from_file(&dir.join("conf/tasks.toml")).unwrap().task[0].clone();
+        assert_eq!(
+            task.env_file,
+            vec![
+                conf.join(".env").to_string_lossy().to_string(),
+                conf.join(".env.local").to_string_lossy().to_string(),
+            ]
+        );
+        let ini = Task::from_file(&dir.join("conf/tasks.ini")).unwrap().task[0].clone();
+        assert_eq!(ini.env_file, vec![conf.join(".env").to_string_lossy().to_string()]);
+
+        // 守护进程环境变量 < env_file（按顺序） < env
+        std::env::set_var("WATCHMEN_TEST_DAEMON", "daemon");
+        std::env::set_var("B", "daemon");
+        let env = task.environment(&InheritEnv::All).unwrap();
+        assert_eq!(env["WATCHMEN_TEST_DAEMON"], "daemon");
+        assert_eq!(env["C"], "daemon");
+        assert_eq!(env["B"], "local");
+        assert_eq!(env["A"], "inline-local");
+
+        let mut missing = task.clone();
+        missing.env_file.push(conf.join("missing").to_string_lossy().to_string());
+        assert!(missing.environment(&InheritEnv::All).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_env_interpolation() {
+        let dir = temp_dir("env-task");
+        std::fs::write(dir.join("conf/.env"), "OUT_DIR=${WATCHMEN_TEST_ROOT}/out\nGREETING=\"hello world\"\n").unwrap();
+        std::fs::write(
+            dir.join("conf/tasks.toml"),
+            r#"
+            [[task]]
+            id = 501
+            name = "env-501"
+            command = "${SHELL_BIN:-sh}"
+            args = ["-c", "echo \"$GREETING ${NAME}\""]
+            env_file = ".env"
+            env = { NAME = "watchmen" }
+            dir = "${WATCHMEN_TEST_ROOT}"
+            stdout = "${OUT_DIR}/stdout.log"
+            task_type = { Async = {} }
+            "#,
+        )
+        .unwrap();
+        std::env::set_var("WATCHMEN_TEST_ROOT", dir.to_string_lossy().to_string());
+        let task = Task::from_file(&dir.join("conf/tasks.toml")).unwrap().task[0].clone();
+        global::add(task).await.unwrap();
+        let added = global::get_all().await.unwrap().remove(&501).unwrap();
+        assert_eq!(added.command, "sh");
+        assert_eq!(added.args[1], "echo \"$GREETING watchmen\"");
+        assert_eq!(added.dir, Some(dir.to_string_lossy().to_string()));
+        assert_eq!(
+            added.stdout,
+            Some(dir.join("out/stdout.log").to_string_lossy().to_string())
+        );
+
+        global::start(TaskFlag {
+            id: 501,
+            name: None,
+            group: None,
+            mat: false,
+        })
+        .await
+        .unwrap();
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        let output = std::fs::read_to_string(dir.join("out/stdout.log")).unwrap();
+        assert_eq!(output, "hello world watchmen\n");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,102 @@
+#[cfg(test)]
+mod tests {
+    use tokio::{io::AsyncWriteExt, net::TcpListener};
+    use watchmend::common::task::{HealthCheck, HealthCheckType, Readiness, ReadinessType, Tasks};
+    use watchmend::health;
+
+    #[tokio::test]
+    async fn test_check_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut hc = HealthCheck::new(HealthCheckType::Tcp);
+        hc.address = Some(listener.local_addr().unwrap().to_string());
+        assert!(health::check(&hc, None).await.is_ok());
+
+        drop(listener);
+        assert!(health::check(&hc, None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_check_http() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            for status in ["200 OK", "503 Service Unavailable"] {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let res = format!("HTTP/1.1 {}\r\ncontent-length: 0\r\n\r\n", status);
+                stream.write_all(res.as_bytes()).await.unwrap();
+            }
+        });
+        let mut hc = HealthCheck::new(HealthCheckType::Http);
+        hc.url = Some(format!("http://{}/health", addr));
+        assert!(health::check(&hc, None).await.is_ok());
+        assert!(health::check(&hc, None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_check_exec() {
+        let mut hc = HealthCheck::new(HealthCheckType::Exec);
+        hc.command = Some("true".to_string());
+        assert!(health::check(&hc, None).await.is_ok());
+        hc.command = Some("false".to_string());
+        assert!(health::check(&hc, None).await.is_err());
+
+        // 超时视为失败
+        hc.command = Some("sleep".to_string());
+        hc.args = vec!["5".to_string()];
+        hc.timeout = 1;
+        assert!(health::check(&hc, None).await.is_err());
+    }
+
+    #[test]
+    fn test_health_check_toml() {
+        let tasks: Tasks = toml::from_str(
+            r#"
+            [[task]]
+            id = 1
+            name = "web"
+            command = "python3"
+            task_type = "None"
+
+            [task.health_check]
+            type = "http"
+            url = "http://127.0.0.1:8000/"
+            retries = 5
+            "#,
+        )
+        .unwrap();
+        let hc = tasks.task[0].health_check.clone().unwrap();
+        assert_eq!(hc.check_type, HealthCheckType::Http);
+        assert_eq!(hc.retries, 5);
+        assert_eq!(hc.interval, 10);
+        assert!(hc.restart);
+        assert!(hc.validate().is_ok());
+
+        let hc = HealthCheck::new(HealthCheckType::Tcp);
+        assert!(hc.validate().is_err());
+    }
+
+    #[test]
+    fn test_readiness_validate() {
+        let tasks: Tasks = toml::from_str(
+            r#"
+            [[task]]
+            id = 1
+            name = "web"
+            command = "python3"
+            task_type = "None"
+            readiness = { type = "log", pattern = "Listening on \\d+" }
+            "#,
+        )
+        .unwrap();
+        let readiness = tasks.task[0].readiness.clone().unwrap();
+        assert_eq!(readiness.check_type, ReadinessType::Log);
+        assert_eq!(readiness.timeout, 30);
+        assert!(readiness.validate().is_ok());
+
+        let mut readiness = Readiness::new(ReadinessType::Log);
+        readiness.pattern = Some("(".to_string());
+        assert!(readiness.validate().is_err());
+        assert!(Readiness::new(ReadinessType::File).validate().is_err());
+        assert!(Readiness::new(ReadinessType::Timeout).validate().is_ok());
+    }
+}
@@ -0,0 +1,136 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::time::{Duration, Instant};
+
+    use watchmend::common::handle::Data;
+    use watchmend::common::task::{Hook, Task};
+    use watchmend::global;
+
+    use crate::common::{self, cleanup, flag, get, temp_dir, wait_file};
+
+    fn task(id: i64, dir: &Path, hooks: &str) -> Task {
+        common::task(
+            id,
+            "hooks",
+            &format!(
+                r#"command = "sleep"
+                args = ["30"]
+                dir = "{}"
+                env = {{ MODE = "prod" }}
+                stop_timeout = 1
+                {}
+                task_type = {{ Async = {{}} }}"#,
+                dir.display(),
+                hooks
+            ),
+        )
+    }
+
+    #[test]
+    fn test_hooks_config() {
+        let dir = temp_dir("hooks-config");
+        let t = task(
+            1,
+            &dir,
+            r#"pre_start = { command = "python", args = ["manage.py", "migrate"], timeout = 300 }
+            post_stop = { command = "redis-cli" }"#,
+        );
+        assert_eq!(
+            t.pre_start,
+            Some(Hook {
+                command: "python".to_string(),
+                args: vec!["manage.py".to_string(), "migrate".to_string()],
+                timeout: 300,
+            })
+        );
+        assert_eq!(t.post_stop.as_ref().unwrap().timeout, 30);
+        assert!(t.post_start.is_none());
+        assert!(Hook::parse("  ").is_err());
+
+        let path = dir.join("hooks.ini");
+        std::fs::write(
+            &path,
+            "[app]\nid = 1\nname = app\ncommand = app\ntask_type = async\npre_start = python manage.py migrate\npre_start_timeout = 300\npost_stop = redis-cli\n",
+        )
+        .unwrap();
+        let ini = Task::from_ini(&path).unwrap().task[0].clone();
+        assert_eq!(ini.pre_start, t.pre_start);
+        assert_eq!(ini.post_stop, t.post_stop);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_hooks_lifecycle() {
+        let dir = temp_dir("hooks-lifecycle");
+        let hook = |name: &str| {
+            format!(
+                r#"{} = {{ command = "sh", args = ["-c", "echo $MODE > {}"] }}"#,
+                name, name
+            )
+        };
+        let hooks = ["pre_start", "post_start", "pre_stop", "post_stop"].map(hook);
+        global::add(task(801, &dir, &hooks.join("\n"))).await.unwrap();
+
+        // 钩子在任务目录下执行，使用任务的环境变量
+        let res = global::start(flag(801)).await.unwrap();
+        assert!(res.is_success());
+        assert_eq!(std::fs::read_to_string(dir.join("pre_start")).unwrap(), "prod\n");
+        let post_start = wait_file(&dir.join("post_start"), |s| s.ends_with('\n')).await;
+        assert_eq!(post_start, "prod\n");
+        assert!(!dir.join("pre_stop").exists());
+
+        global::stop(flag(801), false).await.unwrap();
+        assert_eq!(std::fs::read_to_string(dir.join("pre_stop")).unwrap(), "prod\n");
+        assert_eq!(std::fs::read_to_string(dir.join("post_stop")).unwrap(), "prod\n");
+        assert_eq!(get(801).await.status.as_deref(), Some("stopped"));
+        cleanup(801).await;
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_pre_start_failed() {
+        let dir = temp_dir("hooks-failed");
+        global::add(task(
+            802,
+            &dir,
+            r#"pre_start = { command = "sh", args = ["-c", "exit 3"] }"#,
+        ))
+        .await
+        .unwrap();
+        let res = global::start(flag(802)).await.unwrap();
+        assert!(!res.is_success());
+        match res.data {
+            Some(Data::String(msg)) => {
+                assert!(msg.contains("pre_start hook failed"), "{}", msg);
+                assert!(msg.contains("3"), "{}", msg);
+            }
+            data => panic!("unexpected data: {:?}", data),
+        }
+        let failed = get(802).await;
+        assert_eq!(failed.pid, None);
+        assert_eq!(failed.status.as_deref(), Some("added"));
+
+        // 超时的钩子被结束，启动失败
+        global::add(task(
+            803,
+            &dir,
+            r#"pre_start = { command = "sh", args = ["-c", "sleep 10"], timeout = 1 }"#,
+        ))
+        .await
+        .unwrap();
+        let started = Instant::now();
+        let res = global::start(flag(803)).await.unwrap();
+        assert!(started.elapsed() < Duration::from_secs(5));
+        match res.data {
+            Some(Data::String(msg)) => assert!(msg.contains("timed out"), "{}", msg),
+            data => panic!("unexpected data: {:?}", data),
+        }
+        assert_eq!(get(803).await.pid, None);
+        cleanup(802).await;
+        cleanup(803).await;
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
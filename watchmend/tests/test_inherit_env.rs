@@ -0,0 +1,96 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use watchmend::common::config::Watchmen;
+    use watchmend::common::task::{InheritEnv, Task, Tasks};
+    use watchmend::global;
+
+    use crate::common::{self, cleanup, flag, get, wait_until};
+
+    fn task(id: i64, inherit_env: &str, stdout: &str) -> Task {
+        common::task(
+            id,
+            "inherit",
+            &format!(
+                r#"command = "env"
+                env = {{ INLINE = "inline" }}
+                inherit_env = {}
+                stdout = "{}"
+                task_type = {{ Async = {{}} }}"#,
+                inherit_env, stdout
+            ),
+        )
+    }
+
+    async fn output(task: Task) -> Vec<String> {
+        let id = task.id;
+        let stdout = task.stdout.clone().unwrap();
+        global::add(task).await.unwrap();
+        global::start(flag(id)).await.unwrap();
+        // 进程退出后输出已经全部写入
+        wait_until(&format!("task [{}] exited", id), || async {
+            get(id).await.pid.is_none()
+        })
+        .await;
+        cleanup(id).await;
+        let output = std::fs::read_to_string(&stdout).unwrap();
+        std::fs::remove_file(&stdout).unwrap();
+        let mut names: Vec<String> = output
+            .lines()
+            .filter_map(|l| l.split_once('=').map(|(k, _)| k.to_string()))
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_inherit_env_config() {
+        let allow = InheritEnv::Allow(vec!["PATH".to_string(), "LC_*".to_string()]);
+        assert!(allow.allows("PATH"));
+        assert!(allow.allows("LC_ALL"));
+        assert!(!allow.allows("PATHS"));
+        assert!(!allow.allows("AWS_SECRET_ACCESS_KEY"));
+        assert!(InheritEnv::All.allows("AWS_SECRET_ACCESS_KEY"));
+        assert!(!InheritEnv::None.allows("PATH"));
+
+        assert_eq!(task(1, r#""none""#, "").inherit_env, Some(InheritEnv::None));
+        assert_eq!(task(1, r#"["PATH", "LC_*"]"#, "").inherit_env, Some(allow.clone()));
+        assert!(toml::from_str::<Tasks>(
+            "[[task]]\nid = 1\nname = \"t\"\ncommand = \"env\"\ninherit_env = \"\"\ntask_type = \"None\"\n"
+        )
+        .is_err());
+        assert_eq!("PATH LC_*".parse::<InheritEnv>(), Ok(allow.clone()));
+
+        // 缓存文件为 JSON 格式，序列化后可以还原
+        let json = serde_json::to_string(&allow).unwrap();
+        assert_eq!(json, r#"["PATH","LC_*"]"#);
+        assert_eq!(serde_json::from_str::<InheritEnv>(&json).unwrap(), allow);
+        assert_eq!(serde_json::to_string(&InheritEnv::None).unwrap(), r#""none""#);
+
+        let watchmen: Watchmen = toml::from_str(
+            "engine = \"sock\"\nengines = [\"sock\"]\ninherit_env = [\"PATH\", \"HOME\"]\n",
+        )
+        .unwrap();
+        assert_eq!(
+            watchmen.inherit_env,
+            Some(InheritEnv::Allow(vec!["PATH".to_string(), "HOME".to_string()]))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_inherit_env_start() {
+        std::env::set_var("WATCHMEN_TEST_SECRET", "secret");
+        let dir = std::env::temp_dir();
+        let pid = std::process::id();
+
+        let names = output(task(601, r#""all""#, &format!("{}/inherit-601-{}.log", dir.display(), pid))).await;
+        assert!(names.contains(&"WATCHMEN_TEST_SECRET".to_string()));
+
+        let names = output(task(602, r#"["PATH"]"#, &format!("{}/inherit-602-{}.log", dir.display(), pid))).await;
+        assert_eq!(names, vec!["INLINE".to_string(), "PATH".to_string()]);
+
+        let names = output(task(603, r#""none""#, &format!("{}/inherit-603-{}.log", dir.display(), pid))).await;
+        assert_eq!(names, vec!["INLINE".to_string()]);
+    }
+}
@@ -0,0 +1,189 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use watchmend::common::task::{render_template, Task};
+    use watchmend::global;
+    use watchmend::process;
+
+    use crate::common::{self, cleanup, flag, wait_file};
+
+    fn task(id: i64, toml_task: &str) -> Task {
+        common::task(id, "worker", toml_task)
+    }
+
+    async fn pids(id: i64) -> Vec<(u32, Option<u32>)> {
+        let mut pids: Vec<(u32, Option<u32>)> = global::get_instances()
+            .await
+            .into_iter()
+            .filter(|t| t.id == id)
+            .map(|t| (t.instance.unwrap(), t.pid))
+            .collect();
+        pids.sort();
+        pids
+    }
+
+    #[test]
+    fn test_render_template() {
+        assert_eq!(render_template("{{ 8000 + instance }}", 2).unwrap(), "8002");
+        assert_eq!(render_template("{{instance}}", 3).unwrap(), "3");
+        assert_eq!(
+            render_template("--port={{ 8000 + instance * 10 - 1 }}", 2).unwrap(),
+            "--port=8019"
+        );
+        assert_eq!(render_template("plain", 1).unwrap(), "plain");
+        for template in ["{{ 8000 + }}", "{{ port }}", "{{ 8000 + instance", "{{ }}"] {
+            assert!(render_template(template, 1).is_err(), "{}", template);
+        }
+    }
+
+    #[test]
+    fn test_instance_task() {
+        let t = task(
+            1,
+            r#"command = "worker"
+            args = ["--port", "{{ 9000 + instance }}"]
+            env = { PORT = "{{ 8000 + instance }}", MODE = "prod" }
+            instances = 3
+            task_type = { Async = { max_restart = 2 } }"#,
+        );
+        assert_eq!(t.instances, Some(3));
+        let it = t.instance(2).unwrap();
+        assert_eq!(it.instance, Some(2));
+        assert_eq!(it.instances, None);
+        assert_eq!(it.env["PORT"], "8002");
+        assert_eq!(it.env["MODE"], "prod");
+        assert_eq!(it.env["WATCHMEN_INSTANCE"], "2");
+        assert_eq!(it.args, vec!["--port", "9002"]);
+
+        let path = std::env::temp_dir().join(format!("watchmen-instances-{}.ini", std::process::id()));
+        std::fs::write(
+            &path,
+            "[worker]\nid = 1\nname = worker\ncommand = worker\ninstances = 4\ntask_type = async\n",
+        )
+        .unwrap();
+        let tasks = Task::from_ini(&path).unwrap();
+        assert_eq!(tasks.task[0].instances, Some(4));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_scale() {
+        let t = task(
+            201,
+            r#"command = "sleep"
+            args = ["5"]
+            instances = 2
+            stop_timeout = 1
+            task_type = { Async = {} }"#,
+        );
+        global::add(t).await.unwrap();
+        assert!(global::start(flag(201)).await.unwrap().is_success());
+        let started = pids(201).await;
+        assert_eq!(started.len(), 2);
+        assert!(started.iter().all(|(_, pid)| pid.is_some()));
+
+        // 扩容不影响已有实例
+        assert!(global::scale(flag(201), 4).await.unwrap().is_success());
+        let scaled = pids(201).await;
+        assert_eq!(scaled.len(), 4);
+        assert_eq!(scaled[..2], started[..]);
+
+        // 缩容停止并移除多余的实例
+        assert!(global::scale(flag(201), 1).await.unwrap().is_success());
+        assert_eq!(pids(201).await, started[..1]);
+        for (_, pid) in &scaled[1..] {
+            assert!(!watchmend::process::is_alive(pid.unwrap()));
+        }
+
+        global::stop(flag(201), false).await.unwrap();
+        assert!(!watchmend::process::is_alive(started[0].1.unwrap()));
+        let tasks = global::get_all().await.unwrap();
+        assert_eq!(tasks[&201].status.as_deref(), Some("stopped"));
+        cleanup(201).await;
+    }
+
+    #[tokio::test]
+    async fn test_instances_invalid() {
+        let periodic = task(
+            202,
+            r#"command = "sleep"
+            instances = 2
+            task_type = { Periodic = { interval = 60 } }"#,
+        );
+        assert!(global::add(periodic).await.is_err());
+        let zero = task(
+            203,
+            r#"command = "sleep"
+            instances = 0
+            task_type = { Async = {} }"#,
+        );
+        assert!(global::add(zero).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_load_instances() {
+        let mut child = std::process::Command::new("sleep")
+            .arg("30")
+            .spawn()
+            .unwrap();
+        let pid = child.id();
+        let starttime = process::start_time(pid).unwrap();
+        let args = vec!["30".to_string()];
+        common::wait_until("sleep started", || async {
+            process::is_same(pid, starttime, "sleep", &args)
+        })
+        .await;
+
+        // 守护进程异常退出前缓存的任务和实例，实例 2 是缩容前的实例
+        let mut t = task(
+            204,
+            r#"command = "sleep"
+            args = ["30"]
+            instances = 2
+            stop_timeout = 1
+            task_type = { Async = {} }"#,
+        );
+        t.status = Some("running".to_string());
+        let mut cached = vec![t.clone()];
+        for i in 0..3 {
+            let mut it = t.instance(i).unwrap();
+            it.status = Some("running".to_string());
+            if i == 0 {
+                it.pid = Some(pid);
+                it.pid_start_time = Some(starttime);
+            }
+            cached.push(it);
+        }
+        let dir = common::temp_dir("instances-load");
+        let path = dir.join("cache.json");
+        std::fs::write(&path, serde_json::to_string(&cached).unwrap()).unwrap();
+
+        // 仍在运行的实例被接管，其余实例重新启动
+        global::load(path.to_str().unwrap()).await.unwrap();
+        let loaded = pids(204).await;
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(loaded[0], (0, Some(pid)));
+        assert!(process::is_alive(loaded[1].1.unwrap()));
+        assert_ne!(loaded[1].1, Some(pid));
+
+        // 实例的进程号写入缓存
+        let saved = dir.join("saved.json");
+        global::set_cache(saved.to_string_lossy().to_string()).await;
+        global::cache().await.unwrap();
+        let content = wait_file(&saved, |c| serde_json::from_str::<Vec<Task>>(c).is_ok()).await;
+        let tasks: Vec<Task> = serde_json::from_str(&content).unwrap();
+        let mut instances: Vec<(u32, Option<u32>)> = tasks
+            .iter()
+            .filter(|t| t.id == 204 && t.instance.is_some())
+            .map(|t| (t.instance.unwrap(), t.pid))
+            .collect();
+        instances.sort();
+        assert_eq!(instances, loaded);
+
+        cleanup(204).await;
+        child.wait().unwrap();
+        assert!(!process::is_alive(loaded[1].1.unwrap()));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,77 @@
+#[cfg(test)]
+mod tests {
+    use watchmend::cgroup;
+    use watchmend::common::task::{parse_size, Limits, Tasks};
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("1024").unwrap(), 1024);
+        assert_eq!(parse_size("512K").unwrap(), 512 * 1024);
+        assert_eq!(parse_size("256m").unwrap(), 256 * 1024 * 1024);
+        assert_eq!(parse_size("2GB").unwrap(), 2 * 1024 * 1024 * 1024);
+        assert!(parse_size("12X").is_err());
+        assert!(parse_size("M").is_err());
+    }
+
+    #[test]
+    fn test_limits_toml() {
+        let tasks: Tasks = toml::from_str(
+            r#"
+            [[task]]
+            id = 1
+            name = "worker"
+            command = "python3"
+            task_type = "None"
+            limits = { memory_max = "512M", cpu_quota = 1.5, pids_max = 64, nofile = 4096, core = 0 }
+            "#,
+        )
+        .unwrap();
+        let limits = tasks.task[0].limits.clone().unwrap();
+        assert_eq!(limits.memory_max, Some(512 * 1024 * 1024));
+        assert_eq!(limits.core, Some(0));
+        assert!(limits.has_cgroup());
+        assert!(limits.validate().is_ok());
+
+        let limits = Limits {
+            cpu_weight: Some(0),
+            ..Default::default()
+        };
+        assert!(limits.validate().is_err());
+    }
+
+    #[test]
+    fn test_cgroup_prepare() {
+        // 使用临时目录模拟已委派的 cgroup 目录
+        let root = std::env::temp_dir().join(format!("watchmen-cgroup-{}", std::process::id()));
+        let dir = root.join("task-1");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(root.join("cgroup.controllers"), "cpu memory pids").unwrap();
+        for file in ["memory.max", "cpu.max", "cpu.weight", "pids.max"] {
+            std::fs::write(dir.join(file), "").unwrap();
+        }
+        cgroup::init(root.to_str().unwrap()).unwrap();
+
+        let limits = Limits {
+            memory_max: Some(1024),
+            cpu_quota: Some(0.5),
+            ..Default::default()
+        };
+        let joined = cgroup::prepare(1, Some(&limits)).unwrap().unwrap();
+        assert!(joined.ends_with("task-1"));
+        let read = |file: &str| std::fs::read_to_string(dir.join(file)).unwrap();
+        assert_eq!(read("memory.max"), "1024");
+        assert_eq!(read("cpu.max"), "50000 100000");
+        assert_eq!(read("cpu.weight"), "100");
+        assert_eq!(read("pids.max"), "max");
+
+        // 控制器未启用（文件不存在）时无法设置限制
+        std::fs::remove_file(dir.join("pids.max")).unwrap();
+        let limits = Limits {
+            pids_max: Some(10),
+            ..Default::default()
+        };
+        assert!(cgroup::prepare(1, Some(&limits)).is_err());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
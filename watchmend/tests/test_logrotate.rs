@@ -0,0 +1,247 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use std::os::unix::process::{CommandExt, ExitStatusExt};
+    use std::path::{Path, PathBuf};
+    use std::time::{Duration, SystemTime};
+
+    use flate2::read::GzDecoder;
+    use watchmend::common::task::{LogRotate, Task, Tasks};
+    use watchmend::global;
+    use watchmend::logrotate::RotatingFile;
+    use watchmend::process;
+
+    use crate::common::{self, cleanup, flag, get, temp_dir, wait_status, wait_until};
+
+    fn task(id: i64, command: &str, args: &str, out: &Path) -> Task {
+        common::task(
+            id,
+            "logrotate",
+            &format!(
+                r#"command = "{}"
+                args = {}
+                stdout = "{}"
+                stderr = "{}"
+                stop_timeout = 1
+                log_rotate = {{ max_size = 100, max_files = 3 }}
+                task_type = {{ Async = {{}} }}"#,
+                command,
+                args,
+                out.display(),
+                out.display()
+            ),
+        )
+    }
+
+    fn read(path: &Path) -> String {
+        std::fs::read_to_string(path).unwrap()
+    }
+
+    fn rotated(path: &Path, n: u32, ext: &str) -> PathBuf {
+        PathBuf::from(format!("{}.{}{}", path.display(), n, ext))
+    }
+
+    #[test]
+    fn test_log_rotate_config() {
+        let tasks: Tasks = toml::from_str(
+            r#"
+            [[task]]
+            id = 1
+            name = "app"
+            command = "app"
+            stdout = "app.log"
+            log_rotate = { max_size = "1K", daily = true, compress = true }
+            task_type = { Async = {} }
+            "#,
+        )
+        .unwrap();
+        let rotate = tasks.task[0].log_rotate.clone().unwrap();
+        assert_eq!(
+            rotate,
+            LogRotate {
+                max_size: Some(1024),
+                daily: true,
+                max_files: 5,
+                compress: true,
+            }
+        );
+        assert!(rotate.validate().is_ok());
+        assert!(LogRotate::default().validate().is_err());
+
+        let dir = temp_dir("logrotate-config");
+        let path = dir.join("app.ini");
+        std::fs::write(
+            &path,
+            "[app]\nid = 1\nname = app\ncommand = app\ntask_type = async\nlog_max_size = 1K\nlog_daily = true\nlog_compress = true\n",
+        )
+        .unwrap();
+        assert_eq!(Task::from_ini(&path).unwrap().task[0].log_rotate, Some(rotate));
+        std::fs::write(
+            &path,
+            "[app]\nid = 1\nname = app\ncommand = app\ntask_type = async\nlog_daily = true\nlog_max_files = 0\n",
+        )
+        .unwrap();
+        assert!(Task::from_ini(&path).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_rotate_size() {
+        let dir = temp_dir("logrotate-size");
+        let path = dir.join("app.log");
+        let rotate = LogRotate {
+            max_size: Some(10),
+            max_files: 2,
+            ..Default::default()
+        };
+        let mut file = RotatingFile::open(&path, rotate).unwrap();
+        for line in ["aaaaaaaa\n", "bbbbbbbb\n", "cccc\n", "dddd\n", "eeee\n"] {
+            file.write(line.as_bytes()).unwrap();
+        }
+        // 超过 max_files 的最旧文件被删除
+        assert_eq!(read(&path), "eeee\n");
+        assert_eq!(read(&rotated(&path, 1, "")), "cccc\ndddd\n");
+        assert_eq!(read(&rotated(&path, 2, "")), "bbbbbbbb\n");
+        assert!(!rotated(&path, 3, "").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_rotate_daily_compress() {
+        let dir = temp_dir("logrotate-daily");
+        let path = dir.join("app.log");
+        std::fs::write(&path, "yesterday\n").unwrap();
+        let yesterday = SystemTime::now() - Duration::from_secs(2 * 86400);
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(yesterday)
+            .unwrap();
+
+        let rotate = LogRotate {
+            daily: true,
+            compress: true,
+            ..Default::default()
+        };
+        let mut file = RotatingFile::open(&path, rotate).unwrap();
+        file.write(b"today\n").unwrap();
+        file.write(b"again\n").unwrap();
+        assert_eq!(read(&path), "today\nagain\n");
+        assert!(!rotated(&path, 1, "").exists());
+        let mut content = String::new();
+        GzDecoder::new(std::fs::File::open(rotated(&path, 1, ".gz")).unwrap())
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, "yesterday\n");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_rotate_task_output() {
+        let dir = temp_dir("logrotate-task");
+        let path = dir.join("out.log");
+        let files = [
+            rotated(&path, 3, ""),
+            rotated(&path, 2, ""),
+            rotated(&path, 1, ""),
+            path.clone(),
+        ];
+        let output = || {
+            files
+                .iter()
+                .map(|file| std::fs::read_to_string(file).unwrap_or_default())
+                .collect::<String>()
+        };
+        global::add(task(
+            1001,
+            "sh",
+            r#"["-c", "for i in $(seq 1 30); do echo line-$i; echo err-$i >&2; sleep 0.01; done"]"#,
+            &path,
+        ))
+        .await
+        .unwrap();
+        global::start(flag(1001)).await.unwrap();
+        // 进程退出后守护进程可能还在写入剩余的输出
+        wait_until("output of task [1001]", || {
+            let all = output();
+            async move { all.contains("line-30\n") && all.contains("err-30\n") }
+        })
+        .await;
+
+        // stdout 和 stderr 写入同一个文件，由守护进程按大小轮转
+        for file in &files {
+            let content = read(file);
+            assert!(content.len() <= 100, "{}: {}", file.display(), content.len());
+        }
+        assert!(!rotated(&path, 4, "").exists());
+        assert!(!output().contains("line-1\n"));
+        cleanup(1001).await;
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_open_log_failed() {
+        let dir = temp_dir("logrotate-open");
+        // 日志文件的位置是一个目录，无法打开
+        let path = dir.join("out.log");
+        std::fs::create_dir(&path).unwrap();
+        global::add(task(1002, "sleep", r#"["1002"]"#, &path))
+            .await
+            .unwrap();
+        let res = global::start(flag(1002)).await;
+        assert!(res.map_or(true, |r| !r.is_success()));
+
+        // 打开日志文件失败时不启动进程
+        let leaked = process::descendants(std::process::id())
+            .into_iter()
+            .any(|pid| {
+                process::start_time(pid).is_some_and(|starttime| {
+                    process::is_same(pid, starttime, "sleep", &["1002".to_string()])
+                })
+            });
+        assert!(!leaked);
+        assert_eq!(get(1002).await.pid, None);
+        cleanup(1002).await;
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_load_log_rotate() {
+        let dir = temp_dir("logrotate-load");
+        let path = dir.join("out.log");
+        // 上次守护进程启动的进程，输出管道已经随守护进程关闭
+        let mut child = std::process::Command::new("sleep")
+            .arg("30")
+            .process_group(0)
+            .spawn()
+            .unwrap();
+        let pid = child.id();
+        let starttime = process::start_time(pid).unwrap();
+        let args = vec!["30".to_string()];
+        wait_until("exec of the cached process", || {
+            let same = process::is_same(pid, starttime, "sleep", &args);
+            async move { same }
+        })
+        .await;
+        let waiter = std::thread::spawn(move || child.wait().unwrap());
+
+        let mut cached = task(1003, "sleep", r#"["30"]"#, &path);
+        cached.status = Some("running".to_string());
+        cached.pid = Some(pid);
+        cached.pid_start_time = Some(starttime);
+        let cache = dir.join("cache.json");
+        std::fs::write(&cache, serde_json::to_string(&vec![cached]).unwrap()).unwrap();
+
+        // 配置了日志轮转的任务不接管，结束旧进程后重新启动
+        global::load(cache.to_str().unwrap()).await.unwrap();
+        wait_status(1003, "running").await;
+        let loaded = get(1003).await;
+        assert!(loaded.pid.is_some_and(|p| p != pid));
+        assert!(waiter.join().unwrap().signal().is_some());
+        cleanup(1003).await;
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,112 @@
+#[cfg(test)]
+mod tests {
+    use watchmend::common::task::{MisfirePolicy, Task, TaskType, Tasks};
+    use watchmend::monitor::{misfire, missed_runs};
+
+    // 2024-03-01 00:00:00 UTC
+    static BASE: i64 = 1709251200;
+
+    fn task(toml_task: &str) -> Task {
+        let tasks: Tasks = toml::from_str(&format!(
+            "[[task]]\nid = 1\nname = \"job\"\ncommand = \"job.sh\"\ncreated_at = {}\n{}",
+            BASE, toml_task
+        ))
+        .unwrap();
+        tasks.task[0].clone()
+    }
+
+    #[test]
+    fn test_missed_scheduled() {
+        let mut t = task(
+            r#"status = "waiting"
+            task_type = { Scheduled = { cron = "0 2 * * *", timezone = "UTC" } }"#,
+        );
+        // 停止三天多，错过 4 次 02:00 的执行
+        let now = BASE + 3 * 86400 + 3600 * 3;
+        assert_eq!(
+            missed_runs(&t, now, 10),
+            vec![
+                BASE + 7200,
+                BASE + 86400 + 7200,
+                BASE + 2 * 86400 + 7200,
+                BASE + 3 * 86400 + 7200
+            ]
+        );
+        assert_eq!(missed_runs(&t, now, 2).len(), 2);
+
+        // 超过宽限时间的执行不再计入
+        t.misfire_grace = Some(86400);
+        assert_eq!(missed_runs(&t, now, 10), vec![BASE + 3 * 86400 + 7200]);
+
+        // 默认跳过错过的执行
+        assert_eq!(t.misfire_policy(), MisfirePolicy::Skip);
+        assert_eq!(misfire(&mut t, now), 0);
+        match &t.task_type {
+            TaskType::Scheduled(tt) => assert_eq!(tt.last_run, Some(now)),
+            _ => panic!("not a scheduled task"),
+        }
+        assert!(missed_runs(&t, now, 10).is_empty());
+    }
+
+    #[test]
+    fn test_missed_fixed_time() {
+        let t = task(
+            r#"status = "waiting"
+            task_type = { Scheduled = { year = 2024, hour = 2, minute = 30, second = 0, timezone = "UTC" } }"#,
+        );
+        let now = BASE + 2 * 86400;
+        assert_eq!(
+            missed_runs(&t, now, 10),
+            vec![BASE + 9000, BASE + 86400 + 9000]
+        );
+    }
+
+    #[test]
+    fn test_misfire_policy() {
+        let toml_task = r#"status = "waiting"
+            misfire = "run-all"
+            misfire_max = 2
+            task_type = { Scheduled = { cron = "0 * * * *", timezone = "UTC" } }"#;
+        let now = BASE + 5 * 3600 + 60;
+        let mut t = task(toml_task);
+        assert_eq!(misfire(&mut t, now), 2);
+
+        let mut t = task(&toml_task.replace("run-all", "run-once"));
+        assert_eq!(misfire(&mut t, now), 1);
+
+        // 暂停或执行中的任务不处理
+        let mut t = task(&toml_task.replace("waiting", "processing"));
+        assert_eq!(misfire(&mut t, now), 0);
+    }
+
+    #[test]
+    fn test_misfire_periodic() {
+        let toml_task = format!(
+            r#"status = "interval"
+            task_type = {{ Periodic = {{ interval = 60, last_run = {} }} }}"#,
+            BASE
+        );
+        // 停止 10 分 30 秒，错过 10 次执行，默认只执行一次
+        let now = BASE + 630;
+        let mut t = task(&toml_task);
+        assert_eq!(missed_runs(&t, now, 100).len(), 10);
+        assert_eq!(t.misfire_policy(), MisfirePolicy::RunOnce);
+        assert_eq!(misfire(&mut t, now), 1);
+        match &t.task_type {
+            TaskType::Periodic(tt) => assert_eq!(tt.last_run, (BASE + 600) as u64),
+            _ => panic!("not a periodic task"),
+        }
+
+        let mut t = task(&toml_task);
+        t.misfire = Some(MisfirePolicy::RunAll);
+        assert_eq!(misfire(&mut t, now), 10);
+
+        let mut t = task(&toml_task);
+        t.misfire = Some(MisfirePolicy::Skip);
+        assert_eq!(misfire(&mut t, now), 0);
+        match &t.task_type {
+            TaskType::Periodic(tt) => assert_eq!(tt.last_run, (BASE + 600) as u64),
+            _ => panic!("not a periodic task"),
+        }
+    }
+}
@@ -0,0 +1,198 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc;
+
+    use watchmend::common::config::{Notifier, NotifyEvent};
+    use watchmend::common::task::Task;
+    use watchmend::global;
+    use watchmend::notify::{self, Notification};
+
+    use crate::common::{self, cleanup, flag, wait_status};
+
+    fn task(id: i64, command: &str, task_type: &str) -> Task {
+        common::task(
+            id,
+            "notify",
+            &format!(
+                r#"command = "sh"
+                args = ["-c", "{}"]
+                task_type = {}"#,
+                command, task_type
+            ),
+        )
+    }
+
+    /// Local webhook, the first request fails with 500, received requests are sent
+    /// as `(header, body)`
+    async fn stub() -> (String, mpsc::Receiver<(String, String)>, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let (tx, rx) = mpsc::channel(16);
+        let count = Arc::new(AtomicUsize::new(0));
+        let requests = count.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = Vec::new();
+                let mut chunk = [0u8; 1024];
+                let (head, body) = loop {
+                    let n = stream.read(&mut chunk).await.unwrap();
+                    buf.extend_from_slice(&chunk[..n]);
+                    let text = String::from_utf8_lossy(&buf).to_string();
+                    if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                        let len = head
+                            .lines()
+                            .find_map(|l| {
+                                l.to_lowercase()
+                                    .strip_prefix("content-length: ")
+                                    .map(|v| v.trim().parse::<usize>().unwrap())
+                            })
+                            .unwrap_or(0);
+                        if body.len() >= len || n == 0 {
+                            break (head.to_string(), body.to_string());
+                        }
+                    }
+                };
+                let status = if requests.fetch_add(1, Ordering::SeqCst) == 0 {
+                    "500 Internal Server Error"
+                } else {
+                    tx.send((head, body)).await.unwrap();
+                    "200 OK"
+                };
+                let res = format!("HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status);
+                stream.write_all(res.as_bytes()).await.unwrap();
+            }
+        });
+        (url, rx, count)
+    }
+
+    #[test]
+    fn test_notify_render() {
+        let t = task(1, "exit 3", "{ Async = {} }");
+        let n = Notification::new(
+            NotifyEvent::GaveUp,
+            &t,
+            None,
+            "say \"bye\"\n".to_string(),
+        );
+        let body = notify::render(
+            r#"{"text": "{{ name }} {{event}}: {{ message }}", "code": {{ code }}, "other": "{{ other }}"}"#,
+            &n,
+        );
+        assert_eq!(
+            body,
+            r#"{"text": "notify-1 gave_up: say \"bye\"\n", "code": null, "other": "{{ other }}"}"#
+        );
+
+        let notifier: Notifier = toml::from_str(
+            "url = \"http://127.0.0.1/hook\"\nevents = [\"exited\", \"schedule_failed\"]\nheaders = { Authorization = \"Bearer token\" }\nretries = 5\n",
+        )
+        .unwrap();
+        assert_eq!(
+            notifier.events,
+            vec![NotifyEvent::Exited, NotifyEvent::ScheduleFailed]
+        );
+        assert_eq!(notifier.retries, Some(5));
+        assert!(notifier.body.is_none());
+
+        // 模板渲染结果不是合法 JSON 时配置无效
+        let mut invalid = notifier.clone();
+        invalid.body = Some("{\"text\": {{ name }}}".to_string());
+        assert!(notify::init(vec![invalid]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_notify_events() {
+        let (url, mut rx, count) = stub().await;
+        let mut headers = std::collections::HashMap::new();
+        headers.insert("X-Token".to_string(), "t0ken".to_string());
+        notify::init(vec![
+            Notifier {
+                url: url.clone(),
+                events: vec![
+                    NotifyEvent::Exited,
+                    NotifyEvent::Restarted,
+                    NotifyEvent::GaveUp,
+                ],
+                body: Some(
+                    r#"{"text": "{{ name }} {{ event }}: {{ message }}", "code": {{ code }}}"#
+                        .to_string(),
+                ),
+                headers,
+                retries: Some(2),
+                backoff_ms: Some(50),
+            },
+            Notifier {
+                url,
+                events: vec![NotifyEvent::ScheduleFailed],
+                body: None,
+                headers: Default::default(),
+                retries: Some(0),
+                backoff_ms: None,
+            },
+        ])
+        .unwrap();
+
+        // 崩溃、自动重启、达到 max_restart 后放弃
+        global::add(task(901, "exit 3", "{ Async = { max_restart = 1 } }"))
+            .await
+            .unwrap();
+        global::start(flag(901)).await.unwrap();
+        wait_status(901, "auto restart").await;
+        watchmend::monitor::rerun_tasks(20).await.unwrap();
+        wait_status(901, "errored").await;
+
+        // 定时任务执行失败
+        global::add(task(902, "exit 2", r#"{ Scheduled = { cron = "0 0 1 1 *" } }"#))
+            .await
+            .unwrap();
+        global::start(flag(902)).await.unwrap();
+
+        let mut bodies = Vec::new();
+        while bodies.len() < 5 {
+            let (head, body) = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+                .await
+                .expect("notification not received")
+                .unwrap();
+            assert!(head.starts_with("POST /hook "));
+            bodies.push((head, body));
+        }
+        // 第一次请求失败后重试
+        assert_eq!(count.load(Ordering::SeqCst), 6);
+
+        let custom: Vec<&str> = bodies
+            .iter()
+            .filter(|(head, _)| head.to_lowercase().contains("x-token: t0ken"))
+            .map(|(_, body)| body.as_str())
+            .collect();
+        for expected in [
+            r#"{"text": "notify-901 exited: exited with code 3", "code": 3}"#,
+            r#"{"text": "notify-901 restarted: exited with code 3", "code": 3}"#,
+            r#"{"text": "notify-901 gave_up: crashed 1 times in a row", "code": 3}"#,
+        ] {
+            assert!(custom.contains(&expected), "{:?}", custom);
+        }
+        assert_eq!(custom.len(), 4);
+
+        let (_, scheduled) = bodies
+            .iter()
+            .find(|(head, _)| !head.to_lowercase().contains("x-token"))
+            .unwrap();
+        let value: serde_json::Value = serde_json::from_str(scheduled).unwrap();
+        assert_eq!(value["event"], "schedule_failed");
+        assert_eq!(value["id"], 902);
+        assert_eq!(value["name"], "notify-902");
+        assert_eq!(value["code"], 2);
+        assert_eq!(value["message"], "exited with code 2");
+        cleanup(901).await;
+        cleanup(902).await;
+    }
+}
@@ -0,0 +1,92 @@
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use watchmend::common::task::{TaskFlag, Tasks};
+    use watchmend::global;
+    use watchmend::process::{self, ProcessHandle};
+
+    #[test]
+    fn test_process_handle() {
+        let mut child = std::process::Command::new("sleep").arg("5").spawn().unwrap();
+        let pid = child.id();
+        let starttime = process::start_time(pid).unwrap();
+
+        // 启动时间不一致，视为进程号已被其他进程复用
+        let other = ProcessHandle::new(pid, Some(starttime + 1));
+        assert!(other.is_replaced());
+        assert!(!other.signal(libc::SIGKILL).unwrap());
+        assert!(!other.signal_group(libc::SIGKILL).unwrap());
+        assert!(process::is_alive(pid));
+
+        let handle = ProcessHandle::new(pid, Some(starttime));
+        assert!(!handle.is_replaced());
+        assert!(handle.is_alive());
+        assert!(handle.signal(libc::SIGKILL).unwrap());
+        child.wait().unwrap();
+        assert!(!handle.is_alive());
+    }
+
+    #[tokio::test]
+    async fn test_terminate_reused_pid() {
+        let mut child = std::process::Command::new("sleep").arg("5").spawn().unwrap();
+        let pid = child.id();
+        let starttime = process::start_time(pid).unwrap();
+        let timeout = Duration::from_secs(1);
+
+        let code = process::terminate(pid, Some(starttime + 1), libc::SIGTERM, timeout, None, false)
+            .await
+            .unwrap();
+        assert_eq!(code, None);
+        assert!(process::is_alive(pid));
+
+        process::terminate(pid, Some(starttime), libc::SIGTERM, timeout, None, false)
+            .await
+            .unwrap();
+        child.wait().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_matrix_periodic_runs() {
+        let tasks: Tasks = toml::from_str(
+            r#"
+            [[task]]
+            id = 1401
+            name = "matrix-1401"
+            command = "sleep"
+            args = ["30"]
+            stop_timeout = 1
+            task_type = { Periodic = { interval = 60, concurrency_policy = "allow" } }
+            "#,
+        )
+        .unwrap();
+        global::add(tasks.task[0].clone()).await.unwrap();
+        let flag = || TaskFlag {
+            id: 1401,
+            name: None,
+            group: None,
+            mat: false,
+        };
+        let pid = || async { global::get_all().await.unwrap()[&1401].pid.unwrap() };
+        global::start(flag()).await.unwrap();
+        let first = pid().await;
+        global::start(flag()).await.unwrap();
+        let second = pid().await;
+        assert_ne!(first, second);
+
+        // 并发执行中的每个进程都属于任务
+        assert!(global::matrix(first as usize).await.is_ok());
+        assert!(global::matrix(second as usize).await.is_ok());
+        assert!(global::matrix(std::process::id() as usize).await.is_err());
+
+        // 执行结束的进程不再属于任务
+        process::signal_pgid(first, libc::SIGKILL).unwrap();
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while global::matrix(first as usize).await.is_ok() {
+            assert!(std::time::Instant::now() < deadline, "run not reaped");
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        process::signal_pgid(second, libc::SIGKILL).unwrap();
+        global::remove(flag(), false).await.unwrap();
+    }
+}
@@ -0,0 +1,137 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use watchmend::common::task::Task;
+    use watchmend::global;
+    use watchmend::process::is_alive;
+
+    use crate::common::{self, cleanup, flag, get, wait_status};
+
+    fn task(id: i64, toml_task: &str) -> Task {
+        common::task(id, "api", &format!("stop_timeout = 1\n{}", toml_task))
+    }
+
+    async fn instances(id: i64) -> Vec<Task> {
+        let mut instances: Vec<Task> = global::get_instances()
+            .await
+            .into_iter()
+            .filter(|t| t.id == id)
+            .collect();
+        instances.sort_by_key(|t| t.instance);
+        instances
+    }
+
+    #[tokio::test]
+    async fn test_rolling_reload() {
+        let log = std::env::temp_dir().join(format!("watchmen-reload-{}.log", std::process::id()));
+        let config = |version: &str, script: &str| {
+            task(
+                301,
+                &format!(
+                    r#"command = "sh"
+                    args = ["-c", "{}"]
+                    env = {{ VERSION = "{}" }}
+                    stdout = "{}"
+                    readiness = {{ type = "log", pattern = "ready", timeout = 5 }}
+                    task_type = {{ Async = {{}} }}"#,
+                    script,
+                    version,
+                    log.display()
+                ),
+            )
+        };
+        global::add(config("1", "sleep 1; echo ready; exec sleep 10")).await.unwrap();
+        global::start(flag(301)).await.unwrap();
+        wait_status(301, "running").await;
+        let old = get(301).await.pid.unwrap();
+
+        // 新进程就绪后才停止旧进程
+        let res = global::reload(config("2", "echo ready; exec sleep 10")).await.unwrap();
+        assert!(res.is_success(), "{}", res.msg);
+        let reloaded = get(301).await;
+        assert_eq!(reloaded.status.as_deref(), Some("running"));
+        assert_eq!(reloaded.env["VERSION"], "2");
+        assert_ne!(reloaded.pid, Some(old));
+        assert!(!is_alive(old));
+
+        // 新进程启动失败时旧进程继续运行
+        let res = global::reload(config("3", "exit 1")).await.unwrap();
+        assert!(!res.is_success());
+        let kept = get(301).await;
+        assert_eq!(kept.pid, reloaded.pid);
+        assert_eq!(kept.env["VERSION"], "2");
+        assert!(is_alive(kept.pid.unwrap()));
+
+        cleanup(301).await;
+        assert!(!is_alive(kept.pid.unwrap()));
+        std::fs::remove_file(&log).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_rolling_reload_instances() {
+        let config = |script: &str| {
+            task(
+                302,
+                &format!(
+                    r#"command = "sh"
+                    args = ["-c", "{}"]
+                    instances = 3
+                    task_type = {{ Async = {{}} }}"#,
+                    script
+                ),
+            )
+        };
+        global::add(config("exec sleep 10")).await.unwrap();
+        global::start(flag(302)).await.unwrap();
+        let old = instances(302).await;
+
+        let res = global::reload(config("exec sleep 11")).await.unwrap();
+        assert!(res.is_success(), "{}", res.msg);
+        let reloaded = instances(302).await;
+        assert_eq!(reloaded.len(), 3);
+        for (o, r) in old.iter().zip(&reloaded) {
+            assert_ne!(o.pid, r.pid);
+            assert!(!is_alive(o.pid.unwrap()));
+            assert_eq!(r.args[1], "exec sleep 11");
+        }
+
+        // 实例 1 启动失败，已替换的实例 0 回滚到旧配置，其余实例不受影响
+        let res = global::reload(config("test {{ instance }} -ne 1 && exec sleep 12"))
+            .await
+            .unwrap();
+        assert!(!res.is_success());
+        let rolled = instances(302).await;
+        assert_eq!(rolled[0].args[1], "exec sleep 11");
+        assert_ne!(rolled[0].pid, reloaded[0].pid);
+        assert_eq!(rolled[1].pid, reloaded[1].pid);
+        assert_eq!(rolled[2].pid, reloaded[2].pid);
+        assert!(rolled.iter().all(|t| is_alive(t.pid.unwrap())));
+
+        cleanup(302).await;
+        assert!(rolled.iter().all(|t| !is_alive(t.pid.unwrap())));
+    }
+
+    #[tokio::test]
+    async fn test_reload_invalid() {
+        let config = |log_rotate: &str| {
+            task(
+                303,
+                &format!(
+                    r#"command = "sleep"
+                    args = ["10"]
+                    {}
+                    task_type = {{ Async = {{}} }}"#,
+                    log_rotate
+                ),
+            )
+        };
+        global::add(config("")).await.unwrap();
+
+        // 新配置无效时不移除未运行的旧任务
+        let invalid = config("log_rotate = { daily = true, max_files = 0 }");
+        assert!(global::reload(invalid).await.is_err());
+        assert!(get(303).await.log_rotate.is_none());
+        cleanup(303).await;
+    }
+}
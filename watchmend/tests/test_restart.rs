@@ -0,0 +1,148 @@
+#[cfg(test)]
+mod tests {
+    use watchmend::common::task::{AsyncTask, RestartPolicy, Task, TaskFlag, TaskType};
+    use watchmend::global;
+
+    fn async_task(max_restart: Option<u64>, policy: Option<RestartPolicy>) -> Task {
+        Task {
+            restart_policy: policy,
+            task_type: TaskType::Async(AsyncTask {
+                max_restart,
+                has_restart: 0,
+                started_at: 0,
+                stopped_at: 0,
+                stopped_by_user: false,
+                restart_delay: 0,
+                backoff_multiplier: 2.0,
+                max_backoff: None,
+                min_uptime: None,
+                next_restart_at: 0,
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_default_policy() {
+        // 未配置重启策略时保持原有行为：配置了 max_restart 才在失败时重启
+        let task = async_task(None, None);
+        assert_eq!(task.restart_policy(), RestartPolicy::Never);
+        assert!(!task.should_restart(Some(1)));
+
+        let task = async_task(Some(3), None);
+        assert_eq!(task.restart_policy(), RestartPolicy::OnFailure);
+        assert!(task.should_restart(Some(1)));
+        assert!(task.should_restart(Some(143)));
+        assert!(!task.should_restart(Some(0)));
+    }
+
+    #[test]
+    fn test_recover_stopped() {
+        // 未配置重启策略时保持原有行为：除 kill -15 外意外停止的任务都会被恢复
+        let mut task = async_task(None, None);
+        task.code = Some(1);
+        assert!(task.should_recover());
+        task.code = Some(0);
+        assert!(task.should_recover());
+        task.code = Some(143);
+        assert!(!task.should_recover());
+
+        task.code = Some(1);
+        if let TaskType::Async(tt) = &mut task.task_type {
+            tt.stopped_by_user = true;
+        }
+        assert!(!task.should_recover());
+
+        let mut task = async_task(Some(0), None);
+        task.code = Some(1);
+        assert!(!task.should_recover());
+
+        // 配置了重启策略时按策略判断
+        let mut task = async_task(None, Some(RestartPolicy::OnFailure));
+        task.code = Some(0);
+        assert!(!task.should_recover());
+        task.code = Some(143);
+        assert!(task.should_recover());
+    }
+
+    #[test]
+    fn test_on_failure_exit_codes() {
+        let mut task = async_task(None, Some(RestartPolicy::OnFailure));
+        task.success_exit_codes = vec![0, 2];
+        task.restart_exit_codes = vec![1, 137];
+        assert!(!task.should_restart(Some(0)));
+        assert!(!task.should_restart(Some(2)));
+        assert!(task.should_restart(Some(1)));
+        assert!(task.should_restart(Some(137)));
+        assert!(!task.should_restart(Some(3)));
+        // 退出码未知（接管的进程）不视为失败
+        assert!(!task.should_restart(None));
+        let task = async_task(None, Some(RestartPolicy::Always));
+        assert!(task.should_restart(None));
+    }
+
+    #[test]
+    fn test_always_and_stopped_by_user() {
+        let mut task = async_task(None, Some(RestartPolicy::Always));
+        assert!(task.should_restart(Some(0)));
+        if let TaskType::Async(tt) = &mut task.task_type {
+            tt.stopped_by_user = true;
+        }
+        assert!(!task.should_restart(Some(1)));
+
+        let task = async_task(None, Some(RestartPolicy::Never));
+        assert!(!task.should_restart(Some(1)));
+    }
+
+    #[test]
+    fn test_parse_policy() {
+        assert_eq!(
+            "unless-stopped".parse::<RestartPolicy>().unwrap(),
+            RestartPolicy::UnlessStopped
+        );
+        assert!("sometimes".parse::<RestartPolicy>().is_err());
+        let task: Task = serde_json::from_str(
+            r#"{"id":1,"name":"t","command":"sh","restart_policy":"on-failure","task_type":"None"}"#,
+        )
+        .unwrap();
+        assert_eq!(task.restart_policy, Some(RestartPolicy::OnFailure));
+        assert_eq!(task.success_exit_codes, vec![0]);
+    }
+
+    #[tokio::test]
+    async fn test_max_restart_zero_not_recovered() {
+        let mut task = async_task(Some(0), Some(RestartPolicy::Always));
+        task.id = 1201;
+        task.name = "restart-1201".to_string();
+        task.command = "sh".to_string();
+        global::add(task).await.unwrap();
+
+        // 重启次数为 0 时进程退出后直接停止
+        global::update(
+            1201,
+            Some(None),
+            Some(Some("auto restart".to_string())),
+            Some(Some(1)),
+            Some(true),
+            None,
+        )
+        .await
+        .unwrap();
+        let get = || async { global::get_all().await.unwrap().remove(&1201).unwrap() };
+        assert_eq!(get().await.status.as_deref(), Some("stopped"));
+
+        // 监控不会把重启次数已用完的任务当作意外关闭恢复
+        watchmend::monitor::rerun_tasks(20).await.unwrap();
+        let stopped = get().await;
+        assert_eq!(stopped.status.as_deref(), Some("stopped"));
+        assert_eq!(stopped.pid, None);
+
+        let flag = TaskFlag {
+            id: 1201,
+            name: None,
+            group: None,
+            mat: false,
+        };
+        global::remove(flag, false).await.unwrap();
+    }
+}
@@ -0,0 +1,103 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use watchmend::common::handle::{Command, Request, Status};
+    use watchmend::common::task::{EnvValue, Task, REDACTED};
+    use watchmend::global;
+
+    use crate::common::{self, cleanup, flag, get, wait_file};
+
+    fn task(id: i64, secret_file: &str, stdout: &str) -> Task {
+        common::task(
+            id,
+            "secret",
+            &format!(
+                r#"command = "sh"
+                args = ["-c", "echo $DB_PASSWORD $API_TOKEN $MODE"]
+                stdout = "{}"
+                env = {{ MODE = "prod", DB_PASSWORD = {{ secret_file = "{}" }}, API_TOKEN = {{ from_env = "WATCHMEN_TEST_TOKEN" }} }}
+                task_type = {{ Async = {{}} }}"#,
+                stdout, secret_file
+            ),
+        )
+    }
+
+    #[test]
+    fn test_secret_config() {
+        let t = task(1, "/run/secrets/db", "");
+        assert_eq!(t.env["MODE"], "prod");
+        assert_eq!(
+            t.env["DB_PASSWORD"],
+            EnvValue::SecretFile {
+                secret_file: "/run/secrets/db".to_string()
+            }
+        );
+        assert!(t.env["API_TOKEN"].is_secret());
+
+        // 状态和日志中不出现密钥来源
+        let status = Status::from(t.clone());
+        assert_eq!(status.env["MODE"], "prod");
+        assert_eq!(status.env["DB_PASSWORD"], REDACTED);
+        assert_eq!(status.env["API_TOKEN"], REDACTED);
+        let log = format!(
+            "{:?}",
+            Request {
+                command: Command::Add(t.clone())
+            }
+        );
+        assert!(!log.contains("/run/secrets/db"));
+        assert!(!log.contains("WATCHMEN_TEST_TOKEN"));
+        assert!(log.contains(REDACTED));
+
+        // 缓存中只保存引用，重启后可以再次解析
+        let json = serde_json::to_string(&t).unwrap();
+        let cached: Task = serde_json::from_str(&json).unwrap();
+        assert_eq!(cached.env, t.env);
+
+        let path = std::env::temp_dir().join(format!("watchmen-secret-{}.ini", std::process::id()));
+        std::fs::write(
+            &path,
+            "[app]\nid = 1\nname = app\ncommand = app\ntask_type = async\nenv = MODE=prod\nsecret_file = DB_PASSWORD=/run/secrets/db\nfrom_env = API_TOKEN=WATCHMEN_TEST_TOKEN\n",
+        )
+        .unwrap();
+        let ini = Task::from_ini(&path).unwrap().task[0].clone();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(ini.env, t.env);
+    }
+
+    #[tokio::test]
+    async fn test_secret_start() {
+        let dir = std::env::temp_dir();
+        let pid = std::process::id();
+        let secret = dir.join(format!("watchmen-secret-db-{}", pid));
+        let stdout = dir.join(format!("watchmen-secret-{}.log", pid));
+        std::fs::write(&secret, "s3cr3t\n").unwrap();
+        std::env::set_var("WATCHMEN_TEST_TOKEN", "t0ken");
+
+        global::add(task(701, &secret.to_string_lossy(), &stdout.to_string_lossy()))
+            .await
+            .unwrap();
+        global::start(flag(701)).await.unwrap();
+        let output = wait_file(&stdout, |s| s.ends_with('\n')).await;
+        assert_eq!(output, "s3cr3t t0ken prod\n");
+
+        // 密钥只在启动进程时解析，不保存在任务中
+        let added = get(701).await;
+        let json = serde_json::to_string(&added).unwrap();
+        assert!(!json.contains("s3cr3t"));
+        assert!(!json.contains("t0ken"));
+
+        // 无法解析密钥时启动失败
+        std::fs::remove_file(&secret).unwrap();
+        let missing = task(702, &secret.to_string_lossy(), &stdout.to_string_lossy());
+        global::add(missing).await.unwrap();
+        let res = global::start(flag(702)).await;
+        let err = res.unwrap_err().to_string();
+        assert!(err.contains("DB_PASSWORD"), "{}", err);
+        assert!(!err.contains("t0ken"));
+        cleanup(701).await;
+        cleanup(702).await;
+        std::fs::remove_file(&stdout).unwrap();
+    }
+}
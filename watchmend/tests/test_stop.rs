@@ -0,0 +1,146 @@
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use tokio::process::Command;
+    use tokio::task::JoinHandle;
+
+    use watchmend::process;
+
+    /// Start `sh -c script` in its own process group like tasks are started, the
+    /// handle returns the exit code of the child
+    fn spawn(script: &str) -> (u32, JoinHandle<Option<i32>>) {
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(script)
+            .process_group(0)
+            .spawn()
+            .unwrap();
+        let pid = child.id().unwrap();
+        let jh = tokio::spawn(async move {
+            let status = child.wait().await.ok()?;
+            process::exit_code(&status)
+        });
+        (pid, jh)
+    }
+
+    async fn wait_started(pid: u32) -> u64 {
+        // 出现 sleep 子进程时 shell 已经设置好 trap
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while process::descendants(pid).is_empty() {
+            assert!(Instant::now() < deadline, "process {} not started", pid);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        process::start_time(pid).unwrap()
+    }
+
+    #[test]
+    fn test_parse_signal() {
+        for input in ["SIGTERM", "TERM", "term", "15"] {
+            assert_eq!(process::parse_signal(input).unwrap(), libc::SIGTERM);
+        }
+        assert_eq!(process::parse_signal("SIGQUIT").unwrap(), libc::SIGQUIT);
+        assert!(process::parse_signal("SIGFOO").is_err());
+        assert!(process::parse_signal("64").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_stop_signal_graceful() {
+        let (pid, jh) = spawn("trap 'exit 3' QUIT; while true; do sleep 0.1; done");
+        let starttime = wait_started(pid).await;
+        let started = Instant::now();
+
+        // 进程处理停止信号后自行退出，返回其退出码
+        let code = process::terminate(
+            pid,
+            Some(starttime),
+            libc::SIGQUIT,
+            Duration::from_secs(5),
+            Some(jh),
+            false,
+        )
+        .await
+        .unwrap();
+        assert_eq!(code, Some(3));
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(!process::is_alive(pid));
+    }
+
+    #[tokio::test]
+    async fn test_stop_timeout_sigkill() {
+        let (pid, jh) = spawn("trap '' TERM; while true; do sleep 0.1; done");
+        let starttime = wait_started(pid).await;
+        let started = Instant::now();
+
+        // 忽略 SIGTERM 的进程在 stop_timeout 之后被 SIGKILL 结束
+        let code = process::terminate(
+            pid,
+            Some(starttime),
+            libc::SIGTERM,
+            Duration::from_secs(1),
+            Some(jh),
+            false,
+        )
+        .await
+        .unwrap();
+        assert_eq!(code, Some(128 + libc::SIGKILL));
+        assert!(started.elapsed() >= Duration::from_secs(1));
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(!process::is_alive(pid));
+    }
+
+    #[tokio::test]
+    async fn test_stop_process_group() {
+        let (pid, jh) = spawn("sleep 100 & wait");
+        let starttime = wait_started(pid).await;
+        let grandchild = process::descendants(pid)[0];
+        assert_eq!(process::proc_stat(grandchild).unwrap().pgrp, pid as i32);
+
+        // 停止信号发送给整个进程组，后台的子进程一同退出
+        process::terminate(
+            pid,
+            Some(starttime),
+            libc::SIGTERM,
+            Duration::from_secs(5),
+            Some(jh),
+            false,
+        )
+        .await
+        .unwrap();
+        assert!(process::start_time(grandchild).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_kill_descendants() {
+        // 子进程通过 setsid 离开了任务的进程组
+        let (pid, jh) = spawn("setsid sleep 100 & wait");
+        let starttime = wait_started(pid).await;
+        let grandchild = process::descendants(pid)[0];
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while process::proc_stat(grandchild).unwrap().pgrp == pid as i32 {
+            assert!(Instant::now() < deadline, "setsid not executed");
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+
+        process::terminate(
+            pid,
+            Some(starttime),
+            libc::SIGTERM,
+            Duration::from_secs(5),
+            Some(jh),
+            true,
+        )
+        .await
+        .unwrap();
+        // 逃逸的子进程在退出后由 init 回收
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while process::start_time(grandchild).is_some() {
+            assert!(
+                Instant::now() < deadline,
+                "descendant {} still alive",
+                grandchild
+            );
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    }
+}
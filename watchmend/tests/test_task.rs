@@ -26,6 +26,12 @@ mod tests {
             has_restart: 0,
             started_at: 0,
             stopped_at: 0,
+            stopped_by_user: false,
+            restart_delay: 0,
+            backoff_multiplier: 2.0,
+            max_backoff: None,
+            min_uptime: None,
+            next_restart_at: 0,
         });
 
         let request = Request {
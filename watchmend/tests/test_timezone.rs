@@ -0,0 +1,112 @@
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+    use chrono_tz::Tz;
+    use watchmend::common::cron::{resolve_local, Cron};
+    use watchmend::common::task::{TaskType, Tasks};
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S")
+            .unwrap()
+            .and_utc()
+    }
+
+    fn next_utc(expr: &str, tz: Tz, after: &str) -> String {
+        let cron: Cron = expr.parse().unwrap();
+        cron.next_after_in(&utc(after).with_timezone(&tz))
+            .unwrap()
+            .with_timezone(&Utc)
+            .format("%Y-%m-%d %H:%M:%S")
+            .to_string()
+    }
+
+    #[test]
+    fn test_timezone_cron() {
+        // 服务器使用 UTC，任务在上海时间 02:00 执行
+        let shanghai: Tz = "Asia/Shanghai".parse().unwrap();
+        assert_eq!(
+            next_utc("0 2 * * *", shanghai, "2024-03-01 12:00:00"),
+            "2024-03-01 18:00:00"
+        );
+        assert_eq!(
+            next_utc("0 2 * * *", shanghai, "2024-03-01 18:00:00"),
+            "2024-03-02 18:00:00"
+        );
+    }
+
+    #[test]
+    fn test_timezone_dst_gap() {
+        // 2024-03-10 02:00 -> 03:00，02:30 不存在，在跳变结束时执行一次
+        let new_york: Tz = "America/New_York".parse().unwrap();
+        assert_eq!(
+            next_utc("30 2 * * *", new_york, "2024-03-10 05:00:00"),
+            "2024-03-10 07:00:00"
+        );
+        assert_eq!(
+            next_utc("30 2 * * *", new_york, "2024-03-10 07:00:00"),
+            "2024-03-11 06:30:00"
+        );
+        // 跳过时段内的多个时间只执行一次
+        assert_eq!(
+            next_utc("*/20 2-3 * * *", new_york, "2024-03-10 06:59:00"),
+            "2024-03-10 07:00:00"
+        );
+        assert_eq!(
+            next_utc("*/20 2-3 * * *", new_york, "2024-03-10 07:00:00"),
+            "2024-03-10 07:20:00"
+        );
+    }
+
+    #[test]
+    fn test_timezone_dst_repeated() {
+        // 2024-11-03 02:00 -> 01:00，01:30 出现两次，只在第一次执行
+        let new_york: Tz = "America/New_York".parse().unwrap();
+        assert_eq!(
+            next_utc("30 1 * * *", new_york, "2024-11-03 04:00:00"),
+            "2024-11-03 05:30:00"
+        );
+        assert_eq!(
+            next_utc("30 1 * * *", new_york, "2024-11-03 05:30:00"),
+            "2024-11-04 06:30:00"
+        );
+        // 第二次经过重复时段时不再执行
+        assert_eq!(
+            next_utc("*/30 1 * * *", new_york, "2024-11-03 06:10:00"),
+            "2024-11-04 06:00:00"
+        );
+
+        let naive = NaiveDateTime::parse_from_str("2024-11-03 01:30:00", "%Y-%m-%d %H:%M:%S").unwrap();
+        assert_eq!(
+            resolve_local(&new_york, naive).unwrap(),
+            new_york.from_local_datetime(&naive).earliest().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_timezone_task() {
+        let tasks: Tasks = toml::from_str(
+            r#"
+            [[task]]
+            id = 1
+            name = "report"
+            command = "report.sh"
+            task_type = { Scheduled = { cron = "0 2 * * *", timezone = "Asia/Shanghai" } }
+
+            [[task]]
+            id = 2
+            name = "invalid"
+            command = "report.sh"
+            task_type = { Scheduled = { hour = 2, timezone = "Mars/Olympus" } }
+            "#,
+        )
+        .unwrap();
+        match (&tasks.task[0].task_type, &tasks.task[1].task_type) {
+            (TaskType::Scheduled(valid), TaskType::Scheduled(invalid)) => {
+                assert_eq!(valid.tz().unwrap(), Some(chrono_tz::Asia::Shanghai));
+                assert!(valid.validate().is_ok());
+                assert!(invalid.validate().is_err());
+            }
+            _ => panic!("not scheduled tasks"),
+        }
+    }
+}
@@ -0,0 +1,128 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+    use std::time::Duration;
+
+    use watchmend::common::task::Task;
+    use watchmend::global;
+    use watchmend::watch::Matcher;
+
+    use crate::common::{self, assert_stays, cleanup, flag, get, wait_until};
+
+    static DEBOUNCE_MS: u64 = 200;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = common::temp_dir(name);
+        std::fs::create_dir_all(dir.join("src/lib")).unwrap();
+        dir
+    }
+
+    fn task(id: i64, dir: &Path, watch: &str, ignore: &str) -> Task {
+        common::task(
+            id,
+            "watch",
+            &format!(
+                r#"command = "sleep"
+                args = ["30"]
+                dir = "{}"
+                stop_timeout = 1
+                watch = {}
+                watch_ignore = {}
+                watch_debounce_ms = {}
+                task_type = {{ Async = {{}} }}"#,
+                dir.display(),
+                watch,
+                ignore,
+                DEBOUNCE_MS
+            ),
+        )
+    }
+
+    #[test]
+    fn test_watch_matcher() {
+        let dir = temp_dir("matcher");
+        let t = task(
+            1,
+            &dir,
+            r#"["src", "app.py", "conf/*.toml"]"#,
+            r#"["*.pyc", "__pycache__", "src/lib/*.tmp"]"#,
+        );
+        let matcher = Matcher::new(&t).unwrap();
+        assert!(matcher.is_match(&dir.join("src/main.py")));
+        assert!(matcher.is_match(&dir.join("src/lib/util.py")));
+        assert!(matcher.is_match(&dir.join("app.py")));
+        assert!(matcher.is_match(&dir.join("conf/app.toml")));
+        assert!(!matcher.is_match(&dir.join("conf/nested/app.toml")));
+        assert!(!matcher.is_match(&dir.join("README.md")));
+        assert!(!matcher.is_match(&dir.join("src/main.pyc")));
+        assert!(!matcher.is_match(&dir.join("src/__pycache__/main.py")));
+        assert!(!matcher.is_match(&dir.join("src/lib/a.tmp")));
+        assert!(matcher.is_match(&dir.join("src/a.tmp")));
+
+        let path = dir.join("watch.ini");
+        std::fs::write(
+            &path,
+            "[app]\nid = 1\nname = app\ncommand = python\ntask_type = async\nwatch = src app.py\nwatch_ignore = *.pyc\nwatch_debounce_ms = 300\n",
+        )
+        .unwrap();
+        let tasks = Task::from_ini(&path).unwrap();
+        assert_eq!(
+            tasks.task[0].watch,
+            Some(vec!["src".to_string(), "app.py".to_string()])
+        );
+        assert_eq!(tasks.task[0].watch_ignore, vec!["*.pyc".to_string()]);
+        assert_eq!(tasks.task[0].watch_debounce_ms, Some(300));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_watch_restart() {
+        let dir = temp_dir("watch-restart");
+        // 变化不触发重启时，等待几个防抖周期确认任务没有被重启
+        let quiet = Duration::from_millis(DEBOUNCE_MS * 4);
+        global::add(task(401, &dir, r#"["src"]"#, r#"["*.log"]"#))
+            .await
+            .unwrap();
+        global::start(flag(401)).await.unwrap();
+        let first = get(401).await.pid.unwrap();
+
+        // 忽略的文件变化不重启
+        std::fs::write(dir.join("src/app.log"), "log").unwrap();
+        assert_stays("pid of task [401]", quiet, || async {
+            get(401).await.pid == Some(first)
+        })
+        .await;
+
+        // 连续多次修改只重启一次
+        for i in 0..3 {
+            std::fs::write(dir.join("src/app.py"), format!("print({})", i)).unwrap();
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        wait_until("task [401] restarted", || async {
+            let task = get(401).await;
+            task.pid.is_some_and(|pid| pid != first) && task.status.as_deref() == Some("running")
+        })
+        .await;
+        let task = get(401).await;
+        assert!(!watchmend::process::is_alive(first));
+        assert_eq!(task.restart_reason.as_deref(), Some("restarted by file change"));
+        let second = task.pid;
+        assert_stays("pid of task [401]", quiet, || async {
+            get(401).await.pid == second
+        })
+        .await;
+
+        // 停止后的任务不因文件变化启动
+        global::stop(flag(401), false).await.unwrap();
+        std::fs::write(dir.join("src/app.py"), "print()").unwrap();
+        assert_stays("status of task [401]", quiet, || async {
+            let task = get(401).await;
+            task.status.as_deref() == Some("stopped") && task.pid.is_none()
+        })
+        .await;
+        cleanup(401).await;
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,107 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::time::{Duration, Instant};
+
+    use watchmend::common::task::Tasks;
+    use watchmend::process;
+    use watchmend::watchdog::{exceeded, Usage};
+
+    fn tasks() -> Tasks {
+        toml::from_str(
+            r#"
+            [[task]]
+            id = 1
+            name = "worker"
+            command = "python3"
+            task_type = "None"
+            max_memory_restart = "512M"
+            max_cpu_restart = 80.0
+            max_cpu_seconds = 30
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_watchdog_toml() {
+        let task = tasks().task[0].clone();
+        assert_eq!(task.max_memory_restart, Some(512 * 1024 * 1024));
+        assert_eq!(task.max_cpu_restart, Some(80.0));
+        assert_eq!(task.max_cpu_seconds, Some(30));
+        assert_eq!(task.restart_reason, None);
+    }
+
+    #[test]
+    fn test_watchdog_memory() {
+        let task = tasks().task[0].clone();
+        let now = Instant::now();
+        let mut since = None;
+        let usage = Usage {
+            memory: 256 * 1024 * 1024,
+            cpu: 0.0,
+        };
+        assert_eq!(exceeded(&task, usage, &mut since, now), None);
+        let usage = Usage {
+            memory: 600 * 1024 * 1024,
+            cpu: 0.0,
+        };
+        assert_eq!(
+            exceeded(&task, usage, &mut since, now),
+            Some("memory 600.0M exceeds 512.0M".to_string())
+        );
+    }
+
+    #[test]
+    fn test_watchdog_cpu() {
+        let task = tasks().task[0].clone();
+        let start = Instant::now();
+        let mut since = None;
+        let high = Usage {
+            memory: 0,
+            cpu: 95.0,
+        };
+        assert_eq!(exceeded(&task, high, &mut since, start), None);
+        assert_eq!(since, Some(start));
+        assert_eq!(
+            exceeded(&task, high, &mut since, start + Duration::from_secs(10)),
+            None
+        );
+
+        // 使用率回落后重新计时
+        let low = Usage {
+            memory: 0,
+            cpu: 10.0,
+        };
+        assert_eq!(
+            exceeded(&task, low, &mut since, start + Duration::from_secs(20)),
+            None
+        );
+        assert_eq!(since, None);
+        assert_eq!(
+            exceeded(&task, high, &mut since, start + Duration::from_secs(40)),
+            None
+        );
+        assert_eq!(
+            exceeded(&task, high, &mut since, start + Duration::from_secs(70)),
+            Some("cpu above 80% for 30s".to_string())
+        );
+    }
+
+    #[test]
+    fn test_descendants_in() {
+        // 一次遍历 /proc 得到的父子关系可以查询多个进程树
+        let children = HashMap::from([(1, vec![2, 3]), (2, vec![4]), (5, vec![6])]);
+        let mut tree = process::descendants_in(&children, 1);
+        tree.sort();
+        assert_eq!(tree, vec![2, 3, 4]);
+        assert_eq!(process::descendants_in(&children, 5), vec![6]);
+        assert!(process::descendants_in(&children, 4).is_empty());
+
+        let children = process::children();
+        assert!(children
+            .values()
+            .flatten()
+            .any(|pid| *pid == std::process::id()));
+    }
+}